@@ -34,6 +34,9 @@ fn main() {
     println!("cargo:rustc-link-lib=evdev");
     println!("cargo:rustc-link-lib=udev");
     println!("cargo:rustc-link-lib=X11");
+    if env::var_os("CARGO_FEATURE_XTEST").is_some() {
+        println!("cargo:rustc-link-lib=Xtst");
+    }
 
     // Build the native library bindings.
     let bindings = bindgen::Builder::default()
@@ -0,0 +1,61 @@
+//! Evreplay replays a recording of `evsniff --record` (or evemu-record) with a uinput device that
+//! mirrors the recorded device.
+use std::{
+    error::Error,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{value_t, App, Arg};
+use keyremapper::evdev::{event_log::EventLog, uinput::Uinput, InputEvent};
+
+const NAME: &str = "EvReplay";
+
+/// Entry point.
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let matches = App::new(NAME)
+        .arg(Arg::with_name("file").value_name("FILE").required(true).help(r#"Recording to replay"#))
+        .arg(
+            Arg::with_name("start_delay")
+                .long("start-delay-ms")
+                .default_value("1000")
+                .help(r#"Delay after creating the device, so the other programs have time to open it"#)
+                .takes_value(true),
+        )
+        .get_matches();
+    let start_delay = value_t!(matches.value_of("start_delay"), u64).unwrap_or_else(|e| e.exit());
+
+    let log = EventLog::load(Path::new(matches.value_of("file").unwrap()))?;
+    println!("Replaying {} event(s) of {}", log.records.len(), log.name);
+
+    let uinput = Uinput::with_identity(&log.name, &log.events, &log.identity)?;
+    thread::sleep(Duration::from_millis(start_delay));
+
+    // Send the events frame by frame, at the recorded time of each frame.
+    let start = Instant::now();
+    let mut frame: Vec<InputEvent> = vec![];
+    for ev in &log.records {
+        if frame.is_empty() {
+            let time = start + Duration::from_micros((ev.time_sec * 1_000_000 + ev.time_usec).max(0) as u64);
+            let now = Instant::now();
+            if time > now {
+                thread::sleep(time - now);
+            }
+        }
+        frame.push(*ev);
+        if ev.is_syn_report() {
+            uinput.send_events(&frame)?;
+            frame.clear();
+        }
+    }
+    if !frame.is_empty() {
+        uinput.send_events(&frame)?;
+    }
+
+    // Give the other programs time to read the last events before the device goes away.
+    thread::sleep(Duration::from_millis(500));
+    return Ok(());
+}
@@ -3,13 +3,23 @@ use clap::Arg;
 use libc;
 use std::{
     cell::RefCell,
+    collections::HashMap,
     error::Error,
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
     str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use keyremapper::KeyRemapperConfiguration;
+use keyremapper::{
+    evdev::{
+        event_log::{EventLog, EventLogWriter},
+        EvdevDevice, InputEvent,
+    },
+    KeyRemapperConfiguration,
+};
 use parking_lot::Mutex;
 
 const NAME: &str = "EvSniff";
@@ -128,12 +138,16 @@ impl ColorMode {
     }
 }
 
-#[derive(Debug)]
 struct State {
     color_mode: ColorMode,
     last_was_syn_report: bool,
     last_event_time: Option<Instant>,
     last_device_id: String,
+
+    /// Set with --record. The first device that sends events is recorded to this file, and the others
+    /// to "FILE.2", "FILE.3", etc.
+    record_path: Option<PathBuf>,
+    recorders: HashMap<String, EventLogWriter<BufWriter<File>>>,
 }
 
 impl Default for State {
@@ -143,10 +157,50 @@ impl Default for State {
             last_was_syn_report: false,
             last_event_time: None,
             last_device_id: String::new(),
+            record_path: None,
+            recorders: HashMap::new(),
         };
     }
 }
 
+impl State {
+    fn record(&mut self, device: &EvdevDevice, ev: &InputEvent) {
+        let record_path = match &self.record_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        if !self.recorders.contains_key(&device.id()) {
+            let mut path = record_path.into_os_string();
+            if !self.recorders.is_empty() {
+                path.push(format!(".{}", self.recorders.len() + 1));
+            }
+            let path = PathBuf::from(path);
+            let writer = File::create(&path).and_then(|file| EventLogWriter::new(BufWriter::new(file), &EventLog::from_device(device)));
+            match writer {
+                Ok(writer) => {
+                    println!("# Recording {} to {:?}", device.name(), path);
+                    self.recorders.insert(device.id(), writer);
+                }
+                Err(e) => {
+                    eprintln!("Unable to record to {:?}: {}", path, e);
+                    self.record_path = None;
+                    return;
+                }
+            }
+        }
+
+        let writer = self.recorders.get_mut(&device.id()).unwrap();
+        let mut result = writer.write_event(ev);
+        if ev.is_syn_report() {
+            // Flush each frame, so the file is complete when evsniff is killed.
+            result = result.and_then(|_| writer.flush());
+        }
+        if let Err(e) = result {
+            eprintln!("Unable to record {}: {}", device.name(), e);
+        }
+    }
+}
+
 lazy_static::lazy_static! {
     static ref STATE: Arc<Mutex<RefCell<State>>> = Arc::new(Mutex::new(RefCell::new(State::default())));
 }
@@ -164,14 +218,22 @@ fn main() -> Result<(), Box<dyn Error>> {
         .set_use_system_tray(false);
 
     config.on_init_args(|app| {
-        return app.arg(
-            Arg::with_name("color_mode")
-                .long("colors")
-                .default_value(ColorMode::Auto.to_name())
-                .help(r#"Use colored output"#)
-                .possible_values(&[ColorMode::Always.to_name(), ColorMode::Never.to_name(), ColorMode::Auto.to_name()])
-                .takes_value(true),
-        );
+        return app
+            .arg(
+                Arg::with_name("color_mode")
+                    .long("colors")
+                    .default_value(ColorMode::Auto.to_name())
+                    .help(r#"Use colored output"#)
+                    .possible_values(&[ColorMode::Always.to_name(), ColorMode::Never.to_name(), ColorMode::Auto.to_name()])
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("record")
+                    .long("record")
+                    .value_name("FILE")
+                    .help(r#"Record the events in the evemu format, which evreplay and evemu-play can replay. Use --match-device-name to select the device"#)
+                    .takes_value(true),
+            );
     });
 
     // Parse arguments.
@@ -180,6 +242,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         let color_mode_str = m.value_of("color_mode").unwrap();
         let c: ColorMode = ColorMode::from_str(color_mode_str).unwrap();
         options.borrow_mut().color_mode = c.resolve();
+        options.borrow_mut().record_path = m.value_of("record").map(PathBuf::from);
     });
 
     config.on_devices_detected(|_km, devices| {
@@ -226,6 +289,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             _ => c.other_event(),
         };
         println!("{}{}{}", line_color, ev, c.reset());
+        state.record(device, ev);
 
         state.last_event_time = Some(Instant::now());
         state.last_was_syn_report = ev.is_syn_report();
@@ -11,8 +11,10 @@ use std::{
 
 use clap::{value_t, Arg};
 use keyremapper::{
-    evdev::{self, ec},
+    evdev::{self, ec, uinput::Uinput},
+    layer::Layer,
     res::Resources,
+    scroll::InertialScroller,
     KeyRemapper, KeyRemapperConfiguration,
 };
 
@@ -26,11 +28,15 @@ const NAME: &str = "Keyboard remapper";
 const DEVICE_RE: &str = r#"^(AT Translated Set 2 keyboard|Topre Corporation Realforce|P. I. Engineering XK-16 HID)"#;
 const ID_RE: &str = "^";
 
-// Simulated mouse wheel speed.
+// Simulated mouse wheel speed. The intervals between detents when scrolling starts and at the full speed, and the time
+// it takes to reach the full speed.
 const NORMAL_SCROLL_INTERNAL: &str = "30";
 const FAST_SCROLL_INTERVAL: &str = "5";
 const FIRST_SCROLL_DELAY: &str = "100";
 
+// How often the simulated mouse wheel events are sent.
+const SCROLL_TICK_INTERVAL: Duration = Duration::from_millis(8);
+
 // ESC + These keys will generate SHIFT+ALT+CTRL+META+[THE KEY]. I launch apps using them -- e.g. ESC+ENTER to launch
 // Chrome.
 static VERSATILE_KEYS: &[i32] = &[
@@ -116,35 +122,64 @@ const ARG_NORMAL_SCROLL_INTERVAL: &str = "normal_scroll_internal";
 const ARG_FAST_SCROLL_INTERVAL: &str = "fast_scroll_interval";
 const ARG_FIRST_SCROLL_DELAY: &str = "first_scroll_delay";
 
-/// Worker thread that sends mouse wheel events repeatedly.
-mod wheeler;
-
 /// `State` stores the internal state.
 #[derive(Debug, Default)]
 struct State {
     pending_esc_pressed: bool,
-    wheeler: Option<wheeler::Wheeler>,
+    scroller: InertialScroller,
+
+    /// Receives the wheel events of `scroller`. Created in `on_start`.
+    wheel: Option<Uinput>,
+
+    /// True while `scroll_tick()` is scheduled.
+    scroll_ticking: bool,
 
     normal_scroll_internal: Duration,
     fast_scroll_interval: Duration,
     first_scroll_delay: Duration,
+}
+
+/// In the ALT mode, the layer of the same name is active, which shows its icon and notification.
+const ALT_MODE_LAYER: &str = "alt";
 
-    alt_mode: bool,
+fn is_alt_mode(km: &KeyRemapper) -> bool {
+    return km.is_layer_active(ALT_MODE_LAYER);
 }
 
-impl State {
-    fn set_alt_mode(&mut self, km: &KeyRemapper, enable: bool) {
-        if self.alt_mode == enable {
-            return;
-        }
-        self.alt_mode = enable;
-        if enable {
-            km.set_icon(&ICONS.alt_mode);
-            km.show_notification_with_timeout("ALT mode", Duration::from_secs(60 * 60 * 24));
-        } else {
-            km.set_icon(&ICONS.main);
-            km.show_notification_with_timeout("Leaving ALT mode", Duration::from_millis(100));
-        }
+fn set_alt_mode(km: &KeyRemapper, enable: bool) {
+    if is_alt_mode(km) == enable {
+        return;
+    }
+    if enable {
+        km.push_layer(ALT_MODE_LAYER);
+        km.set_led(ec::LED_SCROLLL, true);
+    } else {
+        km.remove_layer(ALT_MODE_LAYER);
+        km.clear_led(ec::LED_SCROLLL);
+        km.show_notification_with_timeout("Leaving ALT mode", Duration::from_millis(100));
+    }
+}
+
+/// Tick the scroller every `SCROLL_TICK_INTERVAL` once it starts, until it stops with the momentum.
+fn start_scroll_ticks(km: &KeyRemapper, state: &mut State) {
+    if state.scroll_ticking || !state.scroller.is_active() {
+        return;
+    }
+    state.scroll_ticking = true;
+    km.schedule_after(SCROLL_TICK_INTERVAL, scroll_tick);
+}
+
+fn scroll_tick(km: &KeyRemapper) {
+    let lock = STATE.lock();
+    let mut state = lock.borrow_mut();
+
+    let events = state.scroller.tick(Instant::now());
+    if let (Some(wheel), false) = (state.wheel.as_ref(), events.is_empty()) {
+        wheel.send_events(&events).unwrap();
+    }
+    state.scroll_ticking = state.scroller.is_active();
+    if state.scroll_ticking {
+        km.schedule_after(SCROLL_TICK_INTERVAL, scroll_tick);
     }
 }
 
@@ -191,6 +226,13 @@ fn main() -> Result<(), Box<dyn Error>> {
         .set_grab(true)
         .set_write_to_uinput(true);
 
+    // The ALT mode layer doesn't handle any events by itself; `on_event` checks whether it's active.
+    config.add_layer(
+        Layer::new(ALT_MODE_LAYER)
+            .set_icon(&ICONS.alt_mode)
+            .set_notification("ALT mode", Duration::from_secs(60 * 60 * 24)),
+    );
+
     // Set up arguments.
     config.on_init_args(|app| {
         return app
@@ -199,7 +241,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .long("normal-scroll-interval-ms")
                     .value_name("MILLIS")
                     .default_value(NORMAL_SCROLL_INTERNAL)
-                    .help(r#"Simulated mouse wheel event interval for scrolling"#)
+                    .help(r#"Interval between simulated mouse wheel detents when scrolling starts"#)
                     .takes_value(true),
             )
             .arg(
@@ -207,7 +249,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .long("fast-scroll-interval-ms")
                     .value_name("MILLIS")
                     .default_value(FAST_SCROLL_INTERVAL)
-                    .help(r#"Simulated mouse wheel event interval for fast scrolling"#)
+                    .help(r#"Interval between simulated mouse wheel detents at the full speed"#)
                     .takes_value(true),
             )
             .arg(
@@ -215,7 +257,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .long("fast-scroll-delay-ms")
                     .value_name("MILLIS")
                     .default_value(FIRST_SCROLL_DELAY)
-                    .help(r#"Delay before fast mouse wheel events kick in"#)
+                    .help(r#"Time it takes for the simulated mouse wheel to reach the full speed"#)
                     .takes_value(true),
             );
     });
@@ -233,29 +275,31 @@ fn main() -> Result<(), Box<dyn Error>> {
         state.normal_scroll_internal = get_arg(ARG_NORMAL_SCROLL_INTERVAL);
         state.fast_scroll_interval = get_arg(ARG_FAST_SCROLL_INTERVAL);
         state.first_scroll_delay = get_arg(ARG_FIRST_SCROLL_DELAY);
-    });
 
-    config.on_start(|km| {
-        let lock = STATE.lock();
-        let mut state = lock.borrow_mut();
-
-        let wheeler = wheeler::Wheeler::new(
-            km.create_mouse_uinput("-wheel"),
-            state.normal_scroll_internal,
-            state.fast_scroll_interval,
+        let detents_per_sec = |interval: Duration| 1.0 / interval.as_secs_f64();
+        let (initial_speed, max_speed, acceleration_time) = (
+            detents_per_sec(state.normal_scroll_internal),
+            detents_per_sec(state.fast_scroll_interval),
             state.first_scroll_delay,
         );
-        wheeler.start();
-        state.wheeler = Some(wheeler);
+        state
+            .scroller
+            .set_initial_speed(initial_speed)
+            .set_max_speed(max_speed)
+            .set_acceleration_time(acceleration_time);
+    });
+
+    config.on_start(|km| {
+        STATE.lock().borrow_mut().wheel = Some(km.create_mouse_uinput("-wheel"));
 
-        log::debug!("{:#?}", state);
+        log::debug!("{:#?}", STATE.lock().borrow());
     });
 
     config.on_devices_lost(|_km| {
         let lock = STATE.lock();
         let mut state = lock.borrow_mut();
 
-        state.wheeler.as_mut().unwrap().reset();
+        state.scroller.stop();
     });
 
     config.on_event(|km, device, ev| {
@@ -296,17 +340,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         if ev.is_key_down(ec::KEY_ESC, "") {
             // ESC down will always stop the mouse wheel.
-            state.wheeler.as_mut().unwrap().set_vwheel(0);
-            state.wheeler.as_mut().unwrap().set_hwheel(0);
+            state.scroller.stop();
         }
 
         // Special handling for ESC: Don't send "ESC-press" on key-down, but instead send it on key-*up*, unless
         // any keys are pressed between the down and up.
         // This allows to make "ESC + BACKSPACE" act as a DEL press without sending ESC.
-        if !state.alt_mode && ev.code == ec::KEY_ESC {
+        if !is_alt_mode(km) && ev.code == ec::KEY_ESC {
             // Ctrl + ESC or ESC+F1 -> Enter ALT mode
             if ev.is_key_down(ec::KEY_ESC, "ce") {
-                state.set_alt_mode(km, true);
+                set_alt_mode(km, true);
             }
 
             if ev.is_key_down_event() {
@@ -331,33 +374,35 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
 
         // ESC or ENTER will finish the ALT mode.
-        if state.alt_mode && ev.is_any_key_down(&[ec::KEY_ENTER, ec::KEY_ESC], "*") {
-            state.set_alt_mode(km, false);
+        if is_alt_mode(km) && ev.is_any_key_down(&[ec::KEY_ENTER, ec::KEY_ESC], "*") {
+            set_alt_mode(km, false);
             return;
         }
 
         match 0 {
             // ESC + F1 also enables alt mode.
-            _ if ev.is_key_down(ec::KEY_F1, "e") => state.set_alt_mode(km, true),
+            _ if ev.is_key_down(ec::KEY_F1, "e") => set_alt_mode(km, true),
 
             // ESC + H / J / K / L -> emulate wheel. Also support ESC+SPACE / C for left-hand-only scrolling.
-            _ if ev.is_any_key(&[ec::KEY_J, ec::KEY_K, ec::KEY_SPACE, ec::KEY_C], "*") && (state.alt_mode || km.is_esc_on()) => {
+            _ if ev.is_any_key(&[ec::KEY_J, ec::KEY_K, ec::KEY_SPACE, ec::KEY_C], "*") && (is_alt_mode(km) || km.is_esc_on()) => {
                 let speed = match 0 {
                     _ if ev.is_key_up_event() => 0,
                     _ if ev.is_any_key_down(&[ec::KEY_K, ec::KEY_C], "*") => 1,
                     _ if ev.is_any_key_down(&[ec::KEY_J, ec::KEY_SPACE], "*") => -1,
                     _ => return,
                 };
-                state.wheeler.as_mut().unwrap().set_vwheel(speed);
+                state.scroller.set_vertical(speed, Instant::now());
+                start_scroll_ticks(km, &mut state);
             }
-            _ if ev.is_any_key(&[ec::KEY_L, ec::KEY_H], "*") && (state.alt_mode || km.is_esc_on()) => {
+            _ if ev.is_any_key(&[ec::KEY_L, ec::KEY_H], "*") && (is_alt_mode(km) || km.is_esc_on()) => {
                 let speed = match 0 {
                     _ if ev.is_key_up_event() => 0,
                     _ if ev.is_any_key_down(&[ec::KEY_L], "*") => 1,
                     _ if ev.is_any_key_down(&[ec::KEY_H], "*") => -1,
                     _ => return,
                 };
-                state.wheeler.as_mut().unwrap().set_hwheel(speed);
+                state.scroller.set_horizontal(speed, Instant::now());
+                start_scroll_ticks(km, &mut state);
             }
 
             // Chrome only -- F5 / F6 as BACK / FORWARD
@@ -376,8 +421,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             _ if ev.is_key_on(ec::KEY_END, "e") => km.press_key(ec::KEY_RIGHT, "a"),
 
             // ESC + left / right -> vol down / up
-            _ if ev.is_key_on(ec::KEY_LEFT, "e") => km.press_key(ec::KEY_VOLUMEDOWN, ""),
-            _ if ev.is_key_on(ec::KEY_RIGHT, "e") => km.press_key(ec::KEY_VOLUMEUP, ""),
+            _ if ev.is_key_down(ec::KEY_LEFT, "e") => km.hold_key(ev, ec::KEY_VOLUMEDOWN, ""),
+            _ if ev.is_key_down(ec::KEY_RIGHT, "e") => km.hold_key(ev, ec::KEY_VOLUMEUP, ""),
 
             // ESC + Pageup -> ctrl + pageup (prev tab)
             // ESC + Pagedown -> ctrl + pagedown (next tab)
@@ -394,7 +439,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             _ if ev.code == ec::KEY_CAPSLOCK => {}
 
             // In alt-mode, don't use printable char keys as-is.
-            _ if state.alt_mode && ev.is_any_key_on(PRINTABLE_KEYS, "") => {}
+            _ if is_alt_mode(km) && ev.is_any_key_on(PRINTABLE_KEYS, "") => {}
 
             // Default: Just send the original key event.
             _ => km.send_event(&ev),
@@ -0,0 +1,86 @@
+//! Keyremapper-ctl sends a command to a running remapper through its control socket, e.g. from
+//! window manager key bindings or scripts. See `keyremapper::control` for the commands.
+//!
+//! e.g. `keyremapper-ctl mode nav`, `keyremapper-ctl --name "Keyboard Remapper" press TAB as`
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+
+use clap::{App, AppSettings, Arg};
+use keyremapper::control;
+
+const NAME: &str = "keyremapper-ctl";
+
+/// Name of the remapper, from its socket path.
+fn remapper_name(path: &Path) -> String {
+    return path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+}
+
+/// Return the socket of the remapper named `name`, or the only running remapper without `name`.
+fn find_socket(name: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(name) = name {
+        return Ok(control::socket_path(name));
+    }
+    let sockets = control::list_sockets();
+    return match sockets.len() {
+        0 => Err(format!("No remapper is running. (No sockets in {})", control::socket_dir().display())),
+        1 => Ok(sockets[0].clone()),
+        _ => Err(format!(
+            "Multiple remappers are running; select one with --name: {}",
+            sockets.iter().map(|p| remapper_name(p)).collect::<Vec<_>>().join(", ")
+        )),
+    };
+}
+
+/// Entry point.
+fn main() {
+    env_logger::init();
+
+    let matches = App::new(NAME)
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("name")
+                .short("n")
+                .long("name")
+                .value_name("NAME")
+                .help(r#"Name of the remapper to control. Can be omitted if only one is running"#)
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("list").short("l").long("list").help(r#"List the running remappers"#))
+        .arg(
+            Arg::with_name("command")
+                .value_name("COMMAND")
+                .multiple(true)
+                .help(r#"Command to send, e.g. "status", "mode LAYER", "press KEY MODIFIERS" or "reset". Use "help" to list them all"#),
+        )
+        .get_matches();
+
+    if matches.is_present("list") {
+        for path in control::list_sockets() {
+            println!("{}", remapper_name(&path));
+        }
+        return;
+    }
+
+    let command = match matches.values_of("command") {
+        Some(words) => words.collect::<Vec<_>>().join(" "),
+        None => "status".to_string(),
+    };
+
+    let path = find_socket(matches.value_of("name")).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    match control::send_command(&path, &command) {
+        Ok(fields) => {
+            for field in fields {
+                println!("{}", field);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}: {:#}", remapper_name(&path), e);
+            process::exit(1);
+        }
+    }
+}
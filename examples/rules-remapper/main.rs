@@ -0,0 +1,42 @@
+//! Generic remapper driven by a rule file. See `keyremapper::rules` for the file format.
+use std::{error::Error, process};
+
+use clap::Arg;
+use keyremapper::rules::RuleFile;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    // The configuration is built from the rule file, so we need it before `keyremapper::start()`
+    // parses the rest of the arguments.
+    let path = match std::env::args().nth(1) {
+        Some(path) if !path.starts_with('-') => path,
+        _ => {
+            eprintln!("Usage: rules-remapper RULE_FILE [OPTIONS]");
+            process::exit(1);
+        }
+    };
+    let rule_file = match RuleFile::load(&path) {
+        Ok(rule_file) => rule_file,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            process::exit(1);
+        }
+    };
+    log::debug!("{:#?}", rule_file);
+
+    let mut config = rule_file.to_config();
+    config.on_init_args(|app| {
+        return app.arg(
+            Arg::with_name("rule_file")
+                .value_name("RULE_FILE")
+                .help(r#"Rule file to load"#)
+                .required(true)
+                .index(1),
+        );
+    });
+
+    keyremapper::start(config);
+
+    return Ok(());
+}
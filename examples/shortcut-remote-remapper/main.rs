@@ -1,24 +1,27 @@
 //! Keyboard remapper for https://smile.amazon.com/gp/product/B01NC2LEYP
 extern crate lazy_static;
 
-use std::{cell::RefCell, error::Error, path::PathBuf, process, sync::Arc, time::Duration};
+use std::{
+    error::Error,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use clap::{value_t, Arg};
 use keyremapper::{
     evdev::{self, ec},
+    layer::Layer,
     res::Resources,
     KeyRemapper, KeyRemapperConfiguration,
 };
-use parking_lot::ReentrantMutex;
 
 const NAME: &str = "Shortcut-Remote-Remapper";
 const DEVICE_RE: &str = r#"^UGEE TABLET TABLET KT01$"#;
 const ID_RE: &str = "^";
 
 lazy_static::lazy_static! {
-    static ref REMAPPER: Arc<ReentrantMutex<RefCell<Remapper>>> =
-        Arc::new(ReentrantMutex::new(RefCell::new(Remapper::new())));
-
     static ref ICONS: [PathBuf;3] = {
         let mut res = Resources::from_bytes(NAME, include_bytes!("icons.bin"));
         return [
@@ -56,9 +59,12 @@ fn find_key_index(key: i32) -> Option<usize> {
 }
 
 const HALF_TOGGLE: i32 = 0x1000;
-const MODE1: (i32, &str) = (-1, "#Cursor mode");
-const MODE2: (i32, &str) = (-2, "#Volume mode");
-const MODE3: (i32, &str) = (-3, "#Scroll mode");
+
+// The mode keys are unmapped in all the modes, so they fall through to the base layer, which switches the mode.
+const MODE_KEY_INDEX: usize = 6;
+const MODE1: (i32, &str) = (0, "#Cursor mode");
+const MODE2: (i32, &str) = (0, "#Volume mode");
+const MODE3: (i32, &str) = (0, "#Scroll mode");
 
 static CURSOR_MODE: &'static [(i32, &str)] = &[
     (ec::KEY_F, "F"),
@@ -107,82 +113,77 @@ static SCROLL_MODE: &'static [(i32, &str)] = &[
 
 static ALL_MODES: &'static [&[(i32, &str)]] = &[CURSOR_MODE, VOLUME_MODE, SCROLL_MODE];
 
-struct Remapper {
-    mode: usize,
-}
+/// Layer names of the modes.
+static MODE_NAMES: &'static [&str] = &["cursor", "volume", "scroll"];
 
-impl Remapper {
-    fn new() -> Remapper {
-        Remapper { mode: 0 }
-    }
+static INITIAL_MODE: AtomicUsize = AtomicUsize::new(0);
 
-    fn notify_mode(&self, km: &KeyRemapper) {
-        let mode = ALL_MODES[self.mode];
-        let mut body = "".to_string();
-        for (i, (_, desc)) in mode.iter().enumerate() {
-            if i > 0 {
-                body.push_str(&"\n");
-            }
-            body.push_str(KEY_LABELS[i]);
-            body.push_str(":  ");
-            body.push_str(desc)
+fn mode_description(mode: &[(i32, &str)]) -> String {
+    let mut body = "".to_string();
+    for (i, (_, desc)) in mode.iter().enumerate() {
+        if i > 0 {
+            body.push_str(&"\n");
         }
-        km.show_notification_with_timeout(&body, Duration::from_secs(5));
+        body.push_str(KEY_LABELS[i]);
+        body.push_str(":  ");
+        body.push_str(desc)
+    }
+    return body;
+}
 
-        km.set_icon(&ICONS[self.mode]);
+/// Remap an event in a mode. Returns false for unmapped keys, so they fall through to the base layer.
+fn remap(km: &KeyRemapper, ev: &evdev::InputEvent, mode: &[(i32, &str)]) -> bool {
+    // Ignore non-key events.
+    if ev.event_type != ec::EventType::EV_KEY {
+        return false;
     }
 
-    fn on_start(&self, km: &KeyRemapper) {
-        self.notify_mode(km);
+    // Find the key index.
+    let key_index = match find_key_index(ev.code) {
+        Some(i) => i,
+        None => return false,
+    };
+
+    // Find the "to" key.
+    let (to_key, ..) = mode[key_index];
+    if to_key == 0 {
+        return false;
     }
 
-    fn remap(&mut self, km: &KeyRemapper, _device: &evdev::EvdevDevice, ev: &evdev::InputEvent) {
-        // Ignore non-key events.
-        if ev.event_type != ec::EventType::EV_KEY {
-            return;
-        }
+    // Convert to a key event.
+    let half_toggle = (to_key & HALF_TOGGLE) != 0;
+    let to_key = to_key & !HALF_TOGGLE;
 
-        // Ignore CTRL events, which the "6" key would send along with "z".
-        if ev.code == ec::KEY_LEFTCTRL {
-            return;
+    if half_toggle {
+        if !(ev.value == 0 || ev.value == 1) {
+            return true;
         }
-
-        // Find the key index.
-        let key_index = match find_key_index(ev.code) {
-            Some(i) => i,
-            None => {
-                eprintln!("Unknown key detected: {}", ev);
-                return;
-            }
-        };
-
-        // Find the "to" key.
-        let mode = ALL_MODES[self.mode];
-        let (to_key, ..) = mode[key_index];
-
-        // Change mode?
-        if to_key < 0 {
-            let new_mode = (-to_key as usize) - 1;
-            log::info!("Chaning mode to {}", new_mode);
-            self.mode = new_mode;
-            self.notify_mode(km);
-            return;
+    } else {
+        if ev.value == 0 {
+            return true;
         }
+    }
+    km.press_key(to_key, "");
+    return true;
+}
 
-        // Otherwise, convert to a key event.
-        let half_toggle = (to_key & HALF_TOGGLE) != 0;
-        let to_key = to_key & !HALF_TOGGLE;
+/// The base layer, which handles the mode keys.
+fn on_base_event(km: &KeyRemapper, ev: &evdev::InputEvent) {
+    // Ignore non-key events, and CTRL events, which the "6" key would send along with "z".
+    if ev.event_type != ec::EventType::EV_KEY || ev.code == ec::KEY_LEFTCTRL {
+        return;
+    }
 
-        if half_toggle {
-            if !(ev.value == 0 || ev.value == 1) {
-                return;
-            }
-        } else {
-            if ev.value == 0 {
-                return;
+    match find_key_index(ev.code) {
+        Some(i) if (MODE_KEY_INDEX..MODE_KEY_INDEX + ALL_MODES.len()).contains(&i) => {
+            if ev.is_key_down_event() {
+                let new_mode = i - MODE_KEY_INDEX;
+                log::info!("Chaning mode to {}", new_mode);
+                km.switch_layer(MODE_NAMES[new_mode]);
             }
         }
-        km.press_key(to_key, "");
+        Some(_) => {}
+        None => eprintln!("Unknown key detected: {}", ev),
     }
 }
 
@@ -198,6 +199,17 @@ fn main() -> Result<(), Box<dyn Error>> {
         .set_use_non_keyboard(true)
         .set_write_to_uinput(true);
 
+    // Each mode is a layer.
+    for (i, mode) in ALL_MODES.iter().enumerate() {
+        let mode: &'static [(i32, &str)] = mode;
+        config.add_layer(
+            Layer::new(MODE_NAMES[i])
+                .set_icon(&ICONS[i])
+                .set_notification(&mode_description(mode), Duration::from_secs(5))
+                .on_event(move |km, _device, ev| remap(km, ev, mode)),
+        );
+    }
+
     config.on_init_args(|app| {
         return app.arg(
             Arg::with_name("initial_mode")
@@ -211,17 +223,17 @@ fn main() -> Result<(), Box<dyn Error>> {
     });
     config.on_args_parsed(|matches| {
         let mode = value_t!(matches.value_of("initial_mode"), usize).unwrap_or_else(|e| e.exit());
-        if mode > ALL_MODES.len() {
+        if mode >= ALL_MODES.len() {
             eprintln!("Initial mode must be between 0..{}", ALL_MODES.len());
             process::exit(1);
         }
-        REMAPPER.lock().borrow_mut().mode = mode;
+        INITIAL_MODE.store(mode, Ordering::SeqCst);
         log::debug!("Initial mode={}", mode);
     });
 
     config.on_start(|km| {
         log::debug!("{}.on_start", NAME);
-        REMAPPER.lock().borrow().on_start(km);
+        km.switch_layer(MODE_NAMES[INITIAL_MODE.load(Ordering::SeqCst)]);
     });
     config.on_stop(|_| {
         log::debug!("{}.on_stop", NAME);
@@ -238,8 +250,8 @@ fn main() -> Result<(), Box<dyn Error>> {
         log::debug!("{}.on_init_args", NAME);
     });
 
-    config.on_event(|km, device, event| {
-        REMAPPER.lock().borrow_mut().remap(km, device, event);
+    config.on_event(|km, _device, event| {
+        on_base_event(km, event);
     });
 
     keyremapper::start(config);
@@ -1,12 +1,14 @@
 //! Speed up trackpoint.
 extern crate lazy_static;
 
-use std::{cell::RefCell, error::Error, sync::Arc};
+use std::{cell::RefCell, error::Error, sync::Arc, time::Instant};
 
 use clap::{value_t, App, Arg};
 use keyremapper::{
-    evdev::{self, ec, EventsDescriptor, InputEvent},
+    evdev::{self, ec, EventsDescriptor},
+    pointer::{AccelProfile, PointerAccelerator, PointerTransform},
     res::Resources,
+    scroll::MiddleButtonScroll,
     KeyRemapper, KeyRemapperConfiguration,
 };
 use parking_lot::Mutex;
@@ -15,16 +17,9 @@ const NAME: &str = "Trackpoint Spped-up";
 const DEVICE_RE: &str = r#"^TPPS/2 Elan TrackPoint"#;
 const ID_RE: &str = "^";
 
-#[derive(Default, Debug, Copy, Clone)]
-struct Settings {
-    threshold: f64,
-    add: f64,
-    power: f64,
-    scale: f64,
-}
-
 lazy_static::lazy_static! {
-    static ref SETTINGS: Arc<Mutex<RefCell<Settings>>> = Arc::new(Mutex::new(RefCell::new(Settings::default())));
+    static ref ACCELERATOR: Arc<Mutex<RefCell<PointerAccelerator>>> = Arc::new(Mutex::new(RefCell::new(PointerAccelerator::new(&PointerTransform::default()))));
+    static ref MIDDLE_SCROLL: Arc<Mutex<RefCell<MiddleButtonScroll>>> = Arc::new(Mutex::new(RefCell::new(MiddleButtonScroll::new())));
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -34,7 +29,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     supported_events
         .events
         .insert(ec::EventType::EV_KEY, vec![ec::BTN_LEFT, ec::BTN_RIGHT, ec::BTN_MIDDLE]);
-    supported_events.events.insert(ec::EventType::EV_REL, vec![ec::REL_X, ec::REL_Y]);
+    supported_events.events.insert(
+        ec::EventType::EV_REL,
+        vec![ec::REL_X, ec::REL_Y, ec::REL_WHEEL, ec::REL_HWHEEL, ec::REL_WHEEL_HI_RES, ec::REL_HWHEEL_HI_RES],
+    );
 
     // Set up the config.
     let mut config = KeyRemapperConfiguration::new(NAME, DEVICE_RE);
@@ -55,49 +53,30 @@ fn main() -> Result<(), Box<dyn Error>> {
         return ret;
     });
     config.on_args_parsed(|matches| {
-        let locked_settings = SETTINGS.lock();
-        let mut s = locked_settings.borrow_mut();
-        s.threshold = value_t!(matches.value_of("threshold"), f64).unwrap_or_else(|e| e.exit());
-        s.add = value_t!(matches.value_of("add"), f64).unwrap_or_else(|e| e.exit());
-        s.power = value_t!(matches.value_of("power"), f64).unwrap_or_else(|e| e.exit());
-        s.scale = value_t!(matches.value_of("scale"), f64).unwrap_or_else(|e| e.exit());
-
-        log::debug!("{:#?}", s);
+        let profile = AccelProfile::Power {
+            threshold: value_t!(matches.value_of("threshold"), f64).unwrap_or_else(|e| e.exit()),
+            add: value_t!(matches.value_of("add"), f64).unwrap_or_else(|e| e.exit()),
+            power: value_t!(matches.value_of("power"), f64).unwrap_or_else(|e| e.exit()),
+            scale: value_t!(matches.value_of("scale"), f64).unwrap_or_else(|e| e.exit()),
+        };
+        log::debug!("{:#?}", profile);
+
+        ACCELERATOR.lock().borrow_mut().set_transform(&PointerTransform::new(profile));
     });
 
     config.on_event(|km, _device, ev| {
-        let locked_settings = SETTINGS.lock();
-        let s = locked_settings.borrow();
-
-        km.send_event(&speed_up(&s, ev));
+        // Scroll with the middle button first, so the scroll movements aren't accelerated.
+        let events = MIDDLE_SCROLL.lock().borrow_mut().on_event(ev, Instant::now());
+        for ev in &events {
+            // The movements are accelerated as a vector once per frame, so send the whole frame at once.
+            let frame = ACCELERATOR.lock().borrow_mut().on_event(ev);
+            if !frame.is_empty() {
+                km.send_events(&frame);
+            }
+        }
     });
 
     keyremapper::start(config);
 
     return Ok(());
 }
-
-fn speed_up(s: &Settings, ev: &InputEvent) -> InputEvent {
-    let mut new_ev = ev.clone();
-
-    if ev.event_type == ec::EventType::EV_REL {
-        let mut value = (ev.value as f64).abs() - s.threshold;
-        if value < 1_f64 {
-            value = ev.value as f64;
-        } else {
-            value = (value + s.add) / s.scale;
-            value = ((1_f64 + value).powf(s.power) - 1_f64) * s.scale;
-            value = value + s.threshold;
-
-            if ev.value < 0 {
-                value = -value;
-            }
-        }
-
-        log::debug!("{}: {} -> {}", ev.code, ev.value, value);
-
-        new_ev.value = value as i32
-    }
-
-    return new_ev;
-}
@@ -102,6 +102,23 @@ def generate_event_codes(in_file: str, out_file: str) -> None:
 			}
 			'''))
 
+		# Key names to codes. Unlike get_code_name(), this one supports aliases too.
+		out.write(textwrap.dedent('''
+			pub fn get_key_code(name: &str) -> Option<i32> {
+				match name {
+			'''))
+		for key in event_codes.keys():
+			if re.search(r'''(_CNT|_MAX)$''', key):
+				continue
+			if not re.search(r'''^(KEY_|BTN_)''', key):
+				continue
+			out.write(f'''		"{key}" => Some({key}),\n''')
+		out.write(textwrap.dedent('''\
+					_ => None,
+				}
+			}
+			'''))
+
 
 def main(args: Iterable[str]) -> None:
 	generate_event_codes(INPUT_EVENT_CODES_H, INPUT_EVENT_CODES_H_OUT)
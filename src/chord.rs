@@ -0,0 +1,340 @@
+//! Chords, i.e. multiple keys pressed at nearly the same time, e.g. J+K -> ESC.
+//! See `KeyRemapperConfiguration::add_chord()`.
+use core::fmt::Debug;
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    evdev::InputEvent,
+    modifiers::{ModifierRegistry, ModifierSpec},
+    validate_modifiers, KeyRemapper,
+};
+
+pub(crate) type ChordCallback = Arc<dyn Fn(&KeyRemapper) + Send + Sync + 'static>;
+
+/// What to do when a chord is detected.
+#[derive(Clone)]
+pub enum ChordAction {
+    /// Press a key with the given modifiers, using `KeyRemapper::press_key()`.
+    Press { code: i32, modifiers: String },
+
+    /// Call a callback.
+    Callback(ChordCallback),
+}
+
+impl Debug for ChordAction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ChordAction::Press { code, modifiers } => write!(f, "Press{{code: {}, modifiers: {:?}}}", code, modifiers),
+            ChordAction::Callback(_) => write!(f, "Callback{{...}}"),
+        }
+    }
+}
+
+/// Configuration of a single chord.
+#[derive(Debug, Clone)]
+pub struct Chord {
+    pub(crate) keys: Vec<i32>,
+    pub(crate) action: ChordAction,
+    pub(crate) window: Duration,
+}
+
+impl Chord {
+    fn with_action(keys: &[i32], action: ChordAction) -> Chord {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        if keys.len() < 2 {
+            panic!("A chord needs at least 2 keys");
+        }
+        return Chord {
+            keys,
+            action,
+            window: Duration::from_millis(40),
+        };
+    }
+
+    /// When all the `keys` are pressed, press `code` with `modifiers` instead, using `KeyRemapper::press_key()`.
+    pub fn new(keys: &[i32], code: i32, modifiers: &str) -> Chord {
+        validate_modifiers(modifiers, "acsw<>*");
+        ModifierSpec::parse(ModifierRegistry::builtin(), modifiers);
+        return Chord::with_action(
+            keys,
+            ChordAction::Press {
+                code,
+                modifiers: modifiers.to_string(),
+            },
+        );
+    }
+
+    /// When all the `keys` are pressed, call `callback` instead.
+    pub fn with_callback<F: Fn(&KeyRemapper) + Send + Sync + 'static>(keys: &[i32], callback: F) -> Chord {
+        return Chord::with_action(keys, ChordAction::Callback(Arc::new(callback)));
+    }
+
+    /// All the keys need to be pressed within this duration from the first one. The default is 40ms.
+    pub fn set_window(&mut self, value: Duration) -> &mut Chord {
+        self.window = value;
+        self
+    }
+}
+
+/// Output of `ChordState`. `usize`s are indexes to the `Chord`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ChordOutput<P> {
+    /// Pass the event to the next stage.
+    Event(InputEvent, P),
+    Chord(usize),
+}
+
+/// State machine of the chords. Like `TapHoldState`, it's generic over the payload `P`.
+///
+/// When a key that's part of any chord is pressed, it and the following events are buffered until
+/// either a chord is complete, or it turns out no chord can complete, in which case the buffered
+/// events are replayed in the original order. Once a chord is detected, the rest of the events of
+/// its keys, i.e. auto-repeats and key ups, are swallowed.
+#[derive(Debug)]
+pub(crate) struct ChordState<P> {
+    chords: Vec<Chord>,
+
+    /// Keys pressed since the first chord key, in the order they were pressed.
+    pressed: Vec<i32>,
+    started_at: Option<Instant>,
+    buffer: Vec<(InputEvent, P)>,
+
+    /// Keys of the detected chords that haven't been released yet.
+    swallowing: HashSet<i32>,
+}
+
+impl<P> ChordState<P> {
+    pub(crate) fn new(chords: Vec<Chord>) -> ChordState<P> {
+        return ChordState {
+            chords,
+            pressed: vec![],
+            started_at: None,
+            buffer: vec![],
+            swallowing: HashSet::new(),
+        };
+    }
+
+    pub(crate) fn chord(&self, index: usize) -> &Chord {
+        return &self.chords[index];
+    }
+
+    /// Return the chords that contain all the `keys`.
+    fn candidates<'a>(&'a self, keys: &'a [i32]) -> impl Iterator<Item = usize> + 'a {
+        return (0..self.chords.len()).filter(move |i| keys.iter().all(|k| self.chords[*i].keys.contains(k)));
+    }
+
+    /// Return the chord that consists of exactly the pressed keys.
+    fn exact_match(&self) -> Option<usize> {
+        return self.candidates(&self.pressed).find(|i| self.chords[*i].keys.len() == self.pressed.len());
+    }
+
+    /// Deadline of the chord being pressed, if any. It's the longest window of the chords that
+    /// can still complete.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let started_at = self.started_at?;
+        let window = self.candidates(&self.pressed).map(|i| self.chords[i].window).max()?;
+        return Some(started_at + window);
+    }
+
+    pub(crate) fn on_event(&mut self, ev: &InputEvent, payload: P, now: Instant) -> Vec<ChordOutput<P>> {
+        let mut out = vec![];
+        self.check_timeout(now, &mut out);
+        self.process(ev, payload, now, &mut out);
+        return out;
+    }
+
+    /// Give up the chord being pressed if its window has passed.
+    pub(crate) fn on_timeout(&mut self, now: Instant) -> Vec<ChordOutput<P>> {
+        let mut out = vec![];
+        self.check_timeout(now, &mut out);
+        return out;
+    }
+
+    /// Forget all the state, e.g. when the devices are lost. The buffered events are dropped.
+    pub(crate) fn reset(&mut self) {
+        self.pressed.clear();
+        self.started_at = None;
+        self.buffer.clear();
+        self.swallowing.clear();
+    }
+
+    fn check_timeout(&mut self, now: Instant, out: &mut Vec<ChordOutput<P>>) {
+        if let Some(deadline) = self.next_deadline() {
+            if now >= deadline {
+                self.resolve(out);
+            }
+        }
+    }
+
+    /// Finish the chord being pressed, either by detecting it, or by replaying the buffered events.
+    fn resolve(&mut self, out: &mut Vec<ChordOutput<P>>) {
+        match self.exact_match() {
+            Some(index) => self.fire(index, out),
+            None => {
+                out.extend(self.buffer.drain(..).map(|(ev, payload)| ChordOutput::Event(ev, payload)));
+                self.pressed.clear();
+                self.started_at = None;
+            }
+        }
+    }
+
+    fn fire(&mut self, index: usize, out: &mut Vec<ChordOutput<P>>) {
+        // Non-key events, e.g. SYN_REPORT, still go through.
+        for (ev, payload) in self.buffer.drain(..) {
+            if !ev.is_key_event() {
+                out.push(ChordOutput::Event(ev, payload));
+            }
+        }
+        out.push(ChordOutput::Chord(index));
+        self.swallowing.extend(self.pressed.drain(..));
+        self.started_at = None;
+    }
+
+    fn process(&mut self, ev: &InputEvent, payload: P, now: Instant, out: &mut Vec<ChordOutput<P>>) {
+        if ev.is_key_event() && self.swallowing.contains(&ev.code) {
+            if !ev.is_key_down_event() {
+                if ev.is_key_up_event() {
+                    self.swallowing.remove(&ev.code);
+                }
+                return;
+            }
+            // Pressed again without being released, e.g. the key up was lost.
+            self.swallowing.remove(&ev.code);
+        }
+
+        if self.started_at.is_none() {
+            if ev.is_key_down_event() && self.candidates(&[ev.code]).next().is_some() {
+                self.started_at = Some(now);
+                self.pressed.push(ev.code);
+                self.buffer.push((*ev, payload));
+            } else {
+                out.push(ChordOutput::Event(*ev, payload));
+            }
+            return;
+        }
+
+        if !ev.is_key_event() {
+            self.buffer.push((*ev, payload));
+            return;
+        }
+
+        if ev.is_key_down_event() && !self.pressed.contains(&ev.code) {
+            let mut pressed = self.pressed.clone();
+            pressed.push(ev.code);
+            if self.candidates(&pressed).next().is_some() {
+                self.pressed = pressed;
+                self.buffer.push((*ev, payload));
+
+                // Detect it right away, unless a larger chord can still complete.
+                let len = self.pressed.len();
+                if self.candidates(&self.pressed).all(|i| self.chords[i].keys.len() == len) {
+                    self.resolve(out);
+                }
+                return;
+            }
+        }
+
+        // Any other key event, e.g. a key up, ends the chord.
+        self.resolve(out);
+        self.process(ev, payload, now, out);
+    }
+}
+
+#[cfg(test)]
+impl crate::testing::TimedKeyState for ChordState<()> {
+    type Output = ChordOutput<()>;
+
+    fn on_event(&mut self, ev: &InputEvent, now: Instant) -> Vec<ChordOutput<()>> {
+        return ChordState::on_event(self, ev, (), now);
+    }
+
+    fn on_timeout(&mut self, now: Instant) -> Vec<ChordOutput<()>> {
+        return ChordState::on_timeout(self, now);
+    }
+}
+
+#[test]
+fn test_chord() {
+    use crate::evdev::ec;
+    use crate::testing::{run_timed_key_state, TestStep::*};
+    use ChordOutput::{Chord as Detected, Event};
+
+    let start = Instant::now();
+    let key = |code: i32, value: i32| Event(InputEvent::new_key_event(code, value), ());
+    let mut state = ChordState::new(vec![Chord::new(&[ec::KEY_J, ec::KEY_K], ec::KEY_ESC, "")]);
+
+    // Detected as soon as both are pressed, and the rest is swallowed.
+    assert_eq!(
+        vec![Detected(0)],
+        run_timed_key_state(
+            &mut state,
+            start,
+            &[
+                Key(0, ec::KEY_K, 1),
+                Key(10, ec::KEY_J, 1),
+                Key(300, ec::KEY_J, 2),
+                Key(400, ec::KEY_K, 0),
+                Key(410, ec::KEY_J, 0)
+            ]
+        )
+    );
+
+    // Released before the other key -> replayed in the original order.
+    assert_eq!(
+        vec![key(ec::KEY_J, 1), key(ec::KEY_J, 0), key(ec::KEY_K, 1), key(ec::KEY_K, 0)],
+        run_timed_key_state(
+            &mut state,
+            start,
+            &[Key(0, ec::KEY_J, 1), Key(10, ec::KEY_J, 0), Key(20, ec::KEY_K, 1), Key(30, ec::KEY_K, 0)]
+        )
+    );
+
+    // Other keys end the chord.
+    assert_eq!(
+        vec![key(ec::KEY_J, 1), key(ec::KEY_A, 1)],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_J, 1), Key(10, ec::KEY_A, 1)])
+    );
+    assert_eq!(
+        vec![key(ec::KEY_A, 0), key(ec::KEY_J, 0)],
+        run_timed_key_state(&mut state, start, &[Key(20, ec::KEY_A, 0), Key(30, ec::KEY_J, 0)])
+    );
+
+    // Too slow. K may still start another chord, until the timer fires.
+    assert_eq!(
+        vec![key(ec::KEY_J, 1), key(ec::KEY_K, 1)],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_J, 1), Key(50, ec::KEY_K, 1), Timeout(90)])
+    );
+}
+
+#[test]
+fn test_chord_overlapping() {
+    use crate::evdev::ec;
+    use crate::testing::{run_timed_key_state, TestStep::*};
+    use ChordOutput::Chord as Detected;
+
+    let start = Instant::now();
+    let mut state = ChordState::new(vec![
+        Chord::new(&[ec::KEY_J, ec::KEY_K], ec::KEY_ESC, ""),
+        Chord::new(&[ec::KEY_J, ec::KEY_K, ec::KEY_L], ec::KEY_ENTER, ""),
+    ]);
+
+    // J+K waits for L until the window passes.
+    assert_eq!(
+        Vec::<ChordOutput<()>>::new(),
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_J, 1), Key(10, ec::KEY_K, 1)])
+    );
+    assert_eq!(Some(start + Duration::from_millis(40)), state.next_deadline());
+    assert_eq!(vec![Detected(0)], run_timed_key_state(&mut state, start, &[Timeout(40)]));
+    state.reset();
+
+    assert_eq!(
+        vec![Detected(1)],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_J, 1), Key(10, ec::KEY_K, 1), Key(20, ec::KEY_L, 1)])
+    );
+}
@@ -4,7 +4,16 @@ use regex::Regex;
 use std::{path::PathBuf, sync::Arc};
 
 use crate::{
-    evdev::{self, EventsDescriptor},
+    chord::Chord,
+    evdev::{self, DeviceIdentity, EventsDescriptor},
+    gamepad::GamepadMapping,
+    gesture::{GestureBinding, GestureSettings},
+    layer::Layer,
+    leader::Leader,
+    modifiers::ModifierRegistry,
+    rules::{RuleRemapper, Rules},
+    taphold::{HoldAction, TapHoldKey},
+    text::{KeyboardLayout, UnicodeInput},
     KeyRemapper, UINPUT_DEVICE_NAME_PREFIX,
 };
 
@@ -28,6 +37,8 @@ pub(crate) struct KeyRemapperCallbacks {
 
     pub(crate) on_events_batch: Arc<dyn Fn(&KeyRemapper, &evdev::EvdevDevice, &[evdev::InputEvent]) + Send + Sync + 'static>,
     pub(crate) on_event: Arc<dyn Fn(&KeyRemapper, &evdev::EvdevDevice, &evdev::InputEvent) + Send + Sync + 'static>,
+
+    pub(crate) on_out_event: Arc<dyn Fn(&str, &evdev::InputEvent) + Send + Sync + 'static>,
 }
 
 impl Debug for KeyRemapperCallbacks {
@@ -49,6 +60,7 @@ impl KeyRemapperCallbacks {
             on_stop: Arc::new(|_| {}),
             on_events_batch: Arc::new(|_, _, _| {}),
             on_event: Arc::new(|_, _, _| {}),
+            on_out_event: Arc::new(|_, _| {}),
         }
     }
 }
@@ -66,19 +78,44 @@ pub struct KeyRemapperConfiguration {
     pub(crate) id_regex_re: Option<Regex>,
 
     pub(crate) use_system_tray: bool,
+    pub(crate) use_control_socket: bool,
 
     pub(crate) use_non_keyboard: bool,
     pub(crate) grab_devices: bool,
     pub(crate) write_to_uinput: bool,
     pub(crate) uinput_events: EventsDescriptor,
+    pub(crate) uinput_identity: Option<DeviceIdentity>,
     pub(crate) global_lock_name: String,
     pub(crate) uinput_device_name_suffix: String,
 
     pub(crate) uinput_devices_prefix: String,
 
+    pub(crate) tap_hold_keys: Vec<TapHoldKey>,
+
+    pub(crate) chords: Vec<Chord>,
+
+    pub(crate) leader: Option<Leader>,
+
+    pub(crate) layers: Vec<Layer>,
+
+    pub(crate) gestures: Vec<GestureBinding>,
+    pub(crate) gesture_settings: GestureSettings,
+
+    pub(crate) gamepad: Option<GamepadMapping>,
+
+    pub(crate) keyboard_layout: KeyboardLayout,
+    pub(crate) unicode_input: UnicodeInput,
+
+    pub(crate) modifiers: ModifierRegistry,
+
     pub(crate) callbacks: Arc<RwLock<KeyRemapperCallbacks>>,
 }
 
+/// Make `name` usable in file names, e.g. for the lock file and the control socket.
+pub(crate) fn cleanse_name(name: &str) -> String {
+    return Regex::new(r#"[\s/]+"#).unwrap().replace(name, "_").to_string();
+}
+
 impl KeyRemapperConfiguration {
     pub fn new(name: &str, device_name_regex: &str) -> KeyRemapperConfiguration {
         KeyRemapperConfiguration {
@@ -87,13 +124,25 @@ impl KeyRemapperConfiguration {
             device_name_regex: device_name_regex.to_string(),
             id_regex: "".to_string(),
             use_system_tray: true,
+            use_control_socket: true,
             use_non_keyboard: true,
             grab_devices: true,
             write_to_uinput: true,
             uinput_events: EventsDescriptor::default(),
+            uinput_identity: None,
             global_lock_name: String::new(),
             uinput_device_name_suffix: String::new(),
             uinput_devices_prefix: String::new(),
+            tap_hold_keys: vec![],
+            chords: vec![],
+            leader: None,
+            layers: vec![],
+            gestures: vec![],
+            gesture_settings: GestureSettings::default(),
+            gamepad: None,
+            keyboard_layout: KeyboardLayout::us(),
+            unicode_input: UnicodeInput::CtrlShiftU,
+            modifiers: ModifierRegistry::new(),
             callbacks: Arc::new(RwLock::new(KeyRemapperCallbacks::new())),
             device_name_regex_re: None,
             id_regex_re: None,
@@ -123,6 +172,13 @@ impl KeyRemapperConfiguration {
         self
     }
 
+    /// Whether to listen on a control socket, which `keyremapper-ctl` and scripts can use to query and
+    /// control the running remapper. Enabled by default. See `keyremapper::control`.
+    pub fn set_use_control_socket(&mut self, value: bool) -> &mut KeyRemapperConfiguration {
+        self.use_control_socket = value;
+        self
+    }
+
     pub fn set_grab(&mut self, value: bool) -> &mut KeyRemapperConfiguration {
         self.grab_devices = value;
         self
@@ -138,11 +194,98 @@ impl KeyRemapperConfiguration {
         self
     }
 
+    /// Set the bus type, vendor / product IDs, version and phys of the uinput device, e.g. to the ones
+    /// of the source device, so compositors and libinput apply the same per-device settings to it.
+    pub fn set_uinput_identity(&mut self, identity: &DeviceIdentity) -> &mut KeyRemapperConfiguration {
+        self.uinput_identity = Some(identity.clone());
+        self
+    }
+
     pub fn set_use_non_keyboard(&mut self, value: bool) -> &mut KeyRemapperConfiguration {
         self.use_non_keyboard = value;
         self
     }
 
+    /// Add a tap-hold (dual-role) key. Events of the key don't reach `on_event`; instead, the tap
+    /// key is sent when it's tapped, and the hold action takes effect while it's held.
+    /// A `HoldAction::Modifier` needs to be a built-in one or added with `add_modifier()` first.
+    pub fn add_tap_hold_key(&mut self, key: &TapHoldKey) -> &mut KeyRemapperConfiguration {
+        if self.tap_hold_keys.iter().any(|k| k.code == key.code) {
+            panic!("Tap-hold key {} is already added", key.code);
+        }
+        if let HoldAction::Modifier(m) = key.hold {
+            if !self.modifiers.is_registered(m) {
+                panic!(r#"Unknown modifier "{}""#, m);
+            }
+        }
+        self.tap_hold_keys.push(key.clone());
+        self
+    }
+
+    /// Add a chord. Key events that may be part of a chord are held back until the chord completes
+    /// or its window passes, so they reach `on_event` slightly later.
+    pub fn add_chord(&mut self, chord: &Chord) -> &mut KeyRemapperConfiguration {
+        self.chords.push(chord.clone());
+        self
+    }
+
+    /// Set the leader key and its sequences. While a sequence is pending, the keys don't reach `on_event`,
+    /// and if it doesn't match, they're sent to the uinput device as-is.
+    pub fn set_leader(&mut self, leader: &Leader) -> &mut KeyRemapperConfiguration {
+        self.leader = Some(leader.clone());
+        self
+    }
+
+    /// Add a layer, which can be activated with `KeyRemapper::push_layer()` etc, or with its momentary key.
+    pub fn add_layer(&mut self, layer: &Layer) -> &mut KeyRemapperConfiguration {
+        if self.layers.iter().any(|l| l.name == layer.name) {
+            panic!("Layer {} is already added", layer.name);
+        }
+        self.layers.push(layer.clone());
+        self
+    }
+
+    /// Add a touchpad gesture. The touch events still reach `on_event`, so the touchpad keeps working
+    /// as a pointer when it's not grabbed.
+    pub fn add_gesture(&mut self, gesture: &GestureBinding) -> &mut KeyRemapperConfiguration {
+        self.gestures.push(gesture.clone());
+        self
+    }
+
+    pub fn set_gesture_settings(&mut self, settings: &GestureSettings) -> &mut KeyRemapperConfiguration {
+        self.gesture_settings = settings.clone();
+        self
+    }
+
+    /// Drive the keyboard and the mouse with the gamepads. The sticks move the pointer of a separate uinput
+    /// device created with `KeyRemapper::create_mouse_uinput()`. The mapped events don't reach `on_event`.
+    pub fn set_gamepad_mapping(&mut self, mapping: &GamepadMapping) -> &mut KeyRemapperConfiguration {
+        self.gamepad = Some(mapping.clone());
+        self
+    }
+
+    /// Register `keys` as a modifier named `name` for `InputEvent::with_modifiers()` and
+    /// `KeyRemapper::is_modifier_on()`, e.g. `add_modifier('p', &[ec::KEY_CAPSLOCK])`. With two keys,
+    /// `<` and `>` refer to the first and the second one. Panics if the name or any of the keys is
+    /// already used in this configuration.
+    pub fn add_modifier(&mut self, name: char, keys: &[i32]) -> &mut KeyRemapperConfiguration {
+        self.modifiers.register(name, keys);
+        self
+    }
+
+    /// Set the keyboard layout used by `KeyRemapper::type_text()`. The default is the US layout.
+    pub fn set_keyboard_layout(&mut self, layout: &KeyboardLayout) -> &mut KeyRemapperConfiguration {
+        self.keyboard_layout = layout.clone();
+        self
+    }
+
+    /// Set how `KeyRemapper::type_text()` types characters that the keyboard layout can't produce.
+    /// The default is `UnicodeInput::CtrlShiftU`.
+    pub fn set_unicode_input(&mut self, value: UnicodeInput) -> &mut KeyRemapperConfiguration {
+        self.unicode_input = value;
+        self
+    }
+
     pub fn on_init_args<F>(&mut self, callback: F) -> &mut KeyRemapperConfiguration
     where
         F: for<'a, 'b> Fn(clap::App<'a, 'b>) -> clap::App<'a, 'b> + Send + Sync + 'static,
@@ -232,8 +375,30 @@ impl KeyRemapperConfiguration {
         self
     }
 
+    /// Called with the name of the uinput device and each event written to it, including the SYN_REPORTs
+    /// and the key releases by `KeyRemapper::reset_out()`. The redundant events, which aren't written, aren't included.
+    /// It's called on the thread that sends the event, so it should be quick. It's called after the device is
+    /// unlocked, so it may send events or check the output, e.g. `KeyRemapper::is_out_key_on()`; the events
+    /// it sends are passed to it too.
+    pub fn on_out_event<F: Fn(&str, &evdev::InputEvent) + Send + Sync + 'static>(&mut self, callback: F) -> &mut KeyRemapperConfiguration {
+        {
+            let mut callbacks = self.callbacks.write();
+            callbacks.on_out_event = Arc::new(callback);
+        }
+        self
+    }
+
+    /// Remap events with the given `Rules` instead of a hand-written `on_event` callback.
+    /// Events that don't match any rule are sent as-is, except for the keys of the modifiers
+    /// like ESC that are only tapped on release. See the `rules` module.
+    /// The modifiers the rules use need to be built-in ones or added with `add_modifier()` first.
+    pub fn set_rules(&mut self, rules: Rules) -> &mut KeyRemapperConfiguration {
+        let remapper = RuleRemapper::new(rules, &self.modifiers);
+        self.on_event(move |km, _device, ev| remapper.on_event(km, ev))
+    }
+
     pub(crate) fn set_defaults(&mut self) -> &mut KeyRemapperConfiguration {
-        let name_cleansed = Regex::new(r#"[\s/]+"#).unwrap().replace(&self.name, "_").to_string();
+        let name_cleansed = cleanse_name(&self.name);
         if self.global_lock_name.is_empty() {
             self.global_lock_name = name_cleansed.clone();
         }
@@ -252,6 +417,7 @@ impl KeyRemapperConfiguration {
 
         if self.uinput_events.is_empty() {
             self.uinput_events = EventsDescriptor::with_all_key_events();
+            self.uinput_events.add_keyboard_led_events();
         }
         self
     }
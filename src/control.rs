@@ -0,0 +1,401 @@
+//! Control socket, which lets other programs query and control a running remapper, e.g. the
+//! `keyremapper-ctl` sample from window manager key bindings.
+//!
+//! Each remapper listens on `$XDG_RUNTIME_DIR/keyremapper/NAME.sock` (see `socket_path()`), unless
+//! it's disabled with `KeyRemapperConfiguration::set_use_control_socket()`. The protocol is line based:
+//! a client sends one command per line, e.g. `mode nav` or `press TAB as`, and gets back one line
+//! of tab-separated fields for each. The first field is `ok` followed by the results, or `error`
+//! followed by the message. See `COMMANDS` for the commands.
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{config::cleanse_name, evdev::ec, KeyRemapper};
+
+const SOCKET_EXTENSION: &str = "sock";
+
+/// How long to wait for the I/O thread to run a command. It may be busy, e.g. detecting the devices.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The commands and their descriptions, which the `help` command returns.
+pub const COMMANDS: &[&str] = &[
+    "status: Show the name, whether it's paused, the mode (the top layer, or empty for the base layer), the active layers and the number of devices",
+    "devices: List the input devices in use",
+    "layers: List all the layers",
+    "mode [LAYER]: Activate only LAYER, or only the base layer without LAYER",
+    "pause: Stop remapping, and send the input events as-is",
+    "resume: Resume remapping",
+    "toggle-pause: Pause or resume",
+    r#"press KEY [MODIFIERS]: Press a key with modifiers, e.g. "press TAB as" for Alt+Shift+Tab"#,
+    "type TEXT: Type the text",
+    "reset: Reset the states and release all the pressed keys, e.g. when some keys are stuck",
+    "help: Show the commands",
+];
+
+/// Directory of the control sockets: `$XDG_RUNTIME_DIR/keyremapper`, or `/tmp/keyremapper-UID`
+/// if `$XDG_RUNTIME_DIR` isn't set.
+pub fn socket_dir() -> PathBuf {
+    return match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("keyremapper"),
+        _ => PathBuf::from(format!("/tmp/keyremapper-{}", unsafe { libc::getuid() })),
+    };
+}
+
+/// Path of the control socket of the remapper named `name`, i.e. `KeyRemapperConfiguration::new()`'s `name`.
+pub fn socket_path(name: &str) -> PathBuf {
+    return socket_dir().join(format!("{}.{}", cleanse_name(name), SOCKET_EXTENSION));
+}
+
+/// Return the control sockets of the running remappers, skipping the ones left over by the
+/// remappers that didn't exit cleanly.
+pub fn list_sockets() -> Vec<PathBuf> {
+    let entries = match fs::read_dir(socket_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut ret: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == SOCKET_EXTENSION))
+        .filter(|path| UnixStream::connect(path).is_ok())
+        .collect();
+    ret.sort();
+    return ret;
+}
+
+/// Send a command to the remapper listening on `path`, and return the fields of the response
+/// after `ok`. An `error` response is returned as an `Err`.
+pub fn send_command(path: &Path, command: &str) -> anyhow::Result<Vec<String>> {
+    if command.contains('\n') {
+        bail!("Command must be a single line");
+    }
+    let mut stream = UnixStream::connect(path).with_context(|| format!("Unable to connect to {}", path.display()))?;
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT * 2))?;
+    writeln!(stream, "{}", command)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let mut fields = line.trim_end_matches('\n').split('\t').map(|f| f.to_string());
+    return match fields.next().as_deref() {
+        Some("ok") => Ok(fields.collect()),
+        Some("error") => Err(anyhow!(fields.collect::<Vec<_>>().join(" "))),
+        _ => Err(anyhow!("Invalid response: {:?}", line)),
+    };
+}
+
+/// Fail unless `dir` is a directory, not a symlink, owned by the current user with the mode 0700.
+fn check_private_dir(dir: &Path) -> anyhow::Result<()> {
+    let metadata = fs::symlink_metadata(dir).with_context(|| format!("Unable to stat {}", dir.display()))?;
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        bail!(
+            "{} must be a directory owned by UID {} with the mode 0700 (owner: {}, mode: {:o})",
+            dir.display(),
+            uid,
+            metadata.uid(),
+            metadata.mode() & 0o7777
+        );
+    }
+    return Ok(());
+}
+
+/// Listens on a control socket, and runs the commands on the I/O thread of a `KeyRemapper`.
+/// The socket file is removed when it's dropped.
+#[derive(Debug)]
+pub struct ControlServer {
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Start listening on `path`. Fails if another remapper is listening on it already, or if its
+    /// directory is accessible to other users.
+    pub fn start(key_remapper: &KeyRemapper, path: &Path) -> anyhow::Result<ControlServer> {
+        if let Some(dir) = path.parent() {
+            // The commands can inject keys, so only allow the same user.
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .with_context(|| format!("Unable to create {}", dir.display()))?;
+            // It may already exist, e.g. `/tmp/keyremapper-UID` created by another user.
+            check_private_dir(dir)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!("Another remapper is listening on {}", path.display());
+            }
+            // Left over by a process that didn't exit cleanly.
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path).with_context(|| format!("Unable to listen on {}", path.display()))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        log::info!("Control socket: {}", path.display());
+
+        let key_remapper = key_remapper.clone();
+        thread::Builder::new()
+            .name(format!("keyremapper-{}-control", key_remapper.config().name))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let key_remapper = key_remapper.clone();
+                            thread::spawn(move || serve_client(&key_remapper, stream));
+                        }
+                        Err(e) => eprintln!("Unable to accept a control connection: {}", e),
+                    }
+                }
+            })?;
+        return Ok(ControlServer { path: path.to_path_buf() });
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Run the commands sent by a client until it disconnects.
+fn serve_client(key_remapper: &KeyRemapper, stream: UnixStream) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Unable to set up a control connection: {}", e);
+            return;
+        }
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        log::debug!("Control command: {}", line);
+
+        let response = match run_on_io_thread(key_remapper, &line) {
+            Ok(fields) => std::iter::once("ok".to_string()).chain(fields).collect::<Vec<_>>().join("\t"),
+            // The message may be multiple lines, e.g. with the causes.
+            Err(e) => format!("error\t{}", format!("{:#}", e).replace(['\t', '\n'], " ")),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+fn run_on_io_thread(key_remapper: &KeyRemapper, line: &str) -> anyhow::Result<Vec<String>> {
+    let (sender, receiver) = mpsc::channel();
+    let line = line.to_string();
+    key_remapper.schedule_after(Duration::from_secs(0), move |km| {
+        let _ = sender.send(handle_command(km, &line));
+    });
+    return receiver
+        .recv_timeout(COMMAND_TIMEOUT)
+        .map_err(|_| anyhow!("Timed out waiting for the remapper"))?;
+}
+
+fn check_arg_count(command: &str, args: &[&str], min: usize, max: usize) -> anyhow::Result<()> {
+    if args.len() < min || args.len() > max {
+        bail!(r#"Wrong number of arguments for "{}""#, command);
+    }
+    return Ok(());
+}
+
+/// Run a single command line, and return the fields of the response. Must be called on the I/O thread.
+pub fn handle_command(km: &KeyRemapper, line: &str) -> anyhow::Result<Vec<String>> {
+    let line = line.trim();
+    let (command, rest) = match line.find(char::is_whitespace) {
+        Some(pos) => (&line[..pos], line[pos..].trim_start()),
+        None => (line, ""),
+    };
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    match command {
+        "status" => {
+            check_arg_count(command, &args, 0, 0)?;
+            return Ok(vec![
+                format!("name={}", km.config().name),
+                format!("paused={}", km.is_paused()),
+                format!("mode={}", km.top_layer().unwrap_or_default()),
+                format!("layers={}", km.active_layers().join(",")),
+                format!("devices={}", km.devices().len()),
+            ]);
+        }
+        "devices" => {
+            check_arg_count(command, &args, 0, 0)?;
+            return Ok(km.devices().iter().map(|d| format!("{} {}", d.path(), d.name())).collect());
+        }
+        "layers" => {
+            check_arg_count(command, &args, 0, 0)?;
+            return Ok(km.config().layers.iter().map(|l| l.name.clone()).collect());
+        }
+        "mode" => {
+            check_arg_count(command, &args, 0, 1)?;
+            match args.first() {
+                Some(name) => {
+                    // switch_layer() panics with an unknown layer.
+                    if !km.config().layers.iter().any(|l| l.name == *name) {
+                        bail!(r#"Unknown layer "{}""#, name);
+                    }
+                    km.switch_layer(name);
+                }
+                None => km.clear_layers(),
+            }
+        }
+        "pause" | "resume" | "toggle-pause" => {
+            check_arg_count(command, &args, 0, 0)?;
+            let paused = match command {
+                "pause" => true,
+                "resume" => false,
+                _ => !km.is_paused(),
+            };
+            km.set_paused(paused);
+        }
+        "press" => {
+            check_arg_count(command, &args, 1, 2)?;
+            let code = ec::get_key_code_by_name(args[0]).ok_or_else(|| anyhow!(r#"Unknown key "{}""#, args[0]))?;
+            let modifiers = args.get(1).copied().unwrap_or("");
+            // press_key() panics with invalid modifiers.
+            if modifiers != "*" && !modifiers.chars().all(|c| "acsw<>".contains(c)) {
+                bail!(r#"Invalid modifiers "{}""#, modifiers);
+            }
+            if !km.config().write_to_uinput {
+                bail!("uinput is disabled");
+            }
+            km.press_key(code, modifiers);
+        }
+        "type" => {
+            if rest.is_empty() {
+                bail!(r#"Wrong number of arguments for "{}""#, command);
+            }
+            if !km.config().write_to_uinput {
+                bail!("uinput is disabled");
+            }
+            km.type_text(rest);
+        }
+        "reset" => {
+            check_arg_count(command, &args, 0, 0)?;
+            km.reset_all();
+        }
+        "help" => return Ok(COMMANDS.iter().map(|c| c.to_string()).collect()),
+        "" => bail!("Empty command"),
+        _ => bail!(r#"Unknown command "{}". Use "help" to list the commands"#, command),
+    }
+    return Ok(vec![]);
+}
+
+#[test]
+fn test_control_commands() {
+    use crate::{layer::Layer, testing::TestRemapper, KeyRemapperConfiguration};
+
+    let mut config = KeyRemapperConfiguration::new("Control Test", "");
+    config.add_layer(&Layer::new("nav")).on_event(|km, _device, ev| {
+        if ev.is_key_event() && ev.code == ec::KEY_A {
+            km.send_key_event(ec::KEY_B, ev.value);
+            return;
+        }
+        km.send_event(ev);
+    });
+
+    let t = TestRemapper::new(&config);
+    let kbd = t.add_keyboard("keyboard");
+    t.start();
+    let km = t.remapper();
+
+    let status = |expected: &[&str]| assert_eq!(expected.to_vec(), handle_command(km, "status").unwrap());
+    status(&["name=Control Test", "paused=false", "mode=", "layers=", "devices=1"]);
+    assert_eq!(vec![format!("{} keyboard", kbd.path())], handle_command(km, "devices").unwrap());
+    assert_eq!(vec!["nav"], handle_command(km, "layers").unwrap());
+
+    handle_command(km, "mode nav").unwrap();
+    status(&["name=Control Test", "paused=false", "mode=nav", "layers=nav", "devices=1"]);
+    assert!(handle_command(km, "mode nosuchlayer").is_err());
+    handle_command(km, "mode").unwrap();
+    status(&["name=Control Test", "paused=false", "mode=", "layers=", "devices=1"]);
+
+    handle_command(km, "press tab as").unwrap();
+    assert_eq!(
+        vec![
+            (ec::KEY_LEFTALT, 1),
+            (ec::KEY_LEFTSHIFT, 1),
+            (ec::KEY_TAB, 1),
+            (ec::KEY_TAB, 0),
+            (ec::KEY_LEFTALT, 0),
+            (ec::KEY_LEFTSHIFT, 0)
+        ],
+        t.take_output_keys()
+    );
+    assert!(handle_command(km, "press nosuchkey").is_err());
+    assert!(handle_command(km, "press tab x").is_err());
+    assert!(handle_command(km, "nosuchcommand").is_err());
+
+    // While paused, the events aren't remapped, and pausing releases the pressed keys.
+    t.press(&kbd, ec::KEY_A);
+    assert_eq!(vec![(ec::KEY_B, 1)], t.take_output_keys());
+    handle_command(km, "pause").unwrap();
+    assert_eq!(vec![(ec::KEY_B, 0)], t.take_output_keys());
+    t.release(&kbd, ec::KEY_A);
+    t.tap(&kbd, ec::KEY_A);
+    assert_eq!(vec![(ec::KEY_A, 1), (ec::KEY_A, 0)], t.take_output_keys());
+    status(&["name=Control Test", "paused=true", "mode=", "layers=", "devices=1"]);
+    handle_command(km, "toggle-pause").unwrap();
+    t.tap(&kbd, ec::KEY_A);
+    assert_eq!(vec![(ec::KEY_B, 1), (ec::KEY_B, 0)], t.take_output_keys());
+
+    // Through the socket, in a private directory. The commands run when the timers run.
+    let dir = env::temp_dir().join(format!("keyremapper-control-test-{}", std::process::id()));
+    let path = dir.join(format!("test.{}", SOCKET_EXTENSION));
+    let server = ControlServer::start(km, &path).unwrap();
+    assert!(ControlServer::start(km, &path).is_err());
+    let client = {
+        let path = path.clone();
+        thread::spawn(move || (send_command(&path, "mode nav"), send_command(&path, "mode foo")))
+    };
+    while !client.is_finished() {
+        t.advance(Duration::from_millis(1));
+        thread::sleep(Duration::from_millis(1));
+    }
+    let (ok, error) = client.join().unwrap();
+    assert_eq!(Vec::<String>::new(), ok.unwrap());
+    assert_eq!(r#"Unknown layer "foo""#, error.unwrap_err().to_string());
+    assert_eq!(Some("nav".to_string()), km.top_layer());
+
+    drop(server);
+    assert!(!path.exists());
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn test_check_private_dir() {
+    let dir = env::temp_dir().join(format!("keyremapper-private-dir-test-{}", std::process::id()));
+    fs::DirBuilder::new().mode(0o755).create(&dir).unwrap();
+    assert!(check_private_dir(&dir).is_err());
+
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+    check_private_dir(&dir).unwrap();
+
+    // Not a symlink to it either.
+    let link = dir.with_extension("link");
+    std::os::unix::fs::symlink(&dir, &link).unwrap();
+    assert!(check_private_dir(&link).is_err());
+
+    fs::remove_file(&link).unwrap();
+    fs::remove_dir(&dir).unwrap();
+}
@@ -1,5 +1,6 @@
 use std::{
     cell::RefCell,
+    collections::HashSet,
     error::Error,
     path::PathBuf,
     process::{self, Command},
@@ -18,14 +19,30 @@ use rand::prelude::*;
 use signal_hook::iterator::Signals;
 
 use crate::{
+    chord::{ChordAction, ChordOutput, ChordState},
+    control::{self, ControlServer},
     evdev::{
         self,
         ec::{self, EventType},
+        ff::FfForwarder,
+        sink::{CallbackSink, OutputSink},
         uinput::Uinput,
-        EventsDescriptor, InputEventTracker,
+        DeviceIdentity, EventsDescriptor, PerDeviceTracker,
     },
+    gamepad::GamepadState,
+    gesture::{GestureAction, GestureState},
+    hold::{ActiveHold, HoldState},
+    layer::{LayerStack, LayerTarget},
+    leader::{LeaderOutput, LeaderState},
+    led::LedState,
+    macros::{Macro, MacroRecorder, MacroStore, MacroTiming},
+    modifiers::{ModifierRegistry, ModifierSpec},
     res::{self, *},
-    select, KeyRemapperConfiguration,
+    select,
+    taphold::{HoldAction, TapHoldOutput, TapHoldState},
+    text::key_presses_for_char,
+    timer::{Clock, SystemClock, TimerHandle, TimerQueue, Waker},
+    KeyRemapperConfiguration,
 };
 
 use crate::singleton::ensure_singleton;
@@ -41,6 +58,23 @@ pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 /// If all these keys are pressed, force stop the process.
 static EMERGENCY_COMBO: &[i32] = &[ec::KEY_Z, ec::KEY_X, ec::KEY_C, ec::KEY_LEFTSHIFT, ec::KEY_LEFTALT];
 
+/// Finds the input devices to read the events from. `KeyRemapper` uses `DeviceFinder`, which opens
+/// the evdev devices matching the configuration; tests use `testing::MemoryInputSource`.
+pub trait InputSource: Send {
+    fn find_devices(&mut self) -> Result<Vec<evdev::EvdevDevice>>;
+}
+
+/// `InputSource` that finds the evdev devices matching the given `KeyRemapperConfiguration`.
+pub(crate) struct DeviceFinder {
+    config: KeyRemapperConfiguration,
+}
+
+impl InputSource for DeviceFinder {
+    fn find_devices(&mut self) -> Result<Vec<evdev::EvdevDevice>> {
+        return find_devices(&self.config);
+    }
+}
+
 /// Find all the evdev devices matching the given `KeyRemapperConfiguration`.
 fn find_devices(config: &KeyRemapperConfiguration) -> Result<Vec<evdev::EvdevDevice>> {
     log::debug!("Looking for devices...");
@@ -106,21 +140,21 @@ fn find_devices(config: &KeyRemapperConfiguration) -> Result<Vec<evdev::EvdevDev
 }
 
 pub struct KeyRemapperInput {
-    config: KeyRemapperConfiguration,
+    source: Box<dyn InputSource>,
     devices: Vec<evdev::EvdevDevice>,
 }
 
 impl KeyRemapperInput {
-    fn new(config: KeyRemapperConfiguration) -> Result<KeyRemapperInput> {
+    fn new(source: Box<dyn InputSource>) -> Result<KeyRemapperInput> {
         // Find the target devices.
         return Ok(KeyRemapperInput {
-            config: config,
+            source: source,
             devices: vec![],
         });
     }
 
     fn refresh_devices(&mut self) -> Result<()> {
-        self.devices = find_devices(&self.config)?;
+        self.devices = self.source.find_devices()?;
         Ok(())
     }
 
@@ -140,7 +174,12 @@ impl KeyRemapperInput {
 
 pub struct KeyRemapperUi {
     app_indicator: Option<AppIndicator>,
-    notification: NotificationHandle,
+
+    /// Summary of the notifications, or `None` if they're only logged.
+    notification_summary: Option<String>,
+
+    /// The shown notification, which the next one replaces, unless it's closed.
+    notification: Option<NotificationHandle>,
 }
 
 unsafe impl Send for KeyRemapperUi {}
@@ -193,39 +232,136 @@ impl KeyRemapperUi {
 
         return Ok(KeyRemapperUi {
             app_indicator: indicator,
-            notification: notification,
+            notification_summary: Some(config.name.clone()),
+            notification: Some(notification),
         });
     }
 
+    /// UI without the tray icon and the notifications, e.g. for the tests. The notifications are only logged.
+    pub(crate) fn headless() -> KeyRemapperUi {
+        return KeyRemapperUi {
+            app_indicator: None,
+            notification_summary: None,
+            notification: None,
+        };
+    }
+
     fn show_notification_with_timeout(&mut self, message: &str, timeout: Duration) {
-        let notification = &mut self.notification;
-        notification.body(message).timeout(Timeout::Milliseconds(timeout.as_millis() as u32));
-        notification.update();
+        let summary = match &self.notification_summary {
+            Some(summary) => summary,
+            None => {
+                log::info!("Notification: {}", message);
+                return;
+            }
+        };
+        let timeout = Timeout::Milliseconds(timeout.as_millis() as u32);
+        match &mut self.notification {
+            Some(notification) => {
+                notification.body(message).timeout(timeout);
+                notification.update();
+            }
+            None => match Notification::new().summary(summary).body(message).timeout(timeout).show() {
+                Ok(notification) => self.notification = Some(notification),
+                Err(e) => eprintln!("Unable to show a notification: {:?}", e),
+            },
+        }
+    }
+
+    fn close_notification(&mut self) {
+        if let Some(notification) = self.notification.take() {
+            notification.close();
+        }
     }
 
     fn set_icon(&mut self, icon: PathBuf) {
-        self.app_indicator.as_mut().unwrap().set_icon(&(icon.into_os_string().into_string().unwrap()))
+        if let Some(app_indicator) = self.app_indicator.as_mut() {
+            app_indicator.set_icon(&(icon.into_os_string().into_string().unwrap()));
+        }
     }
 }
 
+/// Creates the output `Uinput`s from the name, the supported events and the identity. Tests replace
+/// the default one with a factory that creates `Uinput`s with `sink::MemorySink`s.
+pub(crate) type UinputFactory = Arc<dyn Fn(&str, &EventsDescriptor, Option<&DeviceIdentity>) -> Result<Uinput> + Send + Sync + 'static>;
+
+fn create_real_uinput(name: &str, supported_events: &EventsDescriptor, identity: Option<&DeviceIdentity>) -> Result<Uinput> {
+    let ui = match identity {
+        Some(identity) => Uinput::with_identity(name, supported_events, identity)?,
+        None => Uinput::new(name, supported_events)?,
+    };
+    return Ok(ui);
+}
+
 /// Create a new uinput device using the given `KeyRemapperConfiguration` with a suffix.
-fn create_uinput(config: &KeyRemapperConfiguration, name_suffix: &str, supported_events: &EventsDescriptor) -> Result<Uinput> {
+fn create_uinput(
+    config: &KeyRemapperConfiguration,
+    factory: &UinputFactory,
+    name_suffix: &str,
+    supported_events: &EventsDescriptor,
+    identity: Option<&DeviceIdentity>,
+) -> Result<Uinput> {
     let mut name = config.uinput_devices_prefix.clone();
     name.push_str(name_suffix);
 
-    let ui = Uinput::new(&name, supported_events)?;
+    let ui = (*factory)(&name, supported_events, identity)?;
+
+    let on_out_event = config.callbacks_cloned().on_out_event;
+    ui.add_sink(Box::new(CallbackSink::new(move |ev| (*on_out_event)(&name, ev))));
     return Ok(ui);
 }
 
+type TimerCallback = Arc<dyn Fn(&KeyRemapper) + Send + Sync + 'static>;
+
 #[derive(Clone)]
 pub struct KeyRemapper {
     config: KeyRemapperConfiguration,
+
+    /// `config.modifiers`, which the events refer to.
+    modifiers: &'static ModifierRegistry,
     uinput: Option<Uinput>,
     input: Arc<ReentrantMutex<RefCell<KeyRemapperInput>>>,
-    input_event_tracker: Arc<ReentrantMutex<RefCell<InputEventTracker>>>,
+    input_event_trackers: Arc<ReentrantMutex<RefCell<PerDeviceTracker>>>,
     ui: Arc<ReentrantMutex<RefCell<KeyRemapperUi>>>,
 
     all_uinputs: Arc<ReentrantMutex<RefCell<Vec<Uinput>>>>,
+    uinput_factory: UinputFactory,
+
+    clock: Arc<dyn Clock>,
+    timers: Arc<ReentrantMutex<RefCell<TimerQueue<TimerCallback>>>>,
+    waker: Waker,
+
+    /// Set by `stop_main_loop()`.
+    stopping: Arc<AtomicBool>,
+
+    /// Set by `set_paused()`.
+    paused: Arc<AtomicBool>,
+
+    tap_hold: Arc<ReentrantMutex<RefCell<TapHoldState<evdev::EvdevDevice>>>>,
+    chords: Arc<ReentrantMutex<RefCell<ChordState<evdev::EvdevDevice>>>>,
+    leader: Arc<ReentrantMutex<RefCell<LeaderState<evdev::EvdevDevice>>>>,
+
+    layers: Arc<ReentrantMutex<RefCell<LayerStack>>>,
+
+    gestures: Arc<ReentrantMutex<RefCell<GestureState>>>,
+
+    gamepad: Arc<ReentrantMutex<RefCell<GamepadState>>>,
+
+    /// Receives the pointer and wheel movements of the gamepad sticks, if any are mapped.
+    gamepad_mouse: Option<Uinput>,
+
+    holds: Arc<ReentrantMutex<RefCell<HoldState<ModifierState>>>>,
+
+    leds: Arc<ReentrantMutex<RefCell<LedState>>>,
+
+    /// Input devices to forward the LED states to. It's a copy of `KeyRemapperInput::devices`, which
+    /// is borrowed by the main loop.
+    led_devices: Arc<ReentrantMutex<RefCell<Vec<evdev::EvdevDevice>>>>,
+
+    /// Forwards the force-feedback effects played on the main uinput device to the input devices.
+    ff: Arc<ReentrantMutex<RefCell<FfForwarder>>>,
+
+    macro_recorder: Arc<ReentrantMutex<RefCell<Option<MacroRecorder>>>>,
+    macro_playback: Arc<ReentrantMutex<RefCell<Option<MacroPlayback>>>>,
 }
 
 const MODIFIER_COUNT: usize = 8; // We need this for ModifierState as a const.
@@ -243,41 +379,116 @@ static MODIFIER_KEYS: &'static [i32; MODIFIER_COUNT] = &[
 
 type ModifierState = [bool; MODIFIER_COUNT];
 
+/// State of the macro being played.
+struct MacroPlayback {
+    handles: Vec<TimerHandle>,
+    out_modifier_state: ModifierState,
+
+    /// Keys pressed by the macro so far, which are released if the playback is stopped.
+    pressed: HashSet<i32>,
+}
+
 impl KeyRemapper {
     fn new(config: KeyRemapperConfiguration) -> KeyRemapper {
         let ui = KeyRemapperUi::new(&config).unwrap();
+        return KeyRemapper::with_ui(config, ui);
+    }
+
+    /// Create a `KeyRemapper` with the real input devices and uinput devices, and the given UI.
+    pub(crate) fn with_ui(config: KeyRemapperConfiguration, ui: KeyRemapperUi) -> KeyRemapper {
+        let source = DeviceFinder { config: config.clone() };
+        return KeyRemapper::with_parts(config, ui, Box::new(source), Arc::new(create_real_uinput), Arc::new(SystemClock));
+    }
 
+    /// Create a `KeyRemapper` with the given UI, input source, output and clock, instead of the real ones.
+    pub(crate) fn with_parts(
+        config: KeyRemapperConfiguration,
+        ui: KeyRemapperUi,
+        source: Box<dyn InputSource>,
+        uinput_factory: UinputFactory,
+        clock: Arc<dyn Clock>,
+    ) -> KeyRemapper {
         // Set up uinput
-        // let uinput = if config.builder.
         let uinput = if config.write_to_uinput {
-            Some(create_uinput(&config, "", &config.uinput_events).expect("failed to create uinput device"))
+            Some(create_uinput(&config, &uinput_factory, "", &config.uinput_events, config.uinput_identity.as_ref()).expect("failed to create uinput device"))
         } else {
             None
         };
 
-        let input = KeyRemapperInput::new(config.clone()).expect("failed to initialize input devices");
+        let input = KeyRemapperInput::new(source).expect("failed to initialize input devices");
+        let tap_hold = TapHoldState::new(config.tap_hold_keys.clone());
+        let chords = ChordState::new(config.chords.clone());
+        let leader = LeaderState::new(config.leader.clone());
+        let layers = LayerStack::new(config.layers.clone());
+        let gestures = GestureState::new(config.gestures.clone(), config.gesture_settings.clone());
+        let gamepad = GamepadState::new(config.gamepad.clone());
+        let mut ff = FfForwarder::new();
+        if let Some(codes) = config.uinput_events.events.get(&EventType::EV_FF) {
+            ff.set_uinput_codes(codes);
+        }
 
-        let ret = KeyRemapper {
+        let mut ret = KeyRemapper {
+            modifiers: config.modifiers.intern(),
             config,
             uinput,
             input: Arc::new(ReentrantMutex::new(RefCell::new(input))),
             ui: Arc::new(ReentrantMutex::new(RefCell::new(ui))),
             all_uinputs: Arc::new(ReentrantMutex::new(RefCell::new(vec![]))),
-            input_event_tracker: Arc::new(ReentrantMutex::new(RefCell::new(InputEventTracker::new()))),
+            uinput_factory,
+            clock,
+            input_event_trackers: Arc::new(ReentrantMutex::new(RefCell::new(PerDeviceTracker::new()))),
+            timers: Arc::new(ReentrantMutex::new(RefCell::new(TimerQueue::new()))),
+            waker: Waker::new().expect("failed to create eventfd"),
+            stopping: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            tap_hold: Arc::new(ReentrantMutex::new(RefCell::new(tap_hold))),
+            chords: Arc::new(ReentrantMutex::new(RefCell::new(chords))),
+            leader: Arc::new(ReentrantMutex::new(RefCell::new(leader))),
+            layers: Arc::new(ReentrantMutex::new(RefCell::new(layers))),
+            gestures: Arc::new(ReentrantMutex::new(RefCell::new(gestures))),
+            gamepad: Arc::new(ReentrantMutex::new(RefCell::new(gamepad))),
+            gamepad_mouse: None,
+            holds: Arc::new(ReentrantMutex::new(RefCell::new(HoldState::new()))),
+            leds: Arc::new(ReentrantMutex::new(RefCell::new(LedState::new()))),
+            led_devices: Arc::new(ReentrantMutex::new(RefCell::new(vec![]))),
+            ff: Arc::new(ReentrantMutex::new(RefCell::new(ff))),
+            macro_recorder: Arc::new(ReentrantMutex::new(RefCell::new(None))),
+            macro_playback: Arc::new(ReentrantMutex::new(RefCell::new(None))),
         };
         if let Some(u) = ret.uinput.as_ref() {
             ret.add_uinput(&u);
         }
+        let has_sticks = ret.config.gamepad.as_ref().map_or(false, |m| !m.sticks.is_empty());
+        if has_sticks && ret.config.write_to_uinput {
+            ret.gamepad_mouse = Some(ret.create_mouse_uinput("-gamepad"));
+        }
         return ret;
     }
 
     /// Create a new uinput device supporting given events using the with a suffix.
     pub fn create_uinput(&self, name_suffix: &str, supported_events: &EventsDescriptor) -> Uinput {
-        let u = create_uinput(&self.config, name_suffix, supported_events).expect("failed to create uinput device");
+        let u = create_uinput(&self.config, &self.uinput_factory, name_suffix, supported_events, None).expect("failed to create uinput device");
+        self.add_uinput(&u);
+        return u;
+    }
+
+    /// Same as `create_uinput()`, but also set the bus type, vendor / product IDs, version and phys,
+    /// so compositors and libinput apply the same per-device settings as `identity`'s device.
+    pub fn create_uinput_with_identity(&self, name_suffix: &str, supported_events: &EventsDescriptor, identity: &DeviceIdentity) -> Uinput {
+        let u = create_uinput(&self.config, &self.uinput_factory, name_suffix, supported_events, Some(identity)).expect("failed to create uinput device");
         self.add_uinput(&u);
         return u;
     }
 
+    /// Create a new uinput device that mirrors `device`'s capabilities and identity, except force-feedback:
+    /// only the main uinput device forwards the effects to the input devices, and nothing would answer
+    /// the uploads to this one.
+    pub fn create_cloned_uinput(&self, name_suffix: &str, device: &evdev::EvdevDevice) -> Uinput {
+        let mut events = device.supported_events();
+        events.events.remove(&EventType::EV_FF);
+        return self.create_uinput_with_identity(name_suffix, &events, &device.identity());
+    }
+
     /// Create a new uinput device supporting mouse events using the with a suffix.
     pub fn create_mouse_uinput(&self, name_suffix: &str) -> Uinput {
         return self.create_uinput(name_suffix, &EventsDescriptor::with_mouse_events());
@@ -293,6 +504,12 @@ impl KeyRemapper {
         self.show_notification_with_timeout(message, Duration::from_secs(3))
     }
 
+    /// Close the shown notification, if any.
+    pub fn close_notification(&self) {
+        let ui = self.ui.lock();
+        ui.borrow_mut().close_notification();
+    }
+
     /// Show a notification with the given message with a custom timeout.
     pub fn show_notification_with_timeout(&self, message: &str, timeout: Duration) {
         let ui = self.ui.lock();
@@ -308,12 +525,29 @@ impl KeyRemapper {
         });
     }
 
+    pub(crate) fn config(&self) -> &KeyRemapperConfiguration {
+        return &self.config;
+    }
+
+    /// Current time of the remapper's clock. Use it instead of `Instant::now()` for the time-based
+    /// logic, so it works with the virtual clock in the tests.
+    pub fn now(&self) -> Instant {
+        return self.clock.now();
+    }
+
     fn ensure_uinput(&self) {
         if self.uinput.is_none() {
             panic!("uinput device is not available");
         }
     }
 
+    /// Also write the events sent to the uinput device to `sink`, e.g. `sink::LogSink` or `sink::UnixSocketSink`.
+    /// Multiple sinks can be added. See `Uinput::add_sink()`.
+    pub fn add_output_sink(&self, sink: Box<dyn OutputSink>) {
+        self.ensure_uinput();
+        self.uinput.as_ref().unwrap().add_sink(sink);
+    }
+
     /// Send a SYN_REPORT event. Normally `SyncedUinput` sends them automatically, so this doesn't need to be called.
     pub fn send_syn_report(&self) {
         self.ensure_uinput();
@@ -324,12 +558,14 @@ impl KeyRemapper {
     pub fn send_event(&self, event: &evdev::InputEvent) {
         self.ensure_uinput();
         self.uinput.as_ref().unwrap().send_event(event).unwrap();
+        self.record_sent_events(std::slice::from_ref(event));
     }
 
     /// Send multiple events at once, followed by a syn report.
     pub fn send_events(&self, events: &[evdev::InputEvent]) {
         self.ensure_uinput();
         self.uinput.as_ref().unwrap().send_events(events).unwrap();
+        self.record_sent_events(events);
     }
 
     /// Send a single key event.
@@ -356,32 +592,139 @@ impl KeyRemapper {
     }
 
     pub fn get_in_key_state(&self, code: i32) -> i32 {
-        let tracker = self.input_event_tracker.lock();
-        return tracker.borrow().key_state(code);
+        let trackers = self.input_event_trackers.lock();
+        return trackers.borrow().key_state(code);
+    }
+
+    /// Same as `get_in_key_state()`, but only on `device`.
+    pub fn get_in_key_state_on_device(&self, device: &evdev::EvdevDevice, code: i32) -> i32 {
+        let trackers = self.input_event_trackers.lock();
+        return trackers.borrow().device_key_state(&device.id(), code);
     }
 
+    /// Return true if `code` is pressed on `device`. Unlike `is_key_on()`, which is true if the key is
+    /// pressed on any of the devices, this tells which device it's pressed on.
+    pub fn is_key_on_device(&self, device: &evdev::EvdevDevice, code: i32) -> bool {
+        self.get_in_key_state_on_device(device, code) > 0
+    }
+
+    /// Return the IDs (`EvdevDevice::id()`) of the devices where `code` is pressed.
+    pub fn devices_with_key_on(&self, code: i32) -> Vec<String> {
+        let trackers = self.input_event_trackers.lock();
+        return trackers.borrow().devices_with_key_on(code);
+    }
+
+    /// Return true if `code` is pressed on any of the input devices.
     pub fn is_key_on(&self, code: i32) -> bool {
         self.get_in_key_state(code) > 0
     }
 
+    /// Same as `is_key_on()`, except tap-hold keys only count while they're decided as a hold.
+    fn is_modifier_key_on(&self, code: i32) -> bool {
+        if self.tap_hold.lock().borrow().is_tap_hold_key(code) {
+            return false;
+        }
+        return self.is_key_on(code);
+    }
+
+    /// Return true if a tap-hold key is held as `HoldAction::Modifier(modifier)`.
+    fn is_tap_hold_modifier_on(&self, modifier: char) -> bool {
+        return self.tap_hold.lock().borrow().is_modifier_held(modifier);
+    }
+
+    /// Turn an LED, e.g. `ec::LED_SCROLLL`, on or off on the input devices. It overrides the state
+    /// set by the host until `clear_led()` is called.
+    pub fn set_led(&self, code: i32, on: bool) {
+        let changed = self.leds.lock().borrow_mut().set_override(code, Some(on));
+        if let Some(on) = changed {
+            self.apply_led(code, on);
+        }
+    }
+
+    /// Stop overriding an LED, and go back to the state set by the host.
+    pub fn clear_led(&self, code: i32) {
+        let changed = self.leds.lock().borrow_mut().set_override(code, None);
+        if let Some(on) = changed {
+            self.apply_led(code, on);
+        }
+    }
+
+    pub fn is_led_on(&self, code: i32) -> bool {
+        return self.leds.lock().borrow().led(code);
+    }
+
+    fn apply_led(&self, code: i32, on: bool) {
+        log::debug!("LED {} -> {}", ec::get_code_name(ec::EV_LED, code), on);
+        for device in self.led_devices.lock().borrow().iter() {
+            if let Err(e) = device.set_led(code, on) {
+                eprintln!("Unable to set LED on {}: {}", device.name(), e);
+            }
+        }
+    }
+
+    /// Set the input devices to forward the LED states to, and set the current states on them.
+    fn set_led_devices(&self, devices: &[evdev::EvdevDevice]) {
+        *self.led_devices.lock().borrow_mut() = devices.to_vec();
+        let all = self.leds.lock().borrow().all();
+        for (code, on) in all {
+            self.apply_led(code, on);
+        }
+    }
+
+    /// Handle the events the host wrote to the uinput device.
+    pub(crate) fn handle_uinput_events(&self, events: &[evdev::InputEvent]) {
+        for ev in events {
+            log::debug!("Uinput event: {}", ev);
+            if let Some(uinput) = self.uinput.as_ref() {
+                if self.ff.lock().borrow_mut().handle_uinput_event(uinput, ev) {
+                    continue;
+                }
+            }
+            if ev.event_type != EventType::EV_LED {
+                continue;
+            }
+            let changed = self.leds.lock().borrow_mut().set_host_led(ev.code, ev.value != 0);
+            if let Some(on) = changed {
+                self.apply_led(ev.code, on);
+            }
+        }
+    }
+
+    /// Return the modifier keys that are pressed on the input devices, including tap-hold keys held
+    /// as modifiers.
+    fn current_modifiers(&self) -> evdev::input_event::Modifers {
+        return self
+            .modifiers
+            .build(|name, key, first| self.is_modifier_key_on(key) || (first && self.is_tap_hold_modifier_on(name)));
+    }
+
+    /// Return true if `modifiers` are pressed on the input devices, e.g. `"a"` for either Alt, `">a"`
+    /// for the right Alt, or the name of a modifier added with `KeyRemapperConfiguration::add_modifier()`.
+    /// Unlike `InputEvent::with_modifiers()`, the other modifiers don't matter.
+    pub fn is_modifier_on(&self, modifiers: &str) -> bool {
+        return ModifierSpec::parse(self.modifiers, modifiers)
+            .ignoring_others()
+            .matches(self.modifiers, self.current_modifiers());
+    }
+
     pub fn is_alt_on(&self) -> bool {
-        self.is_key_on(ec::KEY_LEFTALT) || self.is_key_on(ec::KEY_RIGHTALT)
+        self.is_modifier_on("a")
     }
 
     pub fn is_ctrl_on(&self) -> bool {
-        self.is_key_on(ec::KEY_LEFTCTRL) || self.is_key_on(ec::KEY_RIGHTCTRL)
+        self.is_modifier_on("c")
     }
 
     pub fn is_shift_on(&self) -> bool {
-        self.is_key_on(ec::KEY_LEFTSHIFT) || self.is_key_on(ec::KEY_RIGHTSHIFT)
+        self.is_modifier_on("s")
     }
 
     pub fn is_winkey_on(&self) -> bool {
-        self.is_key_on(ec::KEY_LEFTMETA) || self.is_key_on(ec::KEY_RIGHTMETA)
+        self.is_modifier_on("w")
     }
 
     pub fn is_esc_on(&self) -> bool {
-        self.is_key_on(ec::KEY_ESC)
+        self.is_modifier_on("e")
     }
 
     pub fn get_out_key_state(&self, code: i32) -> i32 {
@@ -421,6 +764,8 @@ impl KeyRemapper {
     /// - `'s'` Shift
     /// - `'w'` Meta / Windows key
     ///
+    /// The left keys are pressed, unless the modifier is prefixed with `'>'`, e.g. `">a"` for the right Alt.
+    ///
     /// If `modifiers` is `'*'`, then this will only send a key down and up events without
     /// any modifier key events.
     pub fn press_key(&self, code: i32, modifiers: &str) {
@@ -432,30 +777,835 @@ impl KeyRemapper {
             return;
         }
 
-        validate_modifiers(modifiers, "acsw");
+        validate_modifiers(modifiers, "acsw<>");
+
+        // Save the pressed modifier state and replace them with the requested ones.
+        let out_modifier_state = self.replace_out_modifiers(modifiers);
+
+        self.send_key_events(&[(code, 1), (code, 0)]);
+
+        // Restore the modifiers.
+        self.restore_out_modifier_state(out_modifier_state);
+    }
+
+    /// Type `text` by pressing keys, using the keyboard layout set with
+    /// `KeyRemapperConfiguration::set_keyboard_layout()`. Characters that the layout can't produce
+    /// are typed with the `UnicodeInput` method, or skipped if it's disabled.
+    pub fn type_text(&self, text: &str) {
+        self.ensure_uinput();
+        let _lock = self.uinput.as_ref().unwrap().lock();
+        for c in text.chars() {
+            match key_presses_for_char(&self.config.keyboard_layout, self.config.unicode_input, c) {
+                Some(presses) => {
+                    for (code, modifiers) in presses {
+                        self.press_key(code, modifiers);
+                    }
+                }
+                None => eprintln!("Unable to type {:?} with keyboard layout {}", c, self.config.keyboard_layout.name),
+            }
+        }
+    }
 
-        // Save the pressed modifier state and release them all.
+    /// Release all the modifiers and press the given ones instead. Returns the previous state.
+    fn replace_out_modifiers(&self, modifiers: &str) -> ModifierState {
         let out_modifier_state = self.save_out_modifier_state();
         self.reset_out_modifiers();
 
-        // Then press the requested modifiers, and the actual key.
-        if modifiers.contains('a') {
-            self.send_key_event(ec::KEY_LEFTALT, 1);
+        for key in ModifierSpec::parse(self.modifiers, modifiers).keys_to_press(self.modifiers) {
+            self.send_key_event(key, 1);
         }
-        if modifiers.contains('c') {
-            self.send_key_event(ec::KEY_LEFTCTRL, 1);
+        return out_modifier_state;
+    }
+
+    /// Map the key of `ev`, which must be a key down event, to `code` for as long as it's held:
+    /// the key's repeat and up events are sent as `code`'s repeat and up events. Unlike `press_key()`,
+    /// which only sends a tap, this works for keys that do something for as long as they're pressed.
+    ///
+    /// `modifiers` are the same as `press_key()`, and they're held along with `code`. The hold is
+    /// released when the modifiers of the source key change, e.g. when ESC is released first in
+    /// ESC+LEFT, so the target doesn't stay pressed with the wrong modifiers.
+    pub fn hold_key(&self, ev: &evdev::InputEvent, code: i32, modifiers: &str) {
+        if !ev.is_key_down_event() {
+            return;
         }
-        if modifiers.contains('s') {
-            self.send_key_event(ec::KEY_LEFTSHIFT, 1);
+        self.ensure_uinput();
+        let _lock = self.uinput.as_ref().unwrap().lock();
+
+        let replaces_modifiers = modifiers != "*";
+        if replaces_modifiers {
+            validate_modifiers(modifiers, "acsw<>");
         }
-        if modifiers.contains('w') {
-            self.send_key_event(ec::KEY_LEFTMETA, 1);
+
+        let conflicts = self.holds.lock().borrow_mut().take_conflicts(ev.code, replaces_modifiers);
+        for hold in conflicts {
+            self.release_hold(hold);
         }
 
-        self.send_key_events(&[(code, 1), (code, 0)]);
+        let saved_out_modifiers = if replaces_modifiers {
+            Some(self.replace_out_modifiers(modifiers))
+        } else {
+            None
+        };
+        self.send_key_event(code, 1);
+        self.send_syn_report();
 
-        // Restore the modifiers.
-        self.restore_out_modifier_state(out_modifier_state);
+        self.holds.lock().borrow_mut().insert(
+            ev.code,
+            ActiveHold {
+                code,
+                source_modifiers: ev.modifiers(),
+                saved_out_modifiers,
+            },
+        );
+    }
+
+    fn release_hold(&self, hold: ActiveHold<ModifierState>) {
+        self.send_key_event(hold.code, 0);
+        match hold.saved_out_modifiers {
+            Some(state) => self.restore_out_modifier_state(state),
+            None => self.send_syn_report(),
+        }
+    }
+
+    /// Release all the holds, e.g. when the devices are lost.
+    fn release_all_holds(&self) {
+        let holds = self.holds.lock().borrow_mut().take_all();
+        for hold in holds {
+            self.release_hold(hold);
+        }
+    }
+
+    /// Mirror the events of the keys with active holds to their targets. Returns true if the event
+    /// is consumed.
+    fn process_holds(&self, ev: &evdev::InputEvent) -> bool {
+        if !ev.is_key_event() {
+            return false;
+        }
+        let active = self.holds.lock().borrow().get(ev.code).map(|hold| hold.code);
+        if let Some(target) = active {
+            match ev.value {
+                2 => {
+                    self.send_key_event(target, 2);
+                    self.send_syn_report();
+                    return true;
+                }
+                0 => {
+                    let hold = self.holds.lock().borrow_mut().take(ev.code);
+                    if let Some(hold) = hold {
+                        self.release_hold(hold);
+                    }
+                    return true;
+                }
+                _ => {
+                    // Pressed again without being released; start over.
+                    let hold = self.holds.lock().borrow_mut().take(ev.code);
+                    if let Some(hold) = hold {
+                        self.release_hold(hold);
+                    }
+                    return false;
+                }
+            }
+        }
+
+        // Another key event, e.g. a modifier release, may have changed the modifiers.
+        if ev.value != 2 {
+            let changed = self.holds.lock().borrow_mut().take_changed(ev.modifiers());
+            for hold in changed {
+                self.release_hold(hold);
+            }
+        }
+        return false;
+    }
+
+    fn record_sent_events(&self, events: &[evdev::InputEvent]) {
+        if self.is_playing_macro() {
+            return;
+        }
+        let recorder = self.macro_recorder.lock();
+        let mut recorder = recorder.borrow_mut();
+        if let Some(recorder) = recorder.as_mut() {
+            let now = self.now();
+            for ev in events {
+                recorder.record(ev, now);
+            }
+        }
+    }
+
+    /// Start recording the events sent with `send_event()` and `send_events()` into a macro.
+    /// Events sent by macro playback aren't recorded. If it's already recording, the recording restarts.
+    pub fn start_recording_macro(&self, name: &str) {
+        log::info!("Recording macro {}", name);
+        let recorder = self.macro_recorder.lock();
+        recorder.replace(Some(MacroRecorder::new(name)));
+    }
+
+    /// Stop recording and return the macro, or `None` if it isn't recording.
+    pub fn stop_recording_macro(&self) -> Option<Macro> {
+        let recorder = self.macro_recorder.lock();
+        let ret = recorder.borrow_mut().take().map(|r| r.finish());
+        if let Some(m) = &ret {
+            log::info!("Recorded macro {} with {} events", m.name, m.events.len());
+        }
+        return ret;
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        return self.macro_recorder.lock().borrow().is_some();
+    }
+
+    /// The default `MacroStore` of this remapper.
+    pub fn macro_store(&self) -> MacroStore {
+        return MacroStore::for_app(&self.config.global_lock_name);
+    }
+
+    /// Play a macro on the I/O thread. Like `press_key()`, the modifiers are released during the playback,
+    /// and restored afterwards. If another macro is being played, it's stopped first.
+    pub fn play_macro(&self, m: &Macro, timing: MacroTiming) {
+        self.ensure_uinput();
+
+        // Hold the lock until the playback is set up, so the timers don't run before that.
+        let playback = self.macro_playback.lock();
+        self.stop_macro();
+
+        let out_modifier_state = self.save_out_modifier_state();
+        self.reset_out_modifiers();
+
+        let timeline = m.timeline(timing);
+        let end = timeline.last().map(|(offset, _)| *offset).unwrap_or_default();
+        let mut handles = vec![];
+        for (offset, ev) in timeline {
+            handles.push(self.schedule_after(offset, move |km| km.send_macro_event(&ev)));
+        }
+        handles.push(self.schedule_after(end, |km| km.finish_macro()));
+
+        playback.replace(Some(MacroPlayback {
+            handles,
+            out_modifier_state,
+            pressed: HashSet::new(),
+        }));
+    }
+
+    /// Load a macro from `macro_store()` and play it.
+    pub fn play_saved_macro(&self, name: &str, timing: MacroTiming) {
+        match self.macro_store().load(name) {
+            Ok(m) => self.play_macro(&m, timing),
+            Err(e) => eprintln!("Unable to play macro {}: {:#}", name, e),
+        }
+    }
+
+    fn send_macro_event(&self, ev: &evdev::InputEvent) {
+        {
+            let playback = self.macro_playback.lock();
+            let mut playback = playback.borrow_mut();
+            let playback = match playback.as_mut() {
+                Some(playback) => playback,
+                None => return,
+            };
+            if ev.is_key_down_event() {
+                playback.pressed.insert(ev.code);
+            } else if ev.is_key_up_event() {
+                playback.pressed.remove(&ev.code);
+            }
+        }
+        self.send_event(ev);
+    }
+
+    fn finish_macro(&self) {
+        let playback = self.macro_playback.lock().borrow_mut().take();
+        if let Some(playback) = playback {
+            self.restore_out_modifier_state(playback.out_modifier_state);
+        }
+    }
+
+    /// Stop the macro being played, if any. The keys it has pressed are released.
+    pub fn stop_macro(&self) {
+        let playback = self.macro_playback.lock().borrow_mut().take();
+        if let Some(playback) = playback {
+            for handle in &playback.handles {
+                handle.cancel();
+            }
+            for code in playback.pressed {
+                self.send_key_event(code, 0);
+            }
+            self.restore_out_modifier_state(playback.out_modifier_state);
+        }
+    }
+
+    pub fn is_playing_macro(&self) -> bool {
+        return self.macro_playback.lock().borrow().is_some();
+    }
+
+    /// Call `callback` on the I/O thread after `delay`. Like `on_event`, the callback is called
+    /// while the I/O thread holds its locks, so it doesn't race with the other callbacks.
+    /// It can be called from any thread. Use the returned `TimerHandle` to cancel it.
+    pub fn schedule_after<F>(&self, delay: Duration, callback: F) -> TimerHandle
+    where
+        F: Fn(&KeyRemapper) + Send + Sync + 'static,
+    {
+        return self.schedule(delay, None, Arc::new(callback));
+    }
+
+    /// Call `callback` on the I/O thread every `interval`, until it's cancelled with the returned
+    /// `TimerHandle`. The first call happens after `interval`.
+    pub fn schedule_periodic<F>(&self, interval: Duration, callback: F) -> TimerHandle
+    where
+        F: Fn(&KeyRemapper) + Send + Sync + 'static,
+    {
+        if interval == Duration::from_secs(0) {
+            panic!("interval must not be zero");
+        }
+        return self.schedule(interval, Some(interval), Arc::new(callback));
+    }
+
+    fn schedule(&self, delay: Duration, period: Option<Duration>, callback: TimerCallback) -> TimerHandle {
+        let handle = {
+            let timers = self.timers.lock();
+            let handle = timers.borrow_mut().schedule(self.now(), delay, period, callback);
+            handle
+        };
+        // The I/O thread may be waiting with a later deadline (or none at all), so wake it up.
+        self.waker.wake();
+        return handle;
+    }
+
+    pub(crate) fn next_timer_deadline(&self) -> Option<Instant> {
+        let timers = self.timers.lock();
+        let deadline = timers.borrow_mut().next_deadline();
+        deadline
+    }
+
+    /// Run all the due timers. Must be called on the I/O thread.
+    pub(crate) fn run_due_timers(&self) {
+        let due = {
+            let timers = self.timers.lock();
+            let due = timers.borrow_mut().pop_due(self.now());
+            due
+        };
+        for (handle, callback) in due {
+            // An earlier callback may have cancelled it.
+            if !handle.is_cancelled() {
+                (*callback)(self);
+            }
+        }
+    }
+
+    /// Find the input devices, and call `on_devices_detected` or `on_devices_not_found`.
+    /// Must be called on the I/O thread.
+    pub(crate) fn detect_devices(&self) {
+        let devices = {
+            let input = self.input.lock();
+            let mut input = input.borrow_mut();
+            input.refresh_devices().expect("Unable to detect input devices");
+            input.devices.clone()
+        };
+        let callbacks = self.config.callbacks_cloned();
+
+        if devices.len() == 0 {
+            log::info!("No device found");
+            self.show_notification("No device found");
+            (*callbacks.on_devices_not_found)(self);
+        } else {
+            let mut message = "Device(s) detected".to_string();
+            log::info!("{}", message);
+
+            for device in &devices {
+                message.push_str("\n - ");
+                message.push_str(&device.name());
+            }
+            self.show_notification(&message);
+
+            (*callbacks.on_devices_detected)(self, &devices);
+        }
+        self.set_led_devices(&devices);
+        self.update_uinput_ff_codes(&devices);
+        self.ff.lock().borrow_mut().set_devices(&devices);
+    }
+
+    /// Recreate the main uinput device if `devices` have force-feedback codes it doesn't support yet,
+    /// so that the programs can upload the effects to it, which are forwarded to `devices`.
+    fn update_uinput_ff_codes(&self, devices: &[evdev::EvdevDevice]) {
+        let uinput = match self.uinput.as_ref() {
+            Some(uinput) => uinput,
+            None => return,
+        };
+        let codes = match self.ff.lock().borrow().uinput_codes_with(devices) {
+            Some(codes) => codes,
+            None => return,
+        };
+        log::info!("Recreating the uinput device with the FF codes {:?}", codes);
+
+        let mut events = self.config.uinput_events.clone();
+        events.events.insert(EventType::EV_FF, codes.clone());
+        let identity = self.config.uinput_identity.as_ref();
+        match create_uinput(&self.config, &self.uinput_factory, "", &events, identity) {
+            Ok(new_uinput) => {
+                let _ = uinput.reset();
+                uinput.swap_sink(&new_uinput);
+                self.ff.lock().borrow_mut().set_uinput_codes(&codes);
+            }
+            Err(e) => eprintln!("Unable to recreate the uinput device: {}", e),
+        }
+    }
+
+    /// Call `on_devices_lost`, reset all the states, release the pressed keys and close the input devices.
+    /// Must be called on the I/O thread.
+    pub(crate) fn handle_devices_lost(&self) {
+        let msg = "Devices connected or disconnected";
+        log::info!("{}", msg);
+        self.show_notification(msg);
+        let callbacks = self.config.callbacks_cloned();
+        (*callbacks.on_devices_lost)(self);
+
+        self.reset_all();
+        self.release_devices();
+    }
+
+    /// Reset the states of the tap-hold keys, chords, leader sequences, layer keys, gestures and holds,
+    /// and release all the keys pressed on the uinput devices, e.g. to recover from stuck keys.
+    /// Must be called on the I/O thread.
+    pub fn reset_all(&self) {
+        self.reset_tap_hold();
+        self.chords.lock().borrow_mut().reset();
+        self.leader.lock().borrow_mut().reset();
+        self.update_layers(|layers| layers.reset_keys());
+        self.gestures.lock().borrow_mut().reset();
+        self.gamepad.lock().borrow_mut().reset();
+        self.release_all_holds();
+        self.reset_out(); // Release all the pressed buttons.
+    }
+
+    /// Pause or resume the remapping. While paused, the input events are sent to the main uinput
+    /// device as-is, without calling `on_event` and the other callbacks. Pausing resets the states
+    /// with `reset_all()` first. Must be called on the I/O thread.
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.load(Ordering::SeqCst) == paused {
+            return;
+        }
+        if paused {
+            self.reset_all();
+        }
+        self.paused.store(paused, Ordering::SeqCst);
+
+        let message = if paused { "Remapping paused" } else { "Remapping resumed" };
+        log::info!("{}", message);
+        self.show_notification(message);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.paused.load(Ordering::SeqCst);
+    }
+
+    /// Close all the input devices, including the copies for the LEDs and force-feedback, so they're ungrabbed.
+    fn release_devices(&self) {
+        self.set_led_devices(&[]);
+        self.ff.lock().borrow_mut().set_devices(&[]);
+        self.input.lock().borrow_mut().release_devices();
+        self.input_event_trackers.lock().borrow_mut().clear();
+    }
+
+    /// Input devices in use.
+    pub fn devices(&self) -> Vec<evdev::EvdevDevice> {
+        return self.led_devices.lock().borrow().clone();
+    }
+
+    /// Make `main_loop()` release the input devices and return. It can be called from any thread.
+    pub(crate) fn stop_main_loop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    /// Pass a batch of events read from `device` to `on_events_batch`, the input tracker and `process_input_event()`.
+    /// Must be called on the I/O thread.
+    pub(crate) fn process_input_events(&self, device: &evdev::EvdevDevice, events: &[evdev::InputEvent]) {
+        for ev in events {
+            log::debug!("Input event: {}", ev);
+        }
+
+        let paused = self.is_paused();
+        let callbacks = self.config.callbacks_cloned();
+        if !paused {
+            (*callbacks.on_events_batch)(self, device, events);
+        }
+        for ev in events {
+            {
+                // Update input tracker
+                let lock = self.input_event_trackers.lock();
+                let mut tracker = lock.borrow_mut();
+                tracker.on_event(&device.id(), ev);
+
+                // Check for emergency como
+                let mut emergency = true;
+                for key in EMERGENCY_COMBO {
+                    if tracker.key_state(*key) == 0 {
+                        emergency = false;
+                        break;
+                    }
+                }
+                if emergency {
+                    eprintln!("Emergency stop!");
+                    process_clean_up(self, false);
+                    std::process::exit(9);
+                }
+            }
+            if !paused {
+                self.process_input_event(device, ev);
+            } else if self.uinput.is_some() {
+                self.send_event(ev);
+            }
+        }
+    }
+
+    /// Pass an input event to the gestures, the gamepad mapping and the tap-hold keys, and then to `on_event`.
+    /// Must be called on the I/O thread.
+    fn process_input_event(&self, device: &evdev::EvdevDevice, ev: &evdev::InputEvent) {
+        self.process_gesture_event(device, ev);
+        if self.process_gamepad_event(device, ev) {
+            return;
+        }
+
+        let (outputs, tapping_term) = {
+            let tap_hold = self.tap_hold.lock();
+            let mut tap_hold = tap_hold.borrow_mut();
+            let outputs = tap_hold.on_event(ev, device.clone(), self.now());
+            let tapping_term = match tap_hold.find_key(ev.code) {
+                Some(index) if ev.is_key_down_event() && tap_hold.next_deadline().is_some() => Some(tap_hold.key(index).tapping_term),
+                _ => None,
+            };
+            (outputs, tapping_term)
+        };
+        if let Some(tapping_term) = tapping_term {
+            // Decide it as a hold when the tapping term passes, even if no other events arrive.
+            self.schedule_after(tapping_term, |km| {
+                let outputs = km.tap_hold.lock().borrow_mut().on_timeout(km.now());
+                km.apply_tap_hold_outputs(outputs);
+            });
+        }
+        self.apply_tap_hold_outputs(outputs);
+    }
+
+    /// Run the actions of the gestures detected with the event. The event itself is passed on as-is.
+    fn process_gesture_event(&self, device: &evdev::EvdevDevice, ev: &evdev::InputEvent) {
+        let detected = {
+            let gestures = self.gestures.lock();
+            let mut gestures = gestures.borrow_mut();
+            if !gestures.is_enabled() {
+                return;
+            }
+            gestures.on_event(&device.id(), || device.supported_events(), ev, self.now())
+        };
+        for index in detected {
+            let action = self.gestures.lock().borrow().binding(index).action.clone();
+            match action {
+                GestureAction::Press { code, modifiers } => self.press_key(code, &modifiers),
+                GestureAction::Callback(callback) => (*callback)(self),
+            }
+        }
+    }
+
+    /// Send the keys mapped to a gamepad event, and start moving with the sticks when one is tilted.
+    /// Returns false if the event isn't mapped.
+    fn process_gamepad_event(&self, device: &evdev::EvdevDevice, ev: &evdev::InputEvent) -> bool {
+        let (keys, start_ticking) = {
+            let gamepad = self.gamepad.lock();
+            let mut gamepad = gamepad.borrow_mut();
+            if gamepad.mapping().is_none() {
+                return false;
+            }
+            let keys = match gamepad.on_event(&device.id(), || device.supported_events(), ev) {
+                Some(keys) => keys,
+                None => return false,
+            };
+            (keys, self.gamepad_mouse.is_some() && gamepad.start_ticking(self.now()))
+        };
+        if start_ticking {
+            self.schedule_gamepad_tick();
+        }
+        self.send_gamepad_keys(&keys);
+        return true;
+    }
+
+    /// Send the key events of the gamepad triggers, hats and buttons.
+    fn send_gamepad_keys(&self, keys: &[(i32, i32)]) {
+        for &(code, value) in keys {
+            // Mouse buttons go to the same device as the pointer movements.
+            match self.gamepad_mouse.as_ref() {
+                Some(mouse) if (ec::BTN_MOUSE..=ec::BTN_TASK).contains(&code) => {
+                    mouse.send_event(&evdev::InputEvent::new_key_event(code, value)).expect("send_event failed")
+                }
+                _ => self.send_key_event(code, value),
+            }
+        }
+    }
+
+    fn schedule_gamepad_tick(&self) {
+        let interval = self.config.gamepad.as_ref().unwrap().interval;
+        self.schedule_after(interval, |km| km.move_gamepad_sticks());
+    }
+
+    /// Called every interval while a stick is tilted to move the pointer and the wheel.
+    fn move_gamepad_sticks(&self) {
+        let (movements, is_ticking) = {
+            let gamepad = self.gamepad.lock();
+            let mut gamepad = gamepad.borrow_mut();
+            (gamepad.tick(self.now()), gamepad.is_ticking())
+        };
+        if is_ticking {
+            self.schedule_gamepad_tick();
+        }
+        if movements.is_empty() {
+            return;
+        }
+        let events: Vec<evdev::InputEvent> = movements
+            .iter()
+            .map(|(code, value)| evdev::InputEvent::new(EventType::EV_REL, *code, *value))
+            .collect();
+        if let Some(mouse) = self.gamepad_mouse.as_ref() {
+            mouse.send_events(&events).expect("send_events failed");
+        }
+    }
+
+    fn apply_tap_hold_outputs(&self, outputs: Vec<TapHoldOutput<evdev::EvdevDevice>>) {
+        for output in outputs {
+            match output {
+                TapHoldOutput::Event(ev, device) => self.process_chord_event(&device, ev),
+                TapHoldOutput::Tap(index) => {
+                    let tap_code = self.tap_hold.lock().borrow().key(index).tap_code;
+                    self.press_key(tap_code, "*");
+                }
+                TapHoldOutput::HoldStart(index) => self.set_tap_hold_key_held(index, true),
+                TapHoldOutput::HoldEnd(index) => self.set_tap_hold_key_held(index, false),
+            }
+        }
+    }
+
+    fn set_tap_hold_key_held(&self, index: usize, held: bool) {
+        let hold = self.tap_hold.lock().borrow().key(index).hold;
+        match hold {
+            HoldAction::Key(code) => self.send_key_event(code, if held { 1 } else { 0 }),
+            HoldAction::Modifier(_) => {} // TapHoldState tracks it.
+        }
+    }
+
+    /// Release the tap-hold keys being held, and drop the undecided ones.
+    fn reset_tap_hold(&self) {
+        let outputs = self.tap_hold.lock().borrow_mut().reset();
+        self.apply_tap_hold_outputs(outputs);
+    }
+
+    /// Pass an event to the chords, and then to the layers.
+    fn process_chord_event(&self, device: &evdev::EvdevDevice, ev: evdev::InputEvent) {
+        let now = self.now();
+        let (outputs, new_deadline) = {
+            let chords = self.chords.lock();
+            let mut chords = chords.borrow_mut();
+            let old_deadline = chords.next_deadline();
+            let outputs = chords.on_event(&ev, device.clone(), now);
+            let new_deadline = chords.next_deadline();
+            (outputs, if new_deadline != old_deadline { new_deadline } else { None })
+        };
+        if let Some(deadline) = new_deadline {
+            // Give up the chord when the window passes, even if no other events arrive.
+            self.schedule_after(deadline.saturating_duration_since(now), |km| {
+                let outputs = km.chords.lock().borrow_mut().on_timeout(km.now());
+                km.apply_chord_outputs(outputs);
+            });
+        }
+        self.apply_chord_outputs(outputs);
+    }
+
+    fn apply_chord_outputs(&self, outputs: Vec<ChordOutput<evdev::EvdevDevice>>) {
+        for output in outputs {
+            match output {
+                ChordOutput::Event(ev, device) => self.process_leader_event(&device, ev),
+                ChordOutput::Chord(index) => {
+                    let action = self.chords.lock().borrow().chord(index).action.clone();
+                    match action {
+                        ChordAction::Press { code, modifiers } => self.press_key(code, &modifiers),
+                        ChordAction::Callback(callback) => (*callback)(self),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pass an event to the leader sequences, and then to the layers.
+    fn process_leader_event(&self, device: &evdev::EvdevDevice, ev: evdev::InputEvent) {
+        let now = self.now();
+        let (outputs, new_deadline) = {
+            let leader = self.leader.lock();
+            let mut leader = leader.borrow_mut();
+            let old_deadline = leader.next_deadline();
+            let outputs = leader.on_event(&ev, device.clone(), now);
+            let new_deadline = leader.next_deadline();
+            (outputs, if new_deadline != old_deadline { new_deadline } else { None })
+        };
+        if let Some(deadline) = new_deadline {
+            // Finish the sequence when the timeout passes, even if no other events arrive.
+            self.schedule_after(deadline.saturating_duration_since(now), |km| {
+                let outputs = km.leader.lock().borrow_mut().on_timeout(km.now());
+                km.apply_leader_outputs(outputs);
+            });
+        }
+        self.apply_leader_outputs(outputs);
+    }
+
+    fn apply_leader_outputs(&self, outputs: Vec<LeaderOutput<evdev::EvdevDevice>>) {
+        for output in outputs {
+            match output {
+                LeaderOutput::Event(ev, device) => self.deliver_event(&device, ev),
+                LeaderOutput::Replay(events) => {
+                    if !events.is_empty() {
+                        self.send_events(&events);
+                    }
+                }
+                LeaderOutput::Hint(message, timeout) => self.show_notification_with_timeout(&message, timeout),
+                LeaderOutput::ClearHint => self.close_notification(),
+                LeaderOutput::Sequence(index) => {
+                    let callback = self.leader.lock().borrow().sequence(index).callback.clone();
+                    (*callback)(self);
+                }
+            }
+        }
+    }
+
+    /// Set the modifiers to an event and pass it to the layers, and then to `on_event`.
+    fn deliver_event(&self, device: &evdev::EvdevDevice, mut ev: evdev::InputEvent) {
+        ev.set_modifiers(self.modifiers, self.current_modifiers());
+
+        if self.process_holds(&ev) {
+            return;
+        }
+
+        if ev.is_key_event() {
+            let momentary = self.layers.lock().borrow().find_momentary(ev.code);
+            if let Some(index) = momentary {
+                match ev.value {
+                    0 => self.update_layers(|layers| layers.remove(index)),
+                    1 => self.update_layers(|layers| layers.push(index)),
+                    _ => {}
+                }
+                return;
+            }
+        }
+
+        let route = self.layers.lock().borrow().route(&ev);
+        for target in route {
+            let handled = match target {
+                Some(index) => {
+                    let handler = self.layers.lock().borrow().layer(index).on_event.clone();
+                    match handler {
+                        Some(handler) => (*handler)(self, device, &ev),
+                        None => false,
+                    }
+                }
+                None => {
+                    let callbacks = self.config.callbacks_cloned();
+                    (*callbacks.on_event)(self, device, &ev);
+                    true
+                }
+            };
+            if handled {
+                self.layers.lock().borrow_mut().set_owner(&ev, target);
+                return;
+            }
+        }
+    }
+
+    /// Change the layer stack, and update the tray icon and show the notification if the top layer changes.
+    fn update_layers<F: FnOnce(&mut LayerStack)>(&self, f: F) {
+        let (old_top, new_top) = {
+            let layers = self.layers.lock();
+            let mut layers = layers.borrow_mut();
+            let old_top = layers.top();
+            f(&mut *layers);
+            (old_top, layers.top())
+        };
+        if old_top != new_top {
+            self.on_top_layer_changed(new_top);
+        }
+    }
+
+    fn on_top_layer_changed(&self, top: LayerTarget) {
+        let (icon, notification) = match top {
+            Some(index) => {
+                let layers = self.layers.lock();
+                let layers = layers.borrow();
+                let layer = layers.layer(index);
+                log::info!("Top layer: {}", layer.name);
+                (layer.icon.clone().or_else(|| self.config.icon.clone()), layer.notification.clone())
+            }
+            None => {
+                log::info!("Top layer: base");
+                (self.config.icon.clone(), None)
+            }
+        };
+        if let Some(icon) = icon {
+            self.set_icon(icon);
+        }
+        if let Some((message, timeout)) = notification {
+            self.show_notification_with_timeout(&message, timeout);
+        }
+    }
+
+    /// Activate a layer added with `KeyRemapperConfiguration::add_layer()`. If it's already active,
+    /// it's moved to the top. Panics if there's no such layer.
+    pub fn push_layer(&self, name: &str) {
+        let index = self.layers.lock().borrow().find(name);
+        self.update_layers(|layers| layers.push(index));
+    }
+
+    /// Deactivate the top layer, and return its name.
+    pub fn pop_layer(&self) -> Option<String> {
+        let mut ret = None;
+        self.update_layers(|layers| ret = layers.pop().map(|index| layers.layer(index).name.clone()));
+        return ret;
+    }
+
+    /// Deactivate a layer, wherever it is in the stack.
+    pub fn remove_layer(&self, name: &str) {
+        let index = self.layers.lock().borrow().find(name);
+        self.update_layers(|layers| layers.remove(index));
+    }
+
+    /// Activate a layer if it's inactive, or deactivate it otherwise.
+    pub fn toggle_layer(&self, name: &str) {
+        if self.is_layer_active(name) {
+            self.remove_layer(name);
+        } else {
+            self.push_layer(name);
+        }
+    }
+
+    /// Deactivate all the layers, and activate only the given one.
+    pub fn switch_layer(&self, name: &str) {
+        let index = self.layers.lock().borrow().find(name);
+        self.update_layers(|layers| {
+            layers.clear();
+            layers.push(index);
+        });
+    }
+
+    /// Deactivate all the layers, leaving only the base layer.
+    pub fn clear_layers(&self) {
+        self.update_layers(|layers| layers.clear());
+    }
+
+    pub fn is_layer_active(&self, name: &str) -> bool {
+        let layers = self.layers.lock();
+        let layers = layers.borrow();
+        return layers.is_active(layers.find(name));
+    }
+
+    /// Return the names of the active layers, from the bottom to the top.
+    pub fn active_layers(&self) -> Vec<String> {
+        return self.layers.lock().borrow().active_names();
+    }
+
+    /// Return the name of the top layer, or `None` if only the base layer is active.
+    pub fn top_layer(&self) -> Option<String> {
+        let layers = self.layers.lock();
+        let layers = layers.borrow();
+        return layers.top().map(|index| layers.layer(index).name.clone());
     }
 
     // TODO Support adding menu items.
@@ -491,8 +1641,8 @@ fn test_validate_modifiers_fail_2() {
     validate_modifiers("sca", "sa");
 }
 
-/// Main loop, which runs on the I/O thread.
-fn main_loop(key_remapper: &KeyRemapper) {
+/// Main loop, which runs on the I/O thread until `KeyRemapper::stop_main_loop()` is called.
+pub(crate) fn main_loop(key_remapper: &KeyRemapper) {
     let config = &key_remapper.config;
     let callbacks = config.callbacks_cloned();
 
@@ -507,37 +1657,61 @@ fn main_loop(key_remapper: &KeyRemapper) {
 
     'with_device_detection: loop {
         // First, find the target input devices.
-        let input_lock = key_remapper.input.lock();
-        let mut input = input_lock.borrow_mut();
-        input.refresh_devices().expect("Unable to detect input devices");
+        key_remapper.detect_devices();
 
-        if input.devices.len() == 0 {
-            log::info!("No device found");
-            key_remapper.show_notification("No device found");
-            (*callbacks.on_devices_not_found)(&key_remapper);
-        } else {
-            let mut message = "Device(s) detected".to_string();
-            log::info!("{}", message);
-
-            for device in &input.devices {
-                message.push_str("\n - ");
-                message.push_str(&device.name());
-            }
-            key_remapper.show_notification(&message);
-
-            (*callbacks.on_devices_detected)(&key_remapper, &input.devices);
-        }
+        let input_lock = key_remapper.input.lock();
+        let input = input_lock.borrow();
 
         // Create a list of FDs to select from.
-        let mut fds = vec![udev_fd];
+        let waker_fd = key_remapper.waker.fd();
+        let mut fds = vec![udev_fd, waker_fd];
 
         for device in &input.devices {
             fds.push(device.device_fd());
         }
 
+        // The host writes LED states and force-feedback requests to the uinput device.
+        let uinput_fd = key_remapper.uinput.as_ref().and_then(|u| u.sink_fd());
+        if let Some(fd) = uinput_fd {
+            fds.push(fd);
+        }
+
         // Actual event loop.
         'event_loop: loop {
-            let ready_fd = select::select(&fds).expect("pselect() failed");
+            let timeout = key_remapper
+                .next_timer_deadline()
+                .map(|deadline| deadline.saturating_duration_since(key_remapper.now()));
+            let ready_fd = select::select(&fds, timeout).expect("pselect() failed");
+
+            if key_remapper.stopping.load(Ordering::SeqCst) {
+                log::info!("Stopping the I/O loop");
+                drop(input);
+                drop(input_lock);
+                key_remapper.reset_out();
+                key_remapper.release_devices();
+                return;
+            }
+
+            key_remapper.run_due_timers();
+
+            let ready_fd = match ready_fd {
+                Some(fd) => fd,
+                None => continue 'event_loop, // Timed out.
+            };
+
+            // The waker only interrupts select(), so that the new timeout takes effect.
+            if ready_fd == waker_fd {
+                key_remapper.waker.drain();
+                continue 'event_loop;
+            }
+
+            if Some(ready_fd) == uinput_fd {
+                match key_remapper.uinput.as_ref().unwrap().read_events() {
+                    Ok(events) => key_remapper.handle_uinput_events(&events),
+                    Err(e) => eprintln!("Unable to read uinput events: {}", e),
+                }
+                continue 'event_loop;
+            }
 
             // Handle udev events
             if ready_fd == udev_fd {
@@ -550,13 +1724,10 @@ fn main_loop(key_remapper: &KeyRemapper) {
                     }
                 }
 
-                let msg = "Devices connected or disconnected";
-                log::info!("{}", msg);
-                key_remapper.show_notification(msg);
-                (*callbacks.on_devices_lost)(&key_remapper);
-
-                key_remapper.reset_out(); // Release all the pressed buttons.
-                input.release_devices(); // Close all the input devices.
+                // Release the borrow, as it closes the input devices.
+                drop(input);
+                drop(input_lock);
+                key_remapper.handle_devices_lost();
 
                 thread::sleep(Duration::from_millis(rng.gen_range(1000..2000)));
 
@@ -568,51 +1739,20 @@ fn main_loop(key_remapper: &KeyRemapper) {
             // Handle input events.
 
             let device = input.find_device_by_fd(ready_fd);
-            let mut events = match device.next_events() {
+            let events = match device.next_events() {
                 Ok(event) => event,
                 Err(_) => {
                     eprintln!("Unable to read event; device closed?");
+                    key_remapper.input_event_trackers.lock().borrow_mut().remove_device(&device.id());
+                    key_remapper.gestures.lock().borrow_mut().remove_device(&device.id());
+                    let releases = key_remapper.gamepad.lock().borrow_mut().remove_device(&device.id());
+                    key_remapper.send_gamepad_keys(&releases);
                     thread::sleep(Duration::from_millis(50));
                     continue;
                 }
             };
 
-            for ev in &events {
-                log::debug!("Input event: {}", ev);
-            }
-
-            (*callbacks.on_events_batch)(&key_remapper, &device, &events);
-            for ev in &mut events {
-                {
-                    // Update input tracker
-                    let lock = key_remapper.input_event_tracker.lock();
-                    let tracker = lock.borrow_mut();
-                    tracker.on_event_sent(ev);
-
-                    // Check for emergency como
-                    let mut emergency = true;
-                    for key in EMERGENCY_COMBO {
-                        if tracker.key_state(*key) == 0 {
-                            emergency = false;
-                            break;
-                        }
-                        // println!("XXX {} pressed", key);
-                    }
-                    if emergency {
-                        eprintln!("Emergency stop!");
-                        process_clean_up(key_remapper, false);
-                        std::process::exit(9);
-                    }
-                }
-                ev.set_modifiers(
-                    key_remapper.is_alt_on(),
-                    key_remapper.is_ctrl_on(),
-                    key_remapper.is_shift_on(),
-                    key_remapper.is_winkey_on(),
-                    key_remapper.is_esc_on(),
-                );
-                (*callbacks.on_event)(&key_remapper, &device, ev);
-            }
+            key_remapper.process_input_events(&device, &events);
         }
     }
 }
@@ -717,6 +1857,18 @@ pub fn start(mut config: KeyRemapperConfiguration) {
         setup_signal_handler(key_remapper.clone());
     }
 
+    let control_server = if config.use_control_socket {
+        match ControlServer::start(&key_remapper, &control::socket_path(&name)) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Unable to start the control socket: {:#}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Keep a clone so we can reset the output uinput devices at the end.
     let key_remapper_clone = key_remapper.clone();
 
@@ -732,6 +1884,9 @@ pub fn start(mut config: KeyRemapperConfiguration) {
     gtk::main();
     process_clean_up(&key_remapper_clone, true);
 
+    // Remove the socket file before restarting, which doesn't run the destructors.
+    drop(control_server);
+
     if DO_RESTART_PROCESS.load(Ordering::SeqCst) {
         restart_process();
     }
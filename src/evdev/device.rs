@@ -1,5 +1,13 @@
 use std::os::unix::io::AsRawFd;
-use std::{collections::HashMap, fs::File, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc, Mutex,
+    },
+};
 
 use crate::native::{self, string_from_c_str};
 
@@ -31,10 +39,24 @@ impl EvdevAbsInfo {
     }
 }
 
+/// Identity of an input device, i.e. its `struct input_id` and physical location. Compositors and
+/// libinput use it to apply per-device settings, so uinput devices can mirror it from the source device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub bustype: i32,
+    pub vendor: i32,
+    pub product: i32,
+    pub version: i32,
+    pub phys: Option<String>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct EventsDescriptor {
     pub events: HashMap<EventType, Vec<i32>>,
     pub abs_info: HashMap<i32, EvdevAbsInfo>,
+
+    /// Input properties, e.g. `INPUT_PROP_POINTER`, sorted.
+    pub properties: Vec<i32>,
 }
 
 impl EventsDescriptor {
@@ -42,6 +64,7 @@ impl EventsDescriptor {
         EventsDescriptor {
             events: HashMap::new(),
             abs_info: HashMap::new(),
+            properties: vec![],
         }
     }
 
@@ -87,6 +110,26 @@ impl EventsDescriptor {
         }
     }
 
+    fn add_properties(&mut self, device: *mut native::libevdev) {
+        for prop in 0..ec::INPUT_PROP_MAX + 1 {
+            if unsafe { native::libevdev_has_property(device, prop as u32) } == 1 {
+                self.add_property(prop);
+            }
+        }
+    }
+
+    /// Add an input property, e.g. `INPUT_PROP_BUTTONPAD`.
+    pub fn add_property(&mut self, prop: i32) {
+        if !self.properties.contains(&prop) {
+            self.properties.push(prop);
+            self.properties.sort_unstable();
+        }
+    }
+
+    pub fn has_property(&self, prop: i32) -> bool {
+        return self.properties.contains(&prop);
+    }
+
     fn from_native_input_device(device: *mut native::libevdev) -> EventsDescriptor {
         let mut ret = EventsDescriptor::new();
 
@@ -98,13 +141,14 @@ impl EventsDescriptor {
         ret.add_codes(device, ec::EventType::EV_LED, ec::LED_MAX);
         ret.add_codes(device, ec::EventType::EV_SND, ec::SND_MAX);
         ret.add_codes(device, ec::EventType::EV_REP, ec::REP_MAX);
-        // ret.add_codes(device, ec::EventType::EV_FF, ec::FF_MAX);
+        ret.add_codes(device, ec::EventType::EV_FF, ec::FF_MAX);
         // ret.add_codes(device, ec::EventType::EV_PWR, ec::PWR_MAX);
         // ret.add_codes(device, ec::EventType::EV_FF_STATUS, ec::FF_STATUS_MAX);
 
         for code in 0..ec::ABS_MAX + 1 {
             ret.add_absinfo(device, code)
         }
+        ret.add_properties(device);
         return ret;
     }
 
@@ -123,6 +167,14 @@ impl EventsDescriptor {
         self.events.insert(ec::EventType::EV_KEY, all_keys);
     }
 
+    /// Add the common keyboard LEDs, so the host sets them on a uinput device.
+    pub fn add_keyboard_led_events(&mut self) {
+        self.events.insert(
+            ec::EventType::EV_LED,
+            vec![ec::LED_NUML, ec::LED_CAPSL, ec::LED_SCROLLL, ec::LED_COMPOSE, ec::LED_KANA],
+        );
+    }
+
     pub fn with_mouse_events() -> EventsDescriptor {
         let mut ret = EventsDescriptor::new();
         ret.add_mouse_events();
@@ -174,26 +226,52 @@ unsafe impl Sync for EvdevPtr {}
 pub struct EvdevDevice {
     path: String,
     device: EvdevPtr,
-    file: Arc<File>,
+
+    /// `None` for a virtual device. See `new_virtual()`.
+    file: Option<Arc<File>>,
     fd: std::os::unix::io::RawFd,
     name: String,
+    bustype: i32,
     vendor: i32,
     product: i32,
+    version: i32,
     id_str: String,
 
-    // physical_location: String,
+    phys: Option<String>,
     // uniq_id: String,
     events: EventsDescriptor,
 
     grabbed: bool,
+
+    /// What a virtual device has received, which the tests check. `None` for a real device.
+    virtual_state: Option<Arc<Mutex<VirtualDeviceState>>>,
 }
 
+/// Events written to a virtual device and the force-feedback effects uploaded to it.
+#[derive(Debug, Default)]
+struct VirtualDeviceState {
+    written: Vec<InputEvent>,
+
+    /// Types of the uploaded effects by their IDs.
+    ff_effects: HashMap<i16, u16>,
+    next_ff_id: i16,
+}
+
+nix::ioctl_write_ptr!(eviocsff, b'E', 0x80, native::ff_effect);
+nix::ioctl_write_int!(eviocrmff, b'E', 0x81);
+
+static NEXT_VIRTUAL_DEVICE_ID: AtomicUsize = AtomicUsize::new(0);
+
 impl EvdevDevice {
     pub fn with_path(path: &Path) -> Result<EvdevDevice, EvdevError> {
         log::debug!("Opening evdev device {:?}...", path);
 
         unsafe {
-            let file = File::open(&path)?;
+            // Open it for writing too if possible, which is needed to play force-feedback effects.
+            let file = match OpenOptions::new().read(true).write(true).open(&path) {
+                Ok(file) => file,
+                Err(_) => File::open(&path)?,
+            };
             let fd = file.as_raw_fd();
 
             let device = native::libevdev_new();
@@ -203,24 +281,56 @@ impl EvdevDevice {
 
             let vendor = native::libevdev_get_id_vendor(device);
             let product = native::libevdev_get_id_product(device);
+            let phys = native::libevdev_get_phys(device);
 
             return Ok(EvdevDevice {
                 path: String::from(path.to_str().unwrap()),
                 device: EvdevPtr { ptr: device },
-                file: Arc::new(file),
+                file: Some(Arc::new(file)),
                 fd: fd,
                 name: string_from_c_str(native::libevdev_get_name(device)),
-                // physical_location: String::from(CStr::from_ptr(native::libevdev_get_phys(device)).to_str()?),
+                phys: if phys.is_null() { None } else { Some(string_from_c_str(phys)) },
                 // uniq_id: String::from(CStr::from_ptr(native::libevdev_get_uniq(device)).to_str()?),
+                bustype: native::libevdev_get_id_bustype(device),
                 vendor: vendor,
                 product: product,
+                version: native::libevdev_get_id_version(device),
                 id_str: format!("v{:04x} p{:04x}", vendor, product),
                 events: events,
                 grabbed: false,
+                virtual_state: None,
             });
         }
     }
 
+    /// Create a device that isn't backed by a real evdev device, e.g. for the tests. It never has
+    /// events to read, grabbing it always succeeds, and the LEDs are ignored. The events written to it
+    /// and the force-feedback effects are recorded; see `written_events()`. Its path is `virtual:N`,
+    /// which is unique within the process.
+    pub fn new_virtual(name: &str, identity: &DeviceIdentity, events: &EventsDescriptor) -> EvdevDevice {
+        let id = NEXT_VIRTUAL_DEVICE_ID.fetch_add(1, atomic::Ordering::SeqCst);
+        return EvdevDevice {
+            path: format!("virtual:{}", id),
+            device: EvdevPtr { ptr: std::ptr::null_mut() },
+            file: None,
+            fd: -1,
+            name: name.to_string(),
+            phys: identity.phys.clone(),
+            bustype: identity.bustype,
+            vendor: identity.vendor,
+            product: identity.product,
+            version: identity.version,
+            id_str: format!("v{:04x} p{:04x}", identity.vendor, identity.product),
+            events: events.clone(),
+            grabbed: false,
+            virtual_state: Some(Arc::new(Mutex::new(VirtualDeviceState::default()))),
+        };
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        return self.file.is_none();
+    }
+
     /// Unique ID of the device.
     pub fn id(&self) -> String {
         return self.path();
@@ -246,6 +356,30 @@ impl EvdevDevice {
         return self.id_str.clone();
     }
 
+    pub fn bustype(&self) -> i32 {
+        return self.bustype;
+    }
+
+    pub fn version(&self) -> i32 {
+        return self.version;
+    }
+
+    /// Physical location, e.g. "isa0060/serio0/input0", if any.
+    pub fn phys(&self) -> Option<String> {
+        return self.phys.clone();
+    }
+
+    /// Return the identity of the device, e.g. to create a uinput device that mirrors it.
+    pub fn identity(&self) -> DeviceIdentity {
+        return DeviceIdentity {
+            bustype: self.bustype,
+            vendor: self.vendor,
+            product: self.product,
+            version: self.version,
+            phys: self.phys.clone(),
+        };
+    }
+
     pub fn supported_events(&self) -> EventsDescriptor {
         self.events.clone()
     }
@@ -255,6 +389,10 @@ impl EvdevDevice {
             if self.grabbed == grab {
                 return Ok(());
             }
+            if self.is_virtual() {
+                self.grabbed = grab;
+                return Ok(());
+            }
             let mode = if grab {
                 native::libevdev_grab_mode_LIBEVDEV_GRAB
             } else {
@@ -272,11 +410,99 @@ impl EvdevDevice {
         }
     }
 
+    /// Turn an LED, e.g. `LED_CAPSL`, on or off. LEDs that the device doesn't have are ignored.
+    pub fn set_led(&self, code: i32, on: bool) -> Result<(), EvdevError> {
+        let has_led = self.events.events.get(&EventType::EV_LED).is_some_and(|codes| codes.contains(&code));
+        if !has_led || self.is_virtual() {
+            return Ok(());
+        }
+        let value = if on {
+            native::libevdev_led_value_LIBEVDEV_LED_ON
+        } else {
+            native::libevdev_led_value_LIBEVDEV_LED_OFF
+        };
+        let result = unsafe { native::libevdev_kernel_set_led_value(self.device.ptr, code as u32, value) };
+        if result != 0 {
+            return Err(EvdevError::ErrnoError(-result));
+        }
+        return Ok(());
+    }
+
+    /// Upload a force-feedback effect, and return its ID on the device. If `effect.id` is -1, it's
+    /// a new effect, otherwise the effect with the ID is updated.
+    pub(crate) fn upload_ff_effect(&self, effect: &mut native::ff_effect) -> Result<i16, EvdevError> {
+        if let Some(state) = &self.virtual_state {
+            let mut state = state.lock().unwrap();
+            if effect.id == -1 {
+                effect.id = state.next_ff_id;
+                state.next_ff_id += 1;
+            } else if !state.ff_effects.contains_key(&effect.id) {
+                return Err(EvdevError::ErrnoError(libc::EINVAL));
+            }
+            state.ff_effects.insert(effect.id, effect.type_);
+            return Ok(effect.id);
+        }
+        unsafe {
+            // The kernel sets the new ID to `effect.id`.
+            eviocsff(self.fd, effect as *mut native::ff_effect).map_err(|e| EvdevError::ErrnoError(e as i32))?;
+        }
+        return Ok(effect.id);
+    }
+
+    pub(crate) fn erase_ff_effect(&self, id: i16) -> Result<(), EvdevError> {
+        if let Some(state) = &self.virtual_state {
+            return match state.lock().unwrap().ff_effects.remove(&id) {
+                Some(_) => Ok(()),
+                None => Err(EvdevError::ErrnoError(libc::EINVAL)),
+            };
+        }
+        unsafe {
+            eviocrmff(self.fd, id as nix::sys::ioctl::ioctl_param_type).map_err(|e| EvdevError::ErrnoError(e as i32))?;
+        }
+        return Ok(());
+    }
+
+    /// Write an event to the device, e.g. EV_FF to play a force-feedback effect.
+    pub fn write_event(&self, ev: &InputEvent) -> Result<(), EvdevError> {
+        if let Some(state) = &self.virtual_state {
+            state.lock().unwrap().written.push(*ev);
+            return Ok(());
+        }
+        let ie = native::input_event {
+            time: native::timeval { tv_sec: 0, tv_usec: 0 },
+            type_: ev.event_type as u16,
+            code: ev.code as u16,
+            value: ev.value,
+        };
+        let size = std::mem::size_of::<native::input_event>();
+        let ret = unsafe { libc::write(self.fd, &ie as *const native::input_event as *const libc::c_void, size) };
+        if ret < 0 {
+            return Err(EvdevError::ErrnoError(errno::errno().0));
+        }
+        return Ok(());
+    }
+
+    /// Events written to a virtual device with `write_event()`. Always empty for a real device.
+    pub fn written_events(&self) -> Vec<InputEvent> {
+        return self.virtual_state.as_ref().map_or(vec![], |state| state.lock().unwrap().written.clone());
+    }
+
+    /// Types of the force-feedback effects uploaded to a virtual device, by their IDs. Always empty for a real device.
+    pub fn ff_effect_types(&self) -> HashMap<i16, u16> {
+        return self
+            .virtual_state
+            .as_ref()
+            .map_or(HashMap::new(), |state| state.lock().unwrap().ff_effects.clone());
+    }
+
     pub fn device_fd(&self) -> std::os::unix::io::RawFd {
         return self.fd;
     }
 
     pub fn has_event_pending(&self) -> Result<bool, EvdevError> {
+        if self.is_virtual() {
+            return Ok(false);
+        }
         unsafe {
             let result = native::libevdev_has_event_pending(self.device.ptr);
             if result < 0 {
@@ -352,7 +578,10 @@ impl EvdevDevice {
 
 impl Drop for EvdevDevice {
     fn drop(&mut self) {
-        let file_rc = Arc::strong_count(&self.file);
+        let file_rc = match &self.file {
+            Some(file) => Arc::strong_count(file),
+            None => return, // Virtual device.
+        };
         if file_rc > 1 {
             return;
         }
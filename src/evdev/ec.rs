@@ -2,6 +2,31 @@
 
 include! {"linux_input-event-codes.h.rs"}
 
+// From /usr/include/linux/input.h and /usr/include/linux/uinput.h, which aren't in the generated file.
+
+pub const FF_RUMBLE: i32 = 0x50;
+pub const FF_PERIODIC: i32 = 0x51;
+pub const FF_CONSTANT: i32 = 0x52;
+pub const FF_SPRING: i32 = 0x53;
+pub const FF_FRICTION: i32 = 0x54;
+pub const FF_DAMPER: i32 = 0x55;
+pub const FF_INERTIA: i32 = 0x56;
+pub const FF_RAMP: i32 = 0x57;
+pub const FF_SQUARE: i32 = 0x58;
+pub const FF_TRIANGLE: i32 = 0x59;
+pub const FF_SINE: i32 = 0x5a;
+pub const FF_SAW_UP: i32 = 0x5b;
+pub const FF_SAW_DOWN: i32 = 0x5c;
+pub const FF_CUSTOM: i32 = 0x5d;
+pub const FF_GAIN: i32 = 0x60;
+pub const FF_AUTOCENTER: i32 = 0x61;
+pub const FF_MAX: i32 = 0x7f;
+
+/// Event type of the requests the kernel sends to a uinput device, e.g. `UI_FF_UPLOAD`.
+pub const EV_UINPUT: i32 = 0x0101;
+pub const UI_FF_UPLOAD: i32 = 1;
+pub const UI_FF_ERASE: i32 = 2;
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(i32)]
@@ -18,6 +43,7 @@ pub enum EventType {
     EV_FF = EV_FF,
     EV_PWR = EV_PWR,
     EV_FF_STATUS = EV_FF_STATUS,
+    EV_UINPUT = EV_UINPUT,
 }
 
 impl EventType {
@@ -35,7 +61,24 @@ impl EventType {
             EV_FF => EventType::EV_FF,
             EV_PWR => EventType::EV_PWR,
             EV_FF_STATUS => EventType::EV_FF_STATUS,
+            EV_UINPUT => EventType::EV_UINPUT,
             _ => panic!("Unknown event type: {}", type_),
         }
     }
 }
+
+/// Return the code of a `KEY_*` or `BTN_*` key from its name, e.g. "KEY_ESC" or "BTN_LEFT".
+/// The "KEY_" prefix may be omitted, and the name is case insensitive.
+pub fn get_key_code_by_name(name: &str) -> Option<i32> {
+    let name = name.to_ascii_uppercase();
+    return get_key_code(&name).or_else(|| get_key_code(&format!("KEY_{}", name)));
+}
+
+#[test]
+fn test_get_key_code_by_name() {
+    assert_eq!(Some(KEY_ESC), get_key_code_by_name("KEY_ESC"));
+    assert_eq!(Some(KEY_ESC), get_key_code_by_name("ESC"));
+    assert_eq!(Some(KEY_BACKSPACE), get_key_code_by_name("backspace"));
+    assert_eq!(Some(BTN_LEFT), get_key_code_by_name("BTN_LEFT"));
+    assert_eq!(None, get_key_code_by_name("NO_SUCH_KEY"));
+}
@@ -0,0 +1,345 @@
+//! Recordings of the events of a device, in the text format of evemu
+//! (https://www.freedesktop.org/wiki/Evemu/), so they can be replayed with `evemu-play` too.
+//!
+//! A recording starts with the description of the device:
+//!
+//! ```text
+//! N: Logitech USB Receiver          # Name
+//! I: 0003 046d c52b 0111            # Bus type, vendor, product and version
+//! P: 00 00 00 00 00 00 00 00        # Input properties bitmask
+//! B: 02 03 00 00 00 00 00 00 00     # Event codes bitmask of a type, i.e. EV_REL here
+//! A: 00 0 4095 0 0 40               # ABS code, minimum, maximum, fuzz, flat and resolution
+//! ```
+//!
+//! followed by the events, with the seconds since the first event:
+//!
+//! ```text
+//! E: 0.000000 0002 0000 0003        # Time, type, code and value
+//! ```
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+use super::{
+    ec::{self, EventType},
+    DeviceIdentity, EvdevAbsInfo, EvdevDevice, EvdevError, EventsDescriptor, InputEvent,
+};
+
+/// The event types that are recorded, and their max codes.
+const EVENT_TYPES: &[(EventType, i32)] = &[
+    (EventType::EV_KEY, ec::KEY_MAX),
+    (EventType::EV_REL, ec::REL_MAX),
+    (EventType::EV_ABS, ec::ABS_MAX),
+    (EventType::EV_MSC, ec::MSC_MAX),
+    (EventType::EV_SW, ec::SW_MAX),
+    (EventType::EV_LED, ec::LED_MAX),
+    (EventType::EV_SND, ec::SND_MAX),
+    (EventType::EV_REP, ec::REP_MAX),
+    (EventType::EV_FF, ec::FF_MAX),
+];
+
+/// A device description and its events.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    pub name: String,
+    pub identity: DeviceIdentity,
+    pub events: EventsDescriptor,
+
+    /// The events, with the time since the first one as the timestamps.
+    pub records: Vec<InputEvent>,
+}
+
+impl EventLog {
+    pub fn new(name: &str, identity: &DeviceIdentity, events: &EventsDescriptor) -> EventLog {
+        return EventLog {
+            name: name.to_string(),
+            identity: identity.clone(),
+            events: events.clone(),
+            records: vec![],
+        };
+    }
+
+    /// Create an empty log with the description of a device.
+    pub fn from_device(device: &EvdevDevice) -> EventLog {
+        return EventLog::new(&device.name(), &device.identity(), &device.supported_events());
+    }
+
+    pub fn load(path: &Path) -> Result<EventLog, EvdevError> {
+        return EventLog::read(BufReader::new(File::open(path)?));
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), EvdevError> {
+        let mut writer = EventLogWriter::new(io::BufWriter::new(File::create(path)?), self)?;
+        for ev in &self.records {
+            writer.write_event(ev)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Parse a recording. The lines that this library doesn't use, e.g. LED states, are ignored.
+    pub fn read<R: BufRead>(reader: R) -> Result<EventLog, EvdevError> {
+        let mut ret = EventLog::default();
+        let mut property_bits: Vec<u8> = vec![];
+        let mut code_bits: HashMap<i32, Vec<u8>> = HashMap::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || parse_error(i + 1, line);
+            let (tag, rest) = line.split_at(line.find(':').ok_or_else(error)?);
+            let rest = rest[1..].trim();
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            let hex = |s: &str| i32::from_str_radix(s, 16).map_err(|_| error());
+            let dec = |s: &str| s.parse::<i32>().map_err(|_| error());
+
+            match tag {
+                "N" => ret.name = rest.to_string(),
+                "I" => {
+                    if fields.len() != 4 {
+                        return Err(error());
+                    }
+                    ret.identity.bustype = hex(fields[0])?;
+                    ret.identity.vendor = hex(fields[1])?;
+                    ret.identity.product = hex(fields[2])?;
+                    ret.identity.version = hex(fields[3])?;
+                }
+                "P" => {
+                    for f in &fields {
+                        property_bits.push(hex(f)? as u8);
+                    }
+                }
+                "B" => {
+                    let (type_, bytes) = fields.split_first().ok_or_else(error)?;
+                    let bits = code_bits.entry(hex(type_)?).or_default();
+                    for f in bytes {
+                        bits.push(hex(f)? as u8);
+                    }
+                }
+                "A" => {
+                    if fields.len() < 6 {
+                        return Err(error());
+                    }
+                    let info = EvdevAbsInfo {
+                        value: 0,
+                        minimum: dec(fields[1])?,
+                        maximum: dec(fields[2])?,
+                        fuzz: dec(fields[3])?,
+                        flat: dec(fields[4])?,
+                        resolution: dec(fields[5])?,
+                    };
+                    ret.events.abs_info.insert(hex(fields[0])?, info);
+                }
+                "E" => {
+                    if fields.len() != 4 {
+                        return Err(error());
+                    }
+                    let (sec, usec) = fields[0].split_once('.').ok_or_else(error)?;
+                    let (sec, usec) = (sec.parse::<i64>().map_err(|_| error())?, usec.parse::<i64>().map_err(|_| error())?);
+                    let type_ = hex(fields[1])?;
+                    if !EVENT_TYPES.iter().any(|(t, _)| *t as i32 == type_) && type_ != ec::EV_SYN {
+                        continue;
+                    }
+                    let ev = InputEvent::with_timestamp(sec, usec, EventType::from_i32(type_), hex(fields[2])?, dec(fields[3])?);
+                    ret.records.push(ev);
+                }
+                _ => {}
+            }
+        }
+
+        for prop in bits_to_codes(&property_bits) {
+            ret.events.add_property(prop);
+        }
+        for (event_type, _) in EVENT_TYPES {
+            // The ABS codes come from the "A:" lines, because they need the ranges.
+            if *event_type == EventType::EV_ABS {
+                continue;
+            }
+            if let Some(bits) = code_bits.get(&(*event_type as i32)) {
+                let codes = bits_to_codes(bits);
+                if !codes.is_empty() {
+                    ret.events.events.insert(*event_type, codes);
+                }
+            }
+        }
+        return Ok(ret);
+    }
+}
+
+fn parse_error(line_no: usize, line: &str) -> EvdevError {
+    return io::Error::new(io::ErrorKind::InvalidData, format!("Invalid event log at line {}: {}", line_no, line)).into();
+}
+
+/// Return the indexes of the set bits.
+fn bits_to_codes(bits: &[u8]) -> Vec<i32> {
+    let mut ret = vec![];
+    for (i, byte) in bits.iter().enumerate() {
+        for bit in 0..8 {
+            if byte & (1 << bit) != 0 {
+                ret.push((i * 8 + bit) as i32);
+            }
+        }
+    }
+    return ret;
+}
+
+fn codes_to_bits(codes: &[i32], max_code: i32) -> Vec<u8> {
+    let mut ret = vec![0u8; (max_code as usize + 8) / 8];
+    for code in codes {
+        if *code >= 0 && *code <= max_code {
+            ret[*code as usize / 8] |= 1 << (code % 8);
+        }
+    }
+    return ret;
+}
+
+/// Writes a recording as the events come in, e.g. from `on_event`. The timestamps are made
+/// relative to the first event.
+pub struct EventLogWriter<W: Write> {
+    writer: W,
+
+    /// Timestamp of the first event, in microseconds.
+    start: Option<i64>,
+}
+
+impl<W: Write> EventLogWriter<W> {
+    /// Create a writer and write the device description of `log`. Its events aren't written.
+    pub fn new(mut writer: W, log: &EventLog) -> io::Result<EventLogWriter<W>> {
+        let identity = &log.identity;
+        writeln!(writer, "# EVEMU 1.3")?;
+        writeln!(writer, "# Input device name: \"{}\"", log.name)?;
+        writeln!(
+            writer,
+            "# Input device ID: bus {:#04x} vendor {:#06x} product {:#06x} version {:#06x}",
+            identity.bustype, identity.vendor, identity.product, identity.version
+        )?;
+        writeln!(writer, "N: {}", log.name)?;
+        writeln!(
+            writer,
+            "I: {:04x} {:04x} {:04x} {:04x}",
+            identity.bustype, identity.vendor, identity.product, identity.version
+        )?;
+        write_bits(&mut writer, "P:", &codes_to_bits(&log.events.properties, ec::INPUT_PROP_MAX))?;
+
+        let mut types = vec![ec::EV_SYN];
+        for (event_type, _) in EVENT_TYPES {
+            if log.events.events.contains_key(event_type) || (*event_type == EventType::EV_ABS && !log.events.abs_info.is_empty()) {
+                types.push(*event_type as i32);
+            }
+        }
+        write_bits(&mut writer, "B: 00", &codes_to_bits(&types, ec::EV_MAX))?;
+        for (event_type, max_code) in EVENT_TYPES {
+            let mut codes = log.events.events.get(event_type).cloned().unwrap_or_default();
+            if *event_type == EventType::EV_ABS {
+                codes.extend(log.events.abs_info.keys());
+            }
+            write_bits(&mut writer, &format!("B: {:02x}", *event_type as i32), &codes_to_bits(&codes, *max_code))?;
+        }
+
+        let mut abs_codes: Vec<&i32> = log.events.abs_info.keys().collect();
+        abs_codes.sort();
+        for code in abs_codes {
+            let info = &log.events.abs_info[code];
+            writeln!(
+                writer,
+                "A: {:02x} {} {} {} {} {}",
+                code, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution
+            )?;
+        }
+        return Ok(EventLogWriter { writer, start: None });
+    }
+
+    pub fn write_event(&mut self, ev: &InputEvent) -> io::Result<()> {
+        let time = ev.time_sec * 1_000_000 + ev.time_usec;
+        let elapsed = (time - *self.start.get_or_insert(time)).max(0);
+        let comment = match ev.event_type {
+            EventType::EV_SYN => format!("------------ {} ({}) ----------", ev.code_name(), ev.value),
+            _ => format!("{} / {:<20} {}", ev.type_name(), ev.code_name(), ev.value),
+        };
+        writeln!(
+            self.writer,
+            "E: {}.{:06} {:04x} {:04x} {:04}\t# {}",
+            elapsed / 1_000_000,
+            elapsed % 1_000_000,
+            ev.event_type as i32,
+            ev.code,
+            ev.value,
+            comment
+        )?;
+        return Ok(());
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        return self.writer.flush();
+    }
+}
+
+/// Write a bitmask, 8 bytes per line.
+fn write_bits<W: Write>(writer: &mut W, prefix: &str, bits: &[u8]) -> io::Result<()> {
+    for chunk in bits.chunks(8) {
+        write!(writer, "{}", prefix)?;
+        for byte in chunk {
+            write!(writer, " {:02x}", byte)?;
+        }
+        // Pad the last line like evemu does.
+        for _ in chunk.len()..8 {
+            write!(writer, " 00")?;
+        }
+        writeln!(writer)?;
+    }
+    return Ok(());
+}
+
+#[test]
+fn test_event_log() {
+    let mut events = EventsDescriptor::with_mouse_events();
+    events.abs_info.insert(
+        ec::ABS_X,
+        EvdevAbsInfo {
+            maximum: 4095,
+            resolution: 40,
+            ..Default::default()
+        },
+    );
+    events.add_property(ec::INPUT_PROP_POINTER);
+    let identity = DeviceIdentity {
+        bustype: 3,
+        vendor: 0x46d,
+        product: 0xc52b,
+        version: 0x111,
+        phys: None,
+    };
+    let mut log = EventLog::new("Test mouse", &identity, &events);
+    log.records.push(InputEvent::with_timestamp(100, 999_000, EventType::EV_REL, ec::REL_X, -3));
+    log.records.push(InputEvent::with_timestamp(100, 999_000, EventType::EV_SYN, ec::SYN_REPORT, 0));
+    log.records.push(InputEvent::with_timestamp(101, 11_000, EventType::EV_KEY, ec::BTN_LEFT, 1));
+
+    let mut writer = EventLogWriter::new(vec![], &log).unwrap();
+    for ev in &log.records {
+        writer.write_event(ev).unwrap();
+    }
+    let text = String::from_utf8(writer.writer).unwrap();
+    assert!(text.contains("\nI: 0003 046d c52b 0111\n"));
+    assert!(text.contains("\nA: 00 0 4095 0 0 40\n"));
+    assert!(text.contains("\nE: 0.012000 0001 0110 0001\t"));
+
+    let read = EventLog::read(text.as_bytes()).unwrap();
+    assert_eq!("Test mouse", read.name);
+    assert_eq!(identity, read.identity);
+    assert_eq!(vec![ec::INPUT_PROP_POINTER], read.events.properties);
+    let mut rel_codes = events.events[&EventType::EV_REL].clone();
+    rel_codes.sort_unstable();
+    assert_eq!(rel_codes, read.events.events[&EventType::EV_REL]);
+    assert!(!read.events.events.contains_key(&EventType::EV_ABS));
+    assert_eq!(4095, read.events.abs_info[&ec::ABS_X].maximum);
+    let times: Vec<(i64, i64, i32)> = read.records.iter().map(|ev| (ev.time_sec, ev.time_usec, ev.value)).collect();
+    assert_eq!(vec![(0, 0, -3), (0, 0, 0), (0, 12_000, 1)], times);
+
+    assert!(EventLog::read("E: 0.0 0001".as_bytes()).is_err());
+}
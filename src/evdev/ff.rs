@@ -0,0 +1,249 @@
+//! Force-feedback passthrough. The programs (e.g. games) upload and play effects on the uinput
+//! device, which are forwarded to the grabbed source devices that actually have the motors.
+//!
+//! libevdev sets `ff_effects_max` on a uinput device that supports EV_FF, so the uinput device only
+//! needs the FF codes of the source devices. `KeyRemapper` recreates its main uinput device with them
+//! when a device with new codes is grabbed; see `uinput_codes_with()`.
+use std::collections::HashMap;
+
+use crate::native;
+
+use super::{
+    ec::{self, EventType},
+    uinput::Uinput,
+    EvdevDevice, EvdevError, InputEvent,
+};
+
+/// A source device the effects are forwarded to.
+struct FfTarget {
+    device: EvdevDevice,
+
+    /// Effect IDs on the uinput device -> effect IDs on this device.
+    ids: HashMap<i16, i16>,
+}
+
+/// Forwards the force-feedback effects uploaded to a uinput device to the source devices.
+pub struct FfForwarder {
+    /// Uploaded effects by the ID on the uinput device, which are uploaded again to new devices.
+    effects: HashMap<i16, native::ff_effect>,
+
+    targets: Vec<FfTarget>,
+
+    /// FF codes the uinput device supports, sorted.
+    uinput_codes: Vec<i32>,
+}
+
+impl std::fmt::Debug for FfForwarder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f
+            .debug_struct("FfForwarder")
+            .field("effects", &self.effects.len())
+            .field("targets", &self.targets.iter().map(|t| t.device.name()).collect::<Vec<_>>())
+            .field("uinput_codes", &self.uinput_codes)
+            .finish();
+    }
+}
+
+impl FfForwarder {
+    pub fn new() -> FfForwarder {
+        return FfForwarder {
+            effects: HashMap::new(),
+            targets: vec![],
+            uinput_codes: vec![],
+        };
+    }
+
+    /// Return the FF codes the uinput device needs to support to receive the requests for `devices`,
+    /// or `None` if it already supports them. The codes it supports are kept, so that it isn't
+    /// recreated every time a device is disconnected and connected again.
+    pub fn uinput_codes_with(&self, devices: &[EvdevDevice]) -> Option<Vec<i32>> {
+        let mut codes = self.uinput_codes.clone();
+        for device in devices {
+            if let Some(device_codes) = device.supported_events().events.get(&EventType::EV_FF) {
+                codes.extend(device_codes);
+            }
+        }
+        codes.sort_unstable();
+        codes.dedup();
+        if codes == self.uinput_codes {
+            return None;
+        }
+        return Some(codes);
+    }
+
+    /// Set the FF codes the uinput device supports, after it's (re)created with them. The effects
+    /// uploaded to the old device are forgotten, as they're gone with it.
+    pub fn set_uinput_codes(&mut self, codes: &[i32]) {
+        self.uinput_codes = codes.to_vec();
+        self.uinput_codes.sort_unstable();
+        self.effects.clear();
+        for target in self.targets.iter_mut() {
+            for (_, target_id) in target.ids.drain() {
+                if let Err(e) = target.device.erase_ff_effect(target_id) {
+                    eprintln!("Unable to erase FF effect on {}: {}", target.device.name(), e);
+                }
+            }
+        }
+    }
+
+    /// Set the devices to forward the effects to, and upload the existing effects to them.
+    /// Devices without EV_FF are ignored.
+    pub fn set_devices(&mut self, devices: &[EvdevDevice]) {
+        self.targets = devices
+            .iter()
+            .filter(|d| d.supported_events().events.contains_key(&EventType::EV_FF))
+            .map(|d| FfTarget {
+                device: d.clone(),
+                ids: HashMap::new(),
+            })
+            .collect();
+
+        let mut ids: Vec<i16> = self.effects.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let effect = self.effects[&id];
+            for target in self.targets.iter_mut() {
+                if let Err(e) = upload_to(target, id, &effect) {
+                    eprintln!("Unable to upload FF effect to {}: {}", target.device.name(), e);
+                }
+            }
+        }
+    }
+
+    /// Handle an event read from `uinput`. Returns true if it was a force-feedback event.
+    pub fn handle_uinput_event(&mut self, uinput: &Uinput, ev: &InputEvent) -> bool {
+        let result = match (ev.event_type, ev.code) {
+            (EventType::EV_UINPUT, ec::UI_FF_UPLOAD) => self.handle_upload(uinput, ev.value as u32),
+            (EventType::EV_UINPUT, ec::UI_FF_ERASE) => self.handle_erase(uinput, ev.value as u32),
+            (EventType::EV_FF, _) => {
+                self.play(ev);
+                Ok(())
+            }
+            _ => return false,
+        };
+        if let Err(e) = result {
+            eprintln!("Unable to handle FF request: {}", e);
+        }
+        return true;
+    }
+
+    fn handle_upload(&mut self, uinput: &Uinput, request_id: u32) -> Result<(), EvdevError> {
+        let mut upload = uinput.begin_ff_upload(request_id)?;
+        let id = upload.effect.id;
+        log::debug!("FF upload: id={} type={}", id, upload.effect.type_);
+
+        // The upload succeeds if no device has FF yet; the effect is uploaded when one is added.
+        let mut retval = 0;
+        for target in self.targets.iter_mut() {
+            if let Err(e) = upload_to(target, id, &upload.effect) {
+                eprintln!("Unable to upload FF effect to {}: {}", target.device.name(), e);
+                retval = errno_of(&e);
+            }
+        }
+        if retval == 0 {
+            self.effects.insert(id, upload.effect);
+        }
+        upload.retval = retval;
+        return uinput.end_ff_upload(&upload);
+    }
+
+    fn handle_erase(&mut self, uinput: &Uinput, request_id: u32) -> Result<(), EvdevError> {
+        let mut erase = uinput.begin_ff_erase(request_id)?;
+        let id = erase.effect_id as i16;
+        log::debug!("FF erase: id={}", id);
+
+        self.effects.remove(&id);
+        for target in self.targets.iter_mut() {
+            if let Some(target_id) = target.ids.remove(&id) {
+                if let Err(e) = target.device.erase_ff_effect(target_id) {
+                    eprintln!("Unable to erase FF effect on {}: {}", target.device.name(), e);
+                }
+            }
+        }
+        erase.retval = 0;
+        return uinput.end_ff_erase(&erase);
+    }
+
+    /// Forward a playback (the code is an effect ID) or an FF_GAIN / FF_AUTOCENTER event.
+    fn play(&self, ev: &InputEvent) {
+        for target in self.targets.iter() {
+            let code = match ev.code {
+                ec::FF_GAIN | ec::FF_AUTOCENTER => ev.code,
+                _ => match target.ids.get(&(ev.code as i16)) {
+                    Some(id) => *id as i32,
+                    None => continue,
+                },
+            };
+            if let Err(e) = target.device.write_event(&InputEvent::new(EventType::EV_FF, code, ev.value)) {
+                eprintln!("Unable to play FF effect on {}: {}", target.device.name(), e);
+            }
+        }
+    }
+}
+
+impl Default for FfForwarder {
+    fn default() -> Self {
+        return FfForwarder::new();
+    }
+}
+
+/// Upload an effect, or update it if it's already uploaded, and remember its ID on the device.
+fn upload_to(target: &mut FfTarget, id: i16, effect: &native::ff_effect) -> Result<(), EvdevError> {
+    let mut effect = *effect;
+    effect.id = target.ids.get(&id).copied().unwrap_or(-1);
+    let target_id = target.device.upload_ff_effect(&mut effect)?;
+    target.ids.insert(id, target_id);
+    return Ok(());
+}
+
+/// Return a negative errno for `uinput_ff_upload::retval`.
+fn errno_of(e: &EvdevError) -> i32 {
+    return match e {
+        EvdevError::ErrnoError(errno) => -errno,
+        _ => -libc::EIO,
+    };
+}
+
+#[test]
+fn test_ff_passthrough() {
+    use super::EventsDescriptor;
+    use crate::{testing::TestRemapper, KeyRemapperConfiguration};
+    use std::time::Duration;
+
+    let config = KeyRemapperConfiguration::new("ff test", "");
+    let remapper = TestRemapper::new(&config);
+
+    // A gamepad with a rumble motor. The main uinput device gets its FF code when it's grabbed.
+    let mut events = EventsDescriptor::new();
+    events.events.insert(EventType::EV_KEY, vec![ec::BTN_SOUTH]);
+    events.events.insert(EventType::EV_FF, vec![ec::FF_RUMBLE]);
+    let gamepad = remapper.add_device("gamepad", &events);
+    assert_eq!(None, remapper.supported_events_of("").events.get(&EventType::EV_FF));
+    remapper.start();
+    assert_eq!(Some(&vec![ec::FF_RUMBLE]), remapper.supported_events_of("").events.get(&EventType::EV_FF));
+
+    // Upload, play and erase a rumble effect on the uinput device, like a game would.
+    let id = remapper.upload_ff_rumble(0x8000, 0, Duration::from_millis(100)).unwrap();
+    let uploaded = gamepad.ff_effect_types();
+    assert_eq!(vec![ec::FF_RUMBLE as u16], uploaded.values().copied().collect::<Vec<_>>());
+    let gamepad_id = *uploaded.keys().next().unwrap();
+
+    remapper.play_ff_effect(id, 1);
+    remapper.play_ff_effect(id + 1, 1); // Not uploaded, so it's ignored.
+    assert_eq!(vec![InputEvent::new(EventType::EV_FF, gamepad_id as i32, 1)], gamepad.written_events());
+
+    assert_eq!(0, remapper.erase_ff_effect(id));
+    assert!(gamepad.ff_effect_types().is_empty());
+
+    // The effect is uploaded again when the gamepad is reconnected.
+    let id = remapper.upload_ff_rumble(0x8000, 0, Duration::from_millis(100)).unwrap();
+    let before = gamepad.ff_effect_types();
+    remapper.devices_lost();
+    let reuploaded: Vec<i16> = gamepad.ff_effect_types().keys().filter(|id| !before.contains_key(id)).copied().collect();
+    assert_eq!(1, reuploaded.len());
+    remapper.play_ff_effect(id, 0);
+    assert_eq!(
+        Some(&InputEvent::new(EventType::EV_FF, reuploaded[0] as i32, 0)),
+        gamepad.written_events().last()
+    );
+}
@@ -1,6 +1,12 @@
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+};
 
-use crate::{native, validate_modifiers};
+use crate::{
+    modifiers::{ModifierRegistry, ModifierSpec},
+    native,
+};
 
 use super::ec;
 
@@ -21,13 +27,37 @@ impl KeyEventType {
     }
 }
 
-pub(crate) type Modifers = u32;
+/// Pressed modifier keys, one bit per key. See the `modifiers` module.
+pub(crate) type Modifers = u64;
 
-pub(crate) const MODIFIER_ALT: Modifers = 1 << 0;
-pub(crate) const MODIFIER_CTRL: Modifers = 1 << 1;
-pub(crate) const MODIFIER_SHIFT: Modifers = 1 << 2;
-pub(crate) const MODIFIER_WIN: Modifers = 1 << 3;
-pub(crate) const MODIFIER_ESC: Modifers = 1 << 4;
+/// The registry that built the modifiers of an event, or `None` for the built-in one. It's only
+/// needed to interpret the modifiers, so it doesn't affect the comparisons.
+#[derive(Clone, Copy)]
+struct RegistryRef(Option<&'static ModifierRegistry>);
+
+impl RegistryRef {
+    fn get(&self) -> &'static ModifierRegistry {
+        return self.0.unwrap_or_else(ModifierRegistry::builtin);
+    }
+}
+
+impl fmt::Debug for RegistryRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "..");
+    }
+}
+
+impl PartialEq for RegistryRef {
+    fn eq(&self, _other: &RegistryRef) -> bool {
+        return true;
+    }
+}
+
+impl Eq for RegistryRef {}
+
+impl Hash for RegistryRef {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
 
 /// Represents a single event. See https://www.kernel.org/doc/html/latest/input/input.html#event-interface
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -38,6 +68,7 @@ pub struct InputEvent {
     pub code: i32,
     pub value: i32,
     modifiers: Modifers,
+    registry: RegistryRef,
 }
 
 impl Display for InputEvent {
@@ -48,14 +79,7 @@ impl Display for InputEvent {
         let modifiers = if self.modifiers == 0 {
             String::new()
         } else {
-            format!(
-                " modifiers=[{}{}{}{}{}]",
-                if self.with_alt() { "a" } else { "" },
-                if self.with_ctrl() { "c" } else { "" },
-                if self.with_shift() { "s" } else { "" },
-                if self.with_winkey() { "w" } else { "" },
-                if self.with_esc() { "e" } else { "" },
-            )
+            format!(" modifiers=[{}]", self.registry.get().format(self.modifiers))
         };
 
         return write!(
@@ -81,6 +105,7 @@ impl InputEvent {
             code,
             value,
             modifiers: 0,
+            registry: RegistryRef(None),
         };
     }
 
@@ -92,6 +117,7 @@ impl InputEvent {
             code,
             value,
             modifiers: 0,
+            registry: RegistryRef(None),
         };
     }
 
@@ -112,6 +138,7 @@ impl InputEvent {
             code: ie.code as i32,
             value: ie.value as i32,
             modifiers: 0,
+            registry: RegistryRef(None),
         };
     }
 
@@ -129,70 +156,44 @@ impl InputEvent {
         };
     }
 
-    /// Set the modifiers. This struct itself doesn't set `modifiers` and instead it expects the
+    /// Set the modifiers, which `registry` has built. This struct itself doesn't set `modifiers` and instead it expects the
     /// upper layer sets them via this function. This is because we want to support multiple keyboards
     /// and pressing some modifiers on keyboard A and then press a key on keyboard B.
-    pub(crate) fn set_modifiers(&mut self, alt: bool, ctrl: bool, shift: bool, winkey: bool, esc: bool) {
-        self.modifiers = (if alt { MODIFIER_ALT } else { 0 })
-            | (if ctrl { MODIFIER_CTRL } else { 0 })
-            | (if shift { MODIFIER_SHIFT } else { 0 })
-            | (if winkey { MODIFIER_WIN } else { 0 })
-            | (if esc { MODIFIER_ESC } else { 0 })
-            | 0;
+    pub(crate) fn set_modifiers(&mut self, registry: &'static ModifierRegistry, modifiers: Modifers) {
+        self.modifiers = modifiers;
+        self.registry = RegistryRef(Some(registry));
+    }
+
+    pub(crate) fn modifiers(&self) -> Modifers {
+        return self.modifiers;
     }
 
     pub fn with_alt(&self) -> bool {
-        return (self.modifiers & MODIFIER_ALT) != 0;
+        return self.with_modifiers("a*");
     }
 
     pub fn with_ctrl(&self) -> bool {
-        return (self.modifiers & MODIFIER_CTRL) != 0;
+        return self.with_modifiers("c*");
     }
 
     pub fn with_shift(&self) -> bool {
-        return (self.modifiers & MODIFIER_SHIFT) != 0;
+        return self.with_modifiers("s*");
     }
 
     pub fn with_winkey(&self) -> bool {
-        return (self.modifiers & MODIFIER_WIN) != 0;
+        return self.with_modifiers("w*");
     }
 
     pub fn with_esc(&self) -> bool {
-        return (self.modifiers & MODIFIER_ESC) != 0;
+        return self.with_modifiers("e*");
     }
 
+    /// Return true if the modifiers match `modifiers`, e.g. `"ae"` for Alt and ESC, or `">a"` for
+    /// the right Alt. Unless `modifiers` contains `*`, the other modifiers need to be released.
+    /// See the `modifiers` module for the grammar.
     pub fn with_modifiers(&self, modifiers: &str) -> bool {
-        validate_modifiers(modifiers, "acswe*");
-
-        let ignore_other_modifiers = modifiers.contains('*');
-
-        let alt = modifiers.contains('a');
-        let ctrl = modifiers.contains('c');
-        let shift = modifiers.contains('s');
-        let win = modifiers.contains('w');
-        let esc = modifiers.contains('e');
-
-        if self.with_alt() != alt && (alt || !ignore_other_modifiers) {
-            return false;
-        }
-
-        if self.with_ctrl() != ctrl && (ctrl || !ignore_other_modifiers) {
-            return false;
-        }
-
-        if self.with_shift() != shift && (shift || !ignore_other_modifiers) {
-            return false;
-        }
-
-        if self.with_winkey() != win && (win || !ignore_other_modifiers) {
-            return false;
-        }
-
-        if self.with_esc() != esc && (esc || !ignore_other_modifiers) {
-            return false;
-        }
-
-        return true;
+        let registry = self.registry.get();
+        return ModifierSpec::parse(registry, modifiers).matches(registry, self.modifiers);
     }
 
     /// Return true if it's a SYN_REPORT event.
@@ -315,6 +316,7 @@ fn test_input_event_format_ev_time() {
                 code: ec::KEY_A,
                 value: 1,
                 modifiers: 0,
+                registry: RegistryRef(None),
             }
         )
     )
@@ -4,7 +4,11 @@ use std::{fmt, str::Utf8Error};
 
 pub mod device;
 pub mod ec;
+pub mod event_log;
+pub mod ff;
 pub mod input_event;
+pub mod multitouch;
+pub mod sink;
 pub mod tracker;
 pub mod uinput;
 
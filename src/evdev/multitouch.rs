@@ -0,0 +1,186 @@
+//! Multitouch state of type-B protocol devices, e.g. touchpads, built from the ABS_MT_* events.
+//! See https://www.kernel.org/doc/html/latest/input/multi-touch-protocol.html
+use super::{
+    ec::{self, EventType},
+    EvdevAbsInfo, EventsDescriptor, InputEvent,
+};
+
+/// A finger on the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub slot: usize,
+    pub tracking_id: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    /// -1 if the slot isn't used.
+    tracking_id: i32,
+    x: i32,
+    y: i32,
+}
+
+/// Tracks the contacts in the slots. Feed it all the events of a device with `on_event()`.
+#[derive(Debug, Clone)]
+pub struct MultitouchState {
+    slots: Vec<Slot>,
+    current_slot: usize,
+    x_info: EvdevAbsInfo,
+    y_info: EvdevAbsInfo,
+
+    /// True if any slot changed since the last SYN_REPORT.
+    changed: bool,
+}
+
+impl MultitouchState {
+    pub fn new(slot_count: usize, x_info: EvdevAbsInfo, y_info: EvdevAbsInfo) -> MultitouchState {
+        return MultitouchState {
+            slots: vec![Slot { tracking_id: -1, x: 0, y: 0 }; slot_count.max(1)],
+            current_slot: 0,
+            x_info,
+            y_info,
+            changed: false,
+        };
+    }
+
+    /// Create a state for a device, using its slot count and position ranges, or return `None` if the
+    /// device doesn't support the type-B protocol.
+    pub fn from_events(events: &EventsDescriptor) -> Option<MultitouchState> {
+        let slot_info = events.abs_info.get(&ec::ABS_MT_SLOT)?;
+        let x_info = events.abs_info.get(&ec::ABS_MT_POSITION_X)?;
+        let y_info = events.abs_info.get(&ec::ABS_MT_POSITION_Y)?;
+        return Some(MultitouchState::new((slot_info.maximum + 1) as usize, *x_info, *y_info));
+    }
+
+    /// Update the state with an event. Returns true on a SYN_REPORT that completes a change of the contacts.
+    /// A SYN_DROPPED clears the contacts without completing a change, because the fingers may still be down.
+    pub fn on_event(&mut self, ev: &InputEvent) -> bool {
+        match ev.event_type {
+            EventType::EV_ABS => match ev.code {
+                ec::ABS_MT_SLOT => {
+                    // Ignore slots beyond the range; the events until the next ABS_MT_SLOT go to the last one.
+                    self.current_slot = (ev.value.max(0) as usize).min(self.slots.len() - 1);
+                }
+                ec::ABS_MT_TRACKING_ID => {
+                    self.slots[self.current_slot].tracking_id = ev.value;
+                    self.changed = true;
+                }
+                ec::ABS_MT_POSITION_X => {
+                    self.slots[self.current_slot].x = ev.value;
+                    self.changed = true;
+                }
+                ec::ABS_MT_POSITION_Y => {
+                    self.slots[self.current_slot].y = ev.value;
+                    self.changed = true;
+                }
+                _ => {}
+            },
+            EventType::EV_SYN => match ev.code {
+                ec::SYN_REPORT => {
+                    let changed = self.changed;
+                    self.changed = false;
+                    return changed;
+                }
+                ec::SYN_DROPPED => {
+                    // We can't tell what we missed, so start over; the device resends the contacts as they move.
+                    self.reset();
+                    return false;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        return false;
+    }
+
+    /// Return the active contacts, ordered by the slot.
+    pub fn contacts(&self) -> Vec<Contact> {
+        return self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.tracking_id >= 0)
+            .map(|(slot, s)| Contact {
+                slot,
+                tracking_id: s.tracking_id,
+                x: s.x,
+                y: s.y,
+            })
+            .collect();
+    }
+
+    /// Return the position of a contact scaled to 0.0-1.0 of the device's range.
+    pub fn normalized_position(&self, contact: &Contact) -> (f64, f64) {
+        let normalize = |value: i32, info: &EvdevAbsInfo| {
+            let range = (info.maximum - info.minimum) as f64;
+            if range <= 0.0 {
+                return 0.0;
+            }
+            return ((value - info.minimum) as f64 / range).clamp(0.0, 1.0);
+        };
+        return (normalize(contact.x, &self.x_info), normalize(contact.y, &self.y_info));
+    }
+
+    /// Forget all the contacts, e.g. when events were dropped.
+    pub fn reset(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.tracking_id = -1;
+        }
+        self.current_slot = 0;
+        self.changed = false;
+    }
+}
+
+#[test]
+fn test_multitouch_state() {
+    let info = |maximum| EvdevAbsInfo { maximum, ..Default::default() };
+    let abs = |code, value| InputEvent::new(EventType::EV_ABS, code, value);
+    let syn = InputEvent::new_syn_report();
+
+    let mut mt = MultitouchState::new(5, info(1000), info(500));
+
+    // The first finger doesn't need ABS_MT_SLOT because the slot is 0 by default.
+    assert!(!mt.on_event(&abs(ec::ABS_MT_TRACKING_ID, 10)));
+    mt.on_event(&abs(ec::ABS_MT_POSITION_X, 100));
+    mt.on_event(&abs(ec::ABS_MT_POSITION_Y, 250));
+    assert!(mt.on_event(&syn));
+    assert!(!mt.on_event(&syn));
+
+    // The second finger; the values of the unchanged axes are kept.
+    mt.on_event(&abs(ec::ABS_MT_SLOT, 1));
+    mt.on_event(&abs(ec::ABS_MT_TRACKING_ID, 11));
+    mt.on_event(&abs(ec::ABS_MT_POSITION_X, 500));
+    mt.on_event(&abs(ec::ABS_MT_SLOT, 0));
+    mt.on_event(&abs(ec::ABS_MT_POSITION_X, 200));
+    assert!(mt.on_event(&syn));
+    let contacts = mt.contacts();
+    assert_eq!(
+        vec![
+            Contact {
+                slot: 0,
+                tracking_id: 10,
+                x: 200,
+                y: 250
+            },
+            Contact {
+                slot: 1,
+                tracking_id: 11,
+                x: 500,
+                y: 0
+            }
+        ],
+        contacts
+    );
+    assert_eq!((0.2, 0.5), mt.normalized_position(&contacts[0]));
+
+    // The first finger is lifted.
+    mt.on_event(&abs(ec::ABS_MT_TRACKING_ID, -1));
+    assert!(mt.on_event(&syn));
+    assert_eq!(vec![11], mt.contacts().iter().map(|c| c.tracking_id).collect::<Vec<_>>());
+
+    // Dropped events clear the contacts, but they don't make a frame.
+    assert!(!mt.on_event(&InputEvent::new(EventType::EV_SYN, ec::SYN_DROPPED, 0)));
+    assert_eq!(Vec::<Contact>::new(), mt.contacts());
+}
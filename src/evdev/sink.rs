@@ -0,0 +1,353 @@
+//! Output sinks, which `Uinput` writes its events to. Besides the uinput device itself, a `Uinput`
+//! can write to extra sinks at the same time; see `Uinput::add_sink()`. See also `ui::xtest::XTestSink` with the `xtest` feature.
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    os::unix::{io::RawFd, net::UnixStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::native;
+
+use super::{
+    ec::{self, EventType},
+    EvdevError, InputEvent,
+};
+
+/// Where a `Uinput` writes the events to. Normally it's the uinput device itself, but it can be
+/// replaced, e.g. with a `MemorySink` in tests. See `Uinput::with_sink()`.
+pub trait OutputSink: fmt::Debug + Send + Sync {
+    /// Write a single event. The caller sends SYN_REPORTs explicitly.
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError>;
+
+    /// FD to read the events written by the host from, if any.
+    fn fd(&self) -> Option<RawFd> {
+        return None;
+    }
+
+    /// Read the events written to the sink by the host, e.g. EV_LED.
+    fn read_events(&self) -> Result<Vec<InputEvent>, EvdevError> {
+        return Ok(vec![]);
+    }
+
+    /// Start handling a `UI_FF_UPLOAD` request read with `read_events()`. See `Uinput::begin_ff_upload()`.
+    fn begin_ff_upload(&mut self, _request_id: u32) -> Result<native::uinput_ff_upload, EvdevError> {
+        return Err(EvdevError::ErrnoError(libc::ENODEV));
+    }
+
+    fn end_ff_upload(&mut self, _upload: &native::uinput_ff_upload) -> Result<(), EvdevError> {
+        return Err(EvdevError::ErrnoError(libc::ENODEV));
+    }
+
+    /// Start handling a `UI_FF_ERASE` request read with `read_events()`. See `Uinput::begin_ff_erase()`.
+    fn begin_ff_erase(&mut self, _request_id: u32) -> Result<native::uinput_ff_erase, EvdevError> {
+        return Err(EvdevError::ErrnoError(libc::ENODEV));
+    }
+
+    fn end_ff_erase(&mut self, _erase: &native::uinput_ff_erase) -> Result<(), EvdevError> {
+        return Err(EvdevError::ErrnoError(libc::ENODEV));
+    }
+}
+
+/// `OutputSink` that keeps the events in memory. Clones share the same buffer, so a test can keep
+/// one and hand the other to `Uinput::with_sink()`. A test can also make the force-feedback requests
+/// of the host with `request_ff_upload()` and `request_ff_erase()`.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    events: Arc<Mutex<Vec<InputEvent>>>,
+    ff: Arc<Mutex<MemoryFfRequests>>,
+}
+
+/// Force-feedback requests made on a `MemorySink`, like the kernel does for a uinput device.
+#[derive(Default)]
+struct MemoryFfRequests {
+    /// Effects being uploaded, by the request ID.
+    uploads: HashMap<u32, native::ff_effect>,
+
+    /// Effect IDs being erased, by the request ID.
+    erases: HashMap<u32, i16>,
+
+    /// `retval`s of the finished requests, by the request ID.
+    results: HashMap<u32, i32>,
+    next_request_id: u32,
+    next_effect_id: i16,
+}
+
+impl fmt::Debug for MemoryFfRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f
+            .debug_struct("MemoryFfRequests")
+            .field("uploads", &self.uploads.len())
+            .field("erases", &self.erases.len())
+            .finish();
+    }
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        return MemorySink::default();
+    }
+
+    /// Return the events written so far.
+    pub fn events(&self) -> Vec<InputEvent> {
+        return self.events.lock().unwrap().clone();
+    }
+
+    /// Return the events written so far, and clear the buffer.
+    pub fn take_events(&self) -> Vec<InputEvent> {
+        return std::mem::take(&mut *self.events.lock().unwrap());
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+
+    /// Request uploading `effect` like a program does with `EVIOCSFF`, and return the `UI_FF_UPLOAD`
+    /// event the host writes for it and the effect ID. A new effect (`effect.id` is -1) gets a new ID.
+    pub(crate) fn request_ff_upload(&self, effect: &native::ff_effect) -> (InputEvent, i16) {
+        let mut ff = self.ff.lock().unwrap();
+        let mut effect = *effect;
+        if effect.id == -1 {
+            effect.id = ff.next_effect_id;
+            ff.next_effect_id += 1;
+        }
+        let request_id = ff.next_request_id;
+        ff.next_request_id += 1;
+        ff.uploads.insert(request_id, effect);
+        return (InputEvent::new(EventType::EV_UINPUT, ec::UI_FF_UPLOAD, request_id as i32), effect.id);
+    }
+
+    /// Request erasing an effect like a program does with `EVIOCRMFF`, and return the `UI_FF_ERASE`
+    /// event the host writes for it.
+    pub(crate) fn request_ff_erase(&self, effect_id: i16) -> InputEvent {
+        let mut ff = self.ff.lock().unwrap();
+        let request_id = ff.next_request_id;
+        ff.next_request_id += 1;
+        ff.erases.insert(request_id, effect_id);
+        return InputEvent::new(EventType::EV_UINPUT, ec::UI_FF_ERASE, request_id as i32);
+    }
+
+    /// Return the `retval` of the request `ev` is for, or `None` if it hasn't been finished.
+    pub(crate) fn ff_result(&self, ev: &InputEvent) -> Option<i32> {
+        return self.ff.lock().unwrap().results.get(&(ev.value as u32)).copied();
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError> {
+        // Like uinput, drop the timestamp and the modifiers, so the events can be compared with new ones.
+        self.events.lock().unwrap().push(InputEvent::new(ev.event_type, ev.code, ev.value));
+        return Ok(());
+    }
+
+    fn begin_ff_upload(&mut self, request_id: u32) -> Result<native::uinput_ff_upload, EvdevError> {
+        let effect = match self.ff.lock().unwrap().uploads.get(&request_id) {
+            Some(effect) => *effect,
+            None => return Err(EvdevError::ErrnoError(libc::EINVAL)),
+        };
+        let mut upload: native::uinput_ff_upload = unsafe { std::mem::zeroed() };
+        upload.request_id = request_id;
+        upload.effect = effect;
+        return Ok(upload);
+    }
+
+    fn end_ff_upload(&mut self, upload: &native::uinput_ff_upload) -> Result<(), EvdevError> {
+        let mut ff = self.ff.lock().unwrap();
+        if ff.uploads.remove(&upload.request_id).is_none() {
+            return Err(EvdevError::ErrnoError(libc::EINVAL));
+        }
+        ff.results.insert(upload.request_id, upload.retval);
+        return Ok(());
+    }
+
+    fn begin_ff_erase(&mut self, request_id: u32) -> Result<native::uinput_ff_erase, EvdevError> {
+        let effect_id = match self.ff.lock().unwrap().erases.get(&request_id) {
+            Some(id) => *id,
+            None => return Err(EvdevError::ErrnoError(libc::EINVAL)),
+        };
+        let mut erase: native::uinput_ff_erase = unsafe { std::mem::zeroed() };
+        erase.request_id = request_id;
+        erase.effect_id = effect_id as u32;
+        return Ok(erase);
+    }
+
+    fn end_ff_erase(&mut self, erase: &native::uinput_ff_erase) -> Result<(), EvdevError> {
+        let mut ff = self.ff.lock().unwrap();
+        if ff.erases.remove(&erase.request_id).is_none() {
+            return Err(EvdevError::ErrnoError(libc::EINVAL));
+        }
+        ff.results.insert(erase.request_id, erase.retval);
+        return Ok(());
+    }
+}
+
+/// `OutputSink` that writes the events to `writer` as text, one event per line, e.g. to log them.
+pub struct LogSink<W: Write + Send + Sync> {
+    writer: W,
+}
+
+impl<W: Write + Send + Sync> LogSink<W> {
+    pub fn new(writer: W) -> LogSink<W> {
+        return LogSink { writer };
+    }
+}
+
+impl LogSink<io::Stderr> {
+    pub fn stderr() -> LogSink<io::Stderr> {
+        return LogSink::new(io::stderr());
+    }
+}
+
+impl<W: Write + Send + Sync> fmt::Debug for LogSink<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "LogSink");
+    }
+}
+
+impl<W: Write + Send + Sync> OutputSink for LogSink<W> {
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError> {
+        writeln!(self.writer, "{}", ev)?;
+        if ev.is_syn_report() {
+            self.writer.flush()?;
+        }
+        return Ok(());
+    }
+}
+
+/// `OutputSink` that sends the events to a Unix domain socket, in the same binary format as
+/// /dev/input/event* (`struct input_event`), with the current time as the timestamps.
+#[derive(Debug)]
+pub struct UnixSocketSink {
+    stream: UnixStream,
+}
+
+impl UnixSocketSink {
+    /// Connect to the socket at `path`, which another program listens on.
+    pub fn connect(path: &Path) -> Result<UnixSocketSink, EvdevError> {
+        return Ok(UnixSocketSink {
+            stream: UnixStream::connect(path)?,
+        });
+    }
+}
+
+impl OutputSink for UnixSocketSink {
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        let ie = native::input_event {
+            time: native::timeval {
+                tv_sec: now.as_secs() as _,
+                tv_usec: now.subsec_micros() as _,
+            },
+            type_: ev.event_type as u16,
+            code: ev.code as u16,
+            value: ev.value,
+        };
+        let bytes = unsafe { std::slice::from_raw_parts(&ie as *const native::input_event as *const u8, std::mem::size_of::<native::input_event>()) };
+        self.stream.write_all(bytes)?;
+        return Ok(());
+    }
+}
+
+/// `OutputSink` that calls a callback with each event. `KeyRemapperConfiguration::on_out_event()` uses it.
+pub struct CallbackSink {
+    callback: Arc<dyn Fn(&InputEvent) + Send + Sync + 'static>,
+}
+
+impl CallbackSink {
+    pub fn new<F: Fn(&InputEvent) + Send + Sync + 'static>(callback: F) -> CallbackSink {
+        return CallbackSink { callback: Arc::new(callback) };
+    }
+}
+
+impl fmt::Debug for CallbackSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "CallbackSink");
+    }
+}
+
+impl OutputSink for CallbackSink {
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError> {
+        (*self.callback)(ev);
+        return Ok(());
+    }
+}
+
+#[test]
+fn test_uinput_sinks() {
+    use super::{ec, uinput::Uinput};
+
+    /// Sink that always fails, e.g. a closed socket.
+    #[derive(Debug)]
+    struct BrokenSink;
+
+    impl OutputSink for BrokenSink {
+        fn write_event(&mut self, _ev: &InputEvent) -> Result<(), EvdevError> {
+            return Err(EvdevError::ErrnoError(libc::EPIPE));
+        }
+    }
+
+    let main = MemorySink::new();
+    let tee = MemorySink::new();
+    let observed = Arc::new(Mutex::new(vec![]));
+
+    let uinput = Uinput::with_sink(Box::new(main.clone()));
+    uinput.add_sink(Box::new(tee.clone()));
+    uinput.add_sink(Box::new(BrokenSink));
+    {
+        let observed = observed.clone();
+        uinput.add_sink(Box::new(CallbackSink::new(move |ev| observed.lock().unwrap().push(ev.code))));
+    }
+    uinput.add_sink(Box::new(LogSink::new(vec![])));
+
+    // The redundant press isn't written to any sinks, and the broken sink doesn't affect the others.
+    uinput.send_events(&[InputEvent::new_key_event(ec::KEY_A, 1)]).unwrap();
+    uinput.send_events(&[InputEvent::new_key_event(ec::KEY_A, 1)]).unwrap();
+    uinput.reset().unwrap();
+
+    let expected = vec![
+        InputEvent::new_key_event(ec::KEY_A, 1),
+        InputEvent::new_syn_report(),
+        InputEvent::new_key_event(ec::KEY_A, 0),
+        InputEvent::new_syn_report(),
+    ];
+    assert_eq!(expected, main.events());
+    assert_eq!(expected, tee.events());
+    assert_eq!(vec![ec::KEY_A, ec::SYN_REPORT, ec::KEY_A, ec::SYN_REPORT], *observed.lock().unwrap());
+}
+
+#[test]
+fn test_uinput_sinks_reentrant() {
+    use super::{ec, uinput::Uinput};
+
+    let main = MemorySink::new();
+    let observed = Arc::new(Mutex::new(vec![]));
+    let uinput = Uinput::with_sink(Box::new(main.clone()));
+    {
+        // The sink may use the device, e.g. an `on_out_event` hook that sends events.
+        let uinput_clone = uinput.clone();
+        let observed = observed.clone();
+        uinput.add_sink(Box::new(CallbackSink::new(move |ev| {
+            observed.lock().unwrap().push((ev.code, ev.value, uinput_clone.key_state(ec::KEY_A)));
+            if ev.code == ec::KEY_A && ev.value == 1 {
+                uinput_clone.send_event(&InputEvent::new_key_event(ec::KEY_B, 1)).unwrap();
+            }
+        })));
+    }
+
+    uinput.send_event(&InputEvent::new_key_event(ec::KEY_A, 1)).unwrap();
+
+    let expected = vec![
+        InputEvent::new_key_event(ec::KEY_A, 1),
+        InputEvent::new_syn_report(),
+        InputEvent::new_key_event(ec::KEY_B, 1),
+        InputEvent::new_syn_report(),
+    ];
+    assert_eq!(expected, main.events());
+    assert_eq!(
+        vec![(ec::KEY_A, 1, 1), (ec::SYN_REPORT, 0, 1), (ec::KEY_B, 1, 1), (ec::SYN_REPORT, 0, 1)],
+        *observed.lock().unwrap()
+    );
+}
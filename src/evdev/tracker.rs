@@ -90,6 +90,7 @@ impl InputEventTracker {
                 reset_events.push(ev);
                 reset_events.push(InputEvent::new_syn_report());
                 callback(&ev)?;
+                callback(&InputEvent::new_syn_report())?;
             }
         }
         inner.key_states.clear();
@@ -98,6 +99,54 @@ impl InputEventTracker {
     }
 }
 
+/// `InputEventTracker`s of multiple input devices, keyed by the device ID, i.e. `EvdevDevice::id()`.
+/// The key state without a device ID is the aggregated one, i.e. the highest one across the devices.
+#[derive(Debug, Clone, Default)]
+pub struct PerDeviceTracker {
+    trackers: HashMap<String, InputEventTracker>,
+}
+
+impl PerDeviceTracker {
+    pub fn new() -> PerDeviceTracker {
+        return PerDeviceTracker { trackers: HashMap::new() };
+    }
+
+    pub fn on_event(&mut self, device_id: &str, ev: &InputEvent) {
+        if !self.trackers.contains_key(device_id) {
+            self.trackers.insert(device_id.to_string(), InputEventTracker::new());
+        }
+        self.trackers[device_id].on_event_sent(ev);
+    }
+
+    /// Aggregated key state across all the devices.
+    pub fn key_state(&self, code: i32) -> i32 {
+        return self.trackers.values().map(|t| t.key_state(code)).max().unwrap_or(0);
+    }
+
+    pub fn device_key_state(&self, device_id: &str, code: i32) -> i32 {
+        return match self.trackers.get(device_id) {
+            Some(tracker) => tracker.key_state(code),
+            None => 0,
+        };
+    }
+
+    /// Return the IDs of the devices where `code` is pressed, sorted.
+    pub fn devices_with_key_on(&self, code: i32) -> Vec<String> {
+        let mut ret: Vec<String> = self.trackers.iter().filter(|(_, t)| t.key_state(code) > 0).map(|(id, _)| id.clone()).collect();
+        ret.sort();
+        return ret;
+    }
+
+    /// Forget the state of a device, e.g. when it's removed.
+    pub fn remove_device(&mut self, device_id: &str) {
+        self.trackers.remove(device_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.trackers.clear();
+    }
+}
+
 #[test]
 fn test_input_event_tracker() {
     let et = InputEventTracker::new();
@@ -160,3 +209,25 @@ fn test_input_event_tracker() {
         assert_eq!(expected, reset_events);
     }
 }
+
+#[test]
+fn test_per_device_tracker() {
+    let mut trackers = PerDeviceTracker::new();
+    trackers.on_event("/dev/input/event1", &InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 1));
+    trackers.on_event("/dev/input/event2", &InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 1));
+    trackers.on_event("/dev/input/event2", &InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 2));
+
+    assert_eq!(2, trackers.key_state(ec::KEY_LEFTSHIFT));
+    assert_eq!(1, trackers.device_key_state("/dev/input/event1", ec::KEY_LEFTSHIFT));
+    assert_eq!(0, trackers.device_key_state("/dev/input/event3", ec::KEY_LEFTSHIFT));
+    assert_eq!(vec!["/dev/input/event1", "/dev/input/event2"], trackers.devices_with_key_on(ec::KEY_LEFTSHIFT));
+
+    // Released on one device, but still pressed on the other.
+    trackers.on_event("/dev/input/event2", &InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 0));
+    assert_eq!(1, trackers.key_state(ec::KEY_LEFTSHIFT));
+
+    // The device is unplugged while the key is pressed.
+    trackers.remove_device("/dev/input/event1");
+    assert_eq!(0, trackers.key_state(ec::KEY_LEFTSHIFT));
+    assert!(trackers.devices_with_key_on(ec::KEY_LEFTSHIFT).is_empty());
+}
@@ -1,7 +1,7 @@
 use std::{
     ffi::c_void,
     fs::{self, File},
-    os::unix::io::AsRawFd,
+    os::unix::io::{AsRawFd, RawFd},
     sync::Arc,
 };
 
@@ -9,9 +9,15 @@ use parking_lot::{ReentrantMutex, ReentrantMutexGuard, RwLock};
 
 use crate::native::{self, c_string_from_str};
 
-use super::{ec, EvdevError, EventsDescriptor};
+use super::sink::OutputSink;
+use super::{ec, DeviceIdentity, EvdevError, EventsDescriptor};
 use super::{InputEvent, InputEventTracker};
 
+nix::ioctl_readwrite!(ui_begin_ff_upload, b'U', 200, native::uinput_ff_upload);
+nix::ioctl_write_ptr!(ui_end_ff_upload, b'U', 201, native::uinput_ff_upload);
+nix::ioctl_readwrite!(ui_begin_ff_erase, b'U', 202, native::uinput_ff_erase);
+nix::ioctl_write_ptr!(ui_end_ff_erase, b'U', 203, native::uinput_ff_erase);
+
 #[derive(Debug, Clone, Copy)]
 struct UinputPtr {
     ptr: *mut native::libevdev_uinput,
@@ -24,7 +30,7 @@ unsafe impl Sync for UinputPtr {}
 struct RawUinput {
     name: String,
     file: Arc<File>,
-    fd: std::os::unix::io::RawFd,
+    fd: RawFd,
     uinput: UinputPtr,
 }
 
@@ -37,8 +43,10 @@ impl RawUinput {
             uinput: UinputPtr { ptr: uinput },
         };
     }
+}
 
-    fn send_event(&mut self, ev: &crate::evdev::InputEvent) -> Result<(), EvdevError> {
+impl OutputSink for RawUinput {
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError> {
         log::debug!("Writing event: {}", ev);
         let ret = unsafe { native::libevdev_uinput_write_event(self.uinput.ptr, ev.event_type as u32, ev.code as u32, ev.value) };
         if ret < 0 {
@@ -46,6 +54,53 @@ impl RawUinput {
         }
         return Ok(());
     }
+
+    fn fd(&self) -> Option<RawFd> {
+        return Some(self.fd);
+    }
+
+    /// Read the events written to the device by the host, e.g. EV_LED.
+    fn read_events(&self) -> Result<Vec<InputEvent>, EvdevError> {
+        let mut buf: [native::input_event; 16] = unsafe { std::mem::zeroed() };
+        let ret = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut c_void, std::mem::size_of_val(&buf)) };
+        if ret < 0 {
+            return Err(EvdevError::ErrnoError(errno::errno().0));
+        }
+        let count = ret as usize / std::mem::size_of::<native::input_event>();
+        return Ok(buf[..count].iter().map(InputEvent::from_native_input_event).collect());
+    }
+
+    fn begin_ff_upload(&mut self, request_id: u32) -> Result<native::uinput_ff_upload, EvdevError> {
+        let mut upload: native::uinput_ff_upload = unsafe { std::mem::zeroed() };
+        upload.request_id = request_id;
+        unsafe {
+            ui_begin_ff_upload(self.fd, &mut upload).map_err(|e| EvdevError::ErrnoError(e as i32))?;
+        }
+        return Ok(upload);
+    }
+
+    fn end_ff_upload(&mut self, upload: &native::uinput_ff_upload) -> Result<(), EvdevError> {
+        unsafe {
+            ui_end_ff_upload(self.fd, upload).map_err(|e| EvdevError::ErrnoError(e as i32))?;
+        }
+        return Ok(());
+    }
+
+    fn begin_ff_erase(&mut self, request_id: u32) -> Result<native::uinput_ff_erase, EvdevError> {
+        let mut erase: native::uinput_ff_erase = unsafe { std::mem::zeroed() };
+        erase.request_id = request_id;
+        unsafe {
+            ui_begin_ff_erase(self.fd, &mut erase).map_err(|e| EvdevError::ErrnoError(e as i32))?;
+        }
+        return Ok(erase);
+    }
+
+    fn end_ff_erase(&mut self, erase: &native::uinput_ff_erase) -> Result<(), EvdevError> {
+        unsafe {
+            ui_end_ff_erase(self.fd, erase).map_err(|e| EvdevError::ErrnoError(e as i32))?;
+        }
+        return Ok(());
+    }
 }
 
 impl Drop for RawUinput {
@@ -63,14 +118,33 @@ impl Drop for RawUinput {
 }
 
 /// Uinput with event tracking.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct UinputInner {
-    uinput: RawUinput,
+    sink: Box<dyn OutputSink>,
+
+    /// Sinks that receive a copy of the events. See `Uinput::add_sink()`.
+    extra_sinks: Vec<Box<dyn OutputSink>>,
+
+    /// Events written to `sink` that haven't been written to `extra_sinks` yet. See `Uinput::write_extra_sinks()`.
+    extra_events: Vec<InputEvent>,
+
+    /// True while `Uinput::write_extra_sinks()` has taken the sinks out of `extra_sinks`.
+    writing_extra_sinks: bool,
     event_tracker: InputEventTracker,
 }
 
 impl UinputInner {
-    fn new(name: &str, events: &EventsDescriptor) -> Result<UinputInner, EvdevError> {
+    fn with_sink(sink: Box<dyn OutputSink>) -> UinputInner {
+        return UinputInner {
+            sink: sink,
+            extra_sinks: vec![],
+            extra_events: vec![],
+            writing_extra_sinks: false,
+            event_tracker: InputEventTracker::new(),
+        };
+    }
+
+    fn new(name: &str, events: &EventsDescriptor, identity: Option<&DeviceIdentity>) -> Result<UinputInner, EvdevError> {
         if name.len() == 0 {
             return Err(EvdevError::UinputCreationError("Name must not be empty".to_string()));
         }
@@ -81,6 +155,16 @@ impl UinputInner {
             let dev = native::libevdev_new();
             native::libevdev_set_name(dev, c_string_from_str(name).as_ptr());
 
+            if let Some(identity) = identity {
+                native::libevdev_set_id_bustype(dev, identity.bustype);
+                native::libevdev_set_id_vendor(dev, identity.vendor);
+                native::libevdev_set_id_product(dev, identity.product);
+                native::libevdev_set_id_version(dev, identity.version);
+                if let Some(phys) = &identity.phys {
+                    native::libevdev_set_phys(dev, c_string_from_str(phys).as_ptr());
+                }
+            }
+
             for (event_type, codes) in &events.events {
                 match event_type {
                     ec::EventType::EV_SYN => continue,
@@ -117,15 +201,20 @@ impl UinputInner {
                 }
             }
 
+            for prop in &events.properties {
+                log::debug!("Enabling property {}...", prop);
+                let ret = native::libevdev_enable_property(dev, (*prop) as u32);
+                if ret != 0 {
+                    return Err(EvdevError::UinputCreationError("libevdev_enable_property failed".to_string()));
+                }
+            }
+
             let mut uinput: *mut native::libevdev_uinput = std::ptr::null_mut();
             let err = native::libevdev_uinput_create_from_device(dev, fd, &mut uinput);
             if err < 0 {
                 return Err(EvdevError::ErrnoError(-err));
             }
-            return Ok(UinputInner {
-                uinput: RawUinput::new(name.to_string(), file, fd, uinput),
-                event_tracker: InputEventTracker::new(),
-            });
+            return Ok(UinputInner::with_sink(Box::new(RawUinput::new(name.to_string(), file, fd, uinput))));
         }
     }
 
@@ -134,11 +223,18 @@ impl UinputInner {
         if !self.event_tracker.should_send(ev) {
             return Ok(()); // Redundant event, don't send.
         }
-        self.uinput.send_event(ev)?;
+        self.sink.write_event(ev)?;
+        if self.has_extra_sinks() {
+            self.extra_events.push(*ev);
+        }
         self.event_tracker.on_event_sent(ev);
         return Ok(());
     }
 
+    fn has_extra_sinks(&self) -> bool {
+        return !self.extra_sinks.is_empty() || self.writing_extra_sinks;
+    }
+
     fn send_syn_report(&mut self) -> Result<(), EvdevError> {
         self.send_event(&InputEvent::new_syn_report())?;
         return Ok(());
@@ -163,11 +259,16 @@ impl UinputInner {
         //     self.send_event(ev)?;
         // }
         // return Ok(());
+        let has_extra_sinks = self.has_extra_sinks();
         let event_tracker = &mut self.event_tracker;
-        let uinput = &mut self.uinput;
+        let sink = &mut self.sink;
+        let extra_events = &mut self.extra_events;
 
         event_tracker.reset_with_callback(|ev| {
-            uinput.send_event(ev)?;
+            sink.write_event(ev)?;
+            if has_extra_sinks {
+                extra_events.push(*ev);
+            }
             Ok(())
         })?;
         return Ok(());
@@ -189,33 +290,97 @@ impl Uinput {
     pub fn new(name: &str, events: &EventsDescriptor) -> Result<Uinput, EvdevError> {
         Ok(Uinput {
             lock: Arc::new(ReentrantMutex::new(())),
-            uinput: Arc::new(RwLock::new(UinputInner::new(name, events)?)),
+            uinput: Arc::new(RwLock::new(UinputInner::new(name, events, None)?)),
+        })
+    }
+
+    /// Same as `new()`, but also set the bus type, vendor / product IDs, version and phys.
+    pub fn with_identity(name: &str, events: &EventsDescriptor, identity: &DeviceIdentity) -> Result<Uinput, EvdevError> {
+        Ok(Uinput {
+            lock: Arc::new(ReentrantMutex::new(())),
+            uinput: Arc::new(RwLock::new(UinputInner::new(name, events, Some(identity))?)),
         })
     }
 
+    /// Create a `Uinput` that writes the events to `sink` instead of a uinput device, e.g. a
+    /// `sink::MemorySink` in tests. The redundant events are still dropped.
+    pub fn with_sink(sink: Box<dyn OutputSink>) -> Uinput {
+        return Uinput {
+            lock: Arc::new(ReentrantMutex::new(())),
+            uinput: Arc::new(RwLock::new(UinputInner::with_sink(sink))),
+        };
+    }
+
+    /// Also write the events to `sink`, e.g. a `sink::LogSink`. It receives the same events as the
+    /// uinput device, after the redundant ones are dropped. If it fails, it's removed.
+    ///
+    /// The sinks are written to after the events are sent, without holding the lock of the device, so
+    /// they may use this `Uinput`, e.g. send events, which are then written to the sinks too.
+    pub fn add_sink(&self, sink: Box<dyn OutputSink>) {
+        let _lock = self.lock();
+        self.uinput.write().extra_sinks.push(sink);
+    }
+
+    /// Write the events sent so far to the extra sinks. An extra sink that fails is removed, so that it
+    /// doesn't break the output. Events sent by the sinks themselves are written by the next iteration,
+    /// and a nested call returns without writing, so the sinks receive the events in order.
+    fn write_extra_sinks(&self) {
+        loop {
+            let (events, mut sinks) = {
+                let mut w = self.uinput.write();
+                if w.writing_extra_sinks || w.extra_events.is_empty() {
+                    return;
+                }
+                w.writing_extra_sinks = true;
+                (std::mem::take(&mut w.extra_events), std::mem::take(&mut w.extra_sinks))
+            };
+            for ev in &events {
+                sinks.retain_mut(|extra| match extra.write_event(ev) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        eprintln!("Removing output sink {:?}: {}", extra, e);
+                        false
+                    }
+                });
+            }
+            let mut w = self.uinput.write();
+            w.writing_extra_sinks = false;
+            sinks.append(&mut w.extra_sinks); // Added while writing.
+            w.extra_sinks = sinks;
+        }
+    }
+
     /// Send a single event with a SYN_REPORT.
     pub fn send_event(&self, ev: &crate::evdev::InputEvent) -> Result<(), EvdevError> {
         let _ = self.lock();
-        let mut w = self.uinput.write();
-        w.send_event(ev)?;
-        w.send_syn_report()?;
-        return Ok(());
+        let ret = {
+            let mut w = self.uinput.write();
+            w.send_event(ev).and_then(|_| w.send_syn_report())
+        };
+        self.write_extra_sinks();
+        return ret;
     }
 
     pub fn send_syn_report(&self) -> Result<(), EvdevError> {
         let _ = self.lock();
-        return self.uinput.write().send_syn_report();
+        let ret = self.uinput.write().send_syn_report();
+        self.write_extra_sinks();
+        return ret;
     }
 
     /// Send multiple events with a SYN_REPORT.
     pub fn send_events(&self, events: &[InputEvent]) -> Result<(), EvdevError> {
         let _ = self.lock();
-        return self.uinput.write().send_events(events);
+        let ret = self.uinput.write().send_events(events);
+        self.write_extra_sinks();
+        return ret;
     }
 
     pub fn reset(&self) -> Result<(), EvdevError> {
         let _ = self.lock();
-        return self.uinput.write().reset();
+        let ret = self.uinput.write().reset();
+        self.write_extra_sinks();
+        return ret;
     }
 
     pub fn key_state(&self, code: i32) -> i32 {
@@ -223,6 +388,49 @@ impl Uinput {
         return self.uinput.read().key_state(code);
     }
 
+    /// FD of the uinput device. Panics if it writes to another `OutputSink`; use `sink_fd()` for those.
+    pub fn fd(&self) -> RawFd {
+        return self.sink_fd().expect("Not a uinput device");
+    }
+
+    /// FD of the uinput device, or `None` if it writes to another `OutputSink`, e.g. a `sink::MemorySink`.
+    pub fn sink_fd(&self) -> Option<RawFd> {
+        return self.uinput.read().sink.fd();
+    }
+
+    /// Read the events written to the device by the host, e.g. EV_LED when Caps Lock is toggled.
+    /// The device needs to support them, and it blocks if there's no event.
+    pub fn read_events(&self) -> Result<Vec<InputEvent>, EvdevError> {
+        return self.uinput.read().sink.read_events();
+    }
+
+    /// Start handling a `UI_FF_UPLOAD` request, and return the effect being uploaded.
+    /// `end_ff_upload()` needs to be called with the result in `retval`.
+    pub(crate) fn begin_ff_upload(&self, request_id: u32) -> Result<native::uinput_ff_upload, EvdevError> {
+        return self.uinput.write().sink.begin_ff_upload(request_id);
+    }
+
+    pub(crate) fn end_ff_upload(&self, upload: &native::uinput_ff_upload) -> Result<(), EvdevError> {
+        return self.uinput.write().sink.end_ff_upload(upload);
+    }
+
+    /// Start handling a `UI_FF_ERASE` request. `end_ff_erase()` needs to be called with the result in `retval`.
+    pub(crate) fn begin_ff_erase(&self, request_id: u32) -> Result<native::uinput_ff_erase, EvdevError> {
+        return self.uinput.write().sink.begin_ff_erase(request_id);
+    }
+
+    pub(crate) fn end_ff_erase(&self, erase: &native::uinput_ff_erase) -> Result<(), EvdevError> {
+        return self.uinput.write().sink.end_ff_erase(erase);
+    }
+
+    /// Swap the sink, e.g. the uinput device, with `other`'s, keeping the extra sinks and the key
+    /// states. It's used to recreate the uinput device with other capabilities; the keys should be
+    /// released with `reset()` first.
+    pub(crate) fn swap_sink(&self, other: &Uinput) {
+        let _lock = self.lock();
+        std::mem::swap(&mut self.uinput.write().sink, &mut other.uinput.write().sink);
+    }
+
     pub fn lock(&self) -> ReentrantMutexGuard<()> {
         return self.lock.lock();
     }
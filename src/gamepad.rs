@@ -0,0 +1,548 @@
+//! Driving the keyboard and the mouse with a gamepad or a joystick.
+//! See `KeyRemapperConfiguration::set_gamepad_mapping()`.
+//!
+//! The axes are normalized with their `EvdevAbsInfo`: sticks to -1.0..1.0 around the center of the
+//! range, with `flat` around the center as 0, and triggers to 0.0..1.0. Sticks move the pointer or
+//! the wheel continuously while they're tilted, triggers and hats press keys.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::evdev::{
+    ec::{self, EventType},
+    EvdevAbsInfo, EventsDescriptor, InputEvent,
+};
+
+/// Maps a stick deflection, after the deadzone, to the speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+
+    /// `deflection ^ exponent`, e.g. 2.0 for finer control around the center.
+    Power(f64),
+}
+
+impl ResponseCurve {
+    fn apply(&self, value: f64) -> f64 {
+        match self {
+            ResponseCurve::Linear => return value,
+            ResponseCurve::Power(exponent) => return value.powf(*exponent),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StickOutput {
+    /// REL_X / REL_Y.
+    Pointer,
+
+    /// REL_HWHEEL / REL_WHEEL. Tilting the stick up scrolls up.
+    Wheel,
+}
+
+/// A stick that moves the pointer or the wheel.
+#[derive(Debug, Clone)]
+pub struct StickMapping {
+    pub(crate) x_axis: i32,
+    pub(crate) y_axis: i32,
+    pub(crate) output: StickOutput,
+    pub(crate) speed: f64,
+    pub(crate) deadzone: f64,
+    pub(crate) curve: ResponseCurve,
+}
+
+impl StickMapping {
+    /// `speed` is the pointer pixels or wheel clicks per second at the full deflection.
+    pub fn new(x_axis: i32, y_axis: i32, output: StickOutput, speed: f64) -> StickMapping {
+        return StickMapping {
+            x_axis,
+            y_axis,
+            output,
+            speed,
+            deadzone: 0.15,
+            curve: ResponseCurve::Power(2.0),
+        };
+    }
+
+    /// The stick is ignored while its deflection is below this, on top of the device's `flat`. The default is 0.15.
+    pub fn set_deadzone(&mut self, value: f64) -> &mut StickMapping {
+        if !(0.0..1.0).contains(&value) {
+            panic!("Deadzone must be between 0.0 and 1.0");
+        }
+        self.deadzone = value;
+        self
+    }
+
+    /// The default is `ResponseCurve::Power(2.0)`.
+    pub fn set_curve(&mut self, value: ResponseCurve) -> &mut StickMapping {
+        self.curve = value;
+        self
+    }
+}
+
+/// A trigger, or any other axis, that presses a key.
+#[derive(Debug, Clone)]
+pub struct TriggerMapping {
+    pub(crate) axis: i32,
+    pub(crate) code: i32,
+    pub(crate) press_threshold: f64,
+    pub(crate) release_threshold: f64,
+}
+
+impl TriggerMapping {
+    pub fn new(axis: i32, code: i32) -> TriggerMapping {
+        return TriggerMapping {
+            axis,
+            code,
+            press_threshold: 0.6,
+            release_threshold: 0.4,
+        };
+    }
+
+    /// The key is pressed when the trigger goes above `press` and released when it goes below `release`,
+    /// so it doesn't chatter around a single threshold. The gap is widened to the axis's `fuzz` if it's
+    /// narrower. The defaults are 0.6 and 0.4.
+    pub fn set_thresholds(&mut self, press: f64, release: f64) -> &mut TriggerMapping {
+        if release > press {
+            panic!("The release threshold must not be above the press threshold");
+        }
+        self.press_threshold = press;
+        self.release_threshold = release;
+        self
+    }
+}
+
+/// A hat, i.e. a pair of axes that are -1, 0 or 1, that presses keys.
+#[derive(Debug, Clone)]
+pub(crate) struct HatMapping {
+    x_axis: i32,
+    y_axis: i32,
+
+    /// Left, right, up, down.
+    keys: [i32; 4],
+}
+
+/// Configuration of the gamepad mapping.
+#[derive(Debug, Clone)]
+pub struct GamepadMapping {
+    pub(crate) sticks: Vec<StickMapping>,
+    pub(crate) triggers: Vec<TriggerMapping>,
+    pub(crate) hats: Vec<HatMapping>,
+    pub(crate) buttons: HashMap<i32, i32>,
+    pub(crate) interval: Duration,
+}
+
+impl GamepadMapping {
+    pub fn new() -> GamepadMapping {
+        return GamepadMapping {
+            sticks: vec![],
+            triggers: vec![],
+            hats: vec![],
+            buttons: HashMap::new(),
+            interval: Duration::from_millis(10),
+        };
+    }
+
+    pub fn add_stick(&mut self, stick: &StickMapping) -> &mut GamepadMapping {
+        self.sticks.push(stick.clone());
+        self
+    }
+
+    pub fn add_trigger(&mut self, trigger: &TriggerMapping) -> &mut GamepadMapping {
+        self.triggers.push(trigger.clone());
+        self
+    }
+
+    /// Press `left`, `right`, `up` or `down` while the hat is tilted, e.g. `ABS_HAT0X` and `ABS_HAT0Y`.
+    pub fn add_hat(&mut self, x_axis: i32, y_axis: i32, left: i32, right: i32, up: i32, down: i32) -> &mut GamepadMapping {
+        self.hats.push(HatMapping {
+            x_axis,
+            y_axis,
+            keys: [left, right, up, down],
+        });
+        self
+    }
+
+    /// Press `code` instead of the gamepad button `button`.
+    pub fn add_button(&mut self, button: i32, code: i32) -> &mut GamepadMapping {
+        self.buttons.insert(button, code);
+        self
+    }
+
+    /// Map the d-pad to the arrow keys, whether the gamepad reports it as the first hat or as buttons.
+    pub fn map_dpad_to_arrows(&mut self) -> &mut GamepadMapping {
+        self.add_hat(ec::ABS_HAT0X, ec::ABS_HAT0Y, ec::KEY_LEFT, ec::KEY_RIGHT, ec::KEY_UP, ec::KEY_DOWN);
+        self.add_button(ec::BTN_DPAD_LEFT, ec::KEY_LEFT)
+            .add_button(ec::BTN_DPAD_RIGHT, ec::KEY_RIGHT)
+            .add_button(ec::BTN_DPAD_UP, ec::KEY_UP)
+            .add_button(ec::BTN_DPAD_DOWN, ec::KEY_DOWN)
+    }
+
+    /// How often the sticks move the pointer or the wheel. The default is 10ms.
+    pub fn set_interval(&mut self, value: Duration) -> &mut GamepadMapping {
+        self.interval = value;
+        self
+    }
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        return GamepadMapping::new();
+    }
+}
+
+/// Map an axis value to -1.0..1.0 around the center of its range. Values within `flat` from the center are 0.
+pub fn normalize_axis(value: i32, info: &EvdevAbsInfo) -> f64 {
+    let half = (info.maximum - info.minimum) as f64 / 2.0;
+    let flat = info.flat as f64;
+    if half <= flat {
+        return 0.0;
+    }
+    let offset = value as f64 - (info.minimum as f64 + half);
+    if offset.abs() <= flat {
+        return 0.0;
+    }
+    return ((offset.abs() - flat) / (half - flat) * offset.signum()).clamp(-1.0, 1.0);
+}
+
+/// Map a trigger value to 0.0..1.0 of its range.
+pub fn normalize_trigger(value: i32, info: &EvdevAbsInfo) -> f64 {
+    let range = (info.maximum - info.minimum) as f64;
+    if range <= 0.0 {
+        return 0.0;
+    }
+    return ((value - info.minimum) as f64 / range).clamp(0.0, 1.0);
+}
+
+/// Axes and pressed keys of a single device.
+#[derive(Debug)]
+struct DeviceState {
+    abs_info: HashMap<i32, EvdevAbsInfo>,
+    values: HashMap<i32, i32>,
+
+    /// Whether each trigger is pressed.
+    triggers_on: Vec<bool>,
+
+    /// Keys pressed by the hats, by the axis.
+    hat_keys: HashMap<i32, i32>,
+}
+
+impl DeviceState {
+    fn axis(&self, axis: i32) -> f64 {
+        match (self.values.get(&axis), self.abs_info.get(&axis)) {
+            (Some(value), Some(info)) => return normalize_axis(*value, info),
+            _ => return 0.0,
+        }
+    }
+
+    /// Return the deflection of a stick as x, y and the magnitude.
+    fn stick(&self, stick: &StickMapping) -> (f64, f64, f64) {
+        let (x, y) = (self.axis(stick.x_axis), self.axis(stick.y_axis));
+        return (x, y, (x * x + y * y).sqrt());
+    }
+}
+
+/// State of the gamepads. Feed it the events with `on_event()`, and call `tick()` every interval while
+/// `is_ticking()`, from when `start_ticking()` returns true.
+#[derive(Debug)]
+pub(crate) struct GamepadState {
+    mapping: Option<GamepadMapping>,
+    devices: HashMap<String, DeviceState>,
+    last_tick: Option<Instant>,
+
+    /// True from `start_ticking()` until a `tick()` finds all the sticks in their deadzones.
+    ticking: bool,
+
+    /// Fractions of the REL events that haven't been sent yet, by the code.
+    remainders: HashMap<i32, f64>,
+}
+
+impl GamepadState {
+    pub(crate) fn new(mapping: Option<GamepadMapping>) -> GamepadState {
+        return GamepadState {
+            mapping,
+            devices: HashMap::new(),
+            last_tick: None,
+            ticking: false,
+            remainders: HashMap::new(),
+        };
+    }
+
+    pub(crate) fn mapping(&self) -> Option<&GamepadMapping> {
+        return self.mapping.as_ref();
+    }
+
+    /// Handle an event from a device. Returns the key events to send instead, or `None` if the event
+    /// isn't mapped and should be passed on. `supported_events` is called for the first event of the device.
+    pub(crate) fn on_event<F: FnOnce() -> EventsDescriptor>(&mut self, device_id: &str, supported_events: F, ev: &InputEvent) -> Option<Vec<(i32, i32)>> {
+        let mapping = self.mapping.as_ref()?;
+        match ev.event_type {
+            EventType::EV_KEY => {
+                let code = *mapping.buttons.get(&ev.code)?;
+                return Some(vec![(code, ev.value)]);
+            }
+            EventType::EV_ABS => {}
+            _ => return None,
+        }
+
+        let is_mapped = mapping.sticks.iter().any(|s| s.x_axis == ev.code || s.y_axis == ev.code)
+            || mapping.triggers.iter().any(|t| t.axis == ev.code)
+            || mapping.hats.iter().any(|h| h.x_axis == ev.code || h.y_axis == ev.code);
+        if !is_mapped {
+            return None;
+        }
+
+        let device = self.devices.entry(device_id.to_string()).or_insert_with(|| DeviceState {
+            abs_info: supported_events().abs_info,
+            values: HashMap::new(),
+            triggers_on: vec![false; mapping.triggers.len()],
+            hat_keys: HashMap::new(),
+        });
+        device.values.insert(ev.code, ev.value);
+
+        let mut keys = vec![];
+        for (i, trigger) in mapping.triggers.iter().enumerate().filter(|(_, t)| t.axis == ev.code) {
+            let info = device.abs_info.get(&ev.code).copied().unwrap_or_default();
+            let value = normalize_trigger(ev.value, &info);
+            let range = (info.maximum - info.minimum).max(1) as f64;
+            let release_threshold = trigger.release_threshold.min(trigger.press_threshold - info.fuzz as f64 / range);
+
+            let on = device.triggers_on[i];
+            if !on && value >= trigger.press_threshold {
+                device.triggers_on[i] = true;
+                keys.push((trigger.code, 1));
+            } else if on && value <= release_threshold {
+                device.triggers_on[i] = false;
+                keys.push((trigger.code, 0));
+            }
+        }
+        for hat in mapping.hats.iter() {
+            let (negative, positive) = if hat.x_axis == ev.code {
+                (hat.keys[0], hat.keys[1])
+            } else if hat.y_axis == ev.code {
+                (hat.keys[2], hat.keys[3])
+            } else {
+                continue;
+            };
+            let new_key = match ev.value {
+                v if v < 0 => Some(negative),
+                v if v > 0 => Some(positive),
+                _ => None,
+            };
+            let old_key = device.hat_keys.get(&ev.code).copied();
+            if old_key == new_key {
+                continue;
+            }
+            if let Some(key) = old_key {
+                keys.push((key, 0));
+                device.hat_keys.remove(&ev.code);
+            }
+            if let Some(key) = new_key {
+                keys.push((key, 1));
+                device.hat_keys.insert(ev.code, key);
+            }
+        }
+        return Some(keys);
+    }
+
+    /// Return true if any stick is out of its deadzone.
+    fn is_moving(&self) -> bool {
+        let mapping = match self.mapping.as_ref() {
+            Some(mapping) => mapping,
+            None => return false,
+        };
+        return mapping
+            .sticks
+            .iter()
+            .any(|stick| self.devices.values().any(|device| device.stick(stick).2 > stick.deadzone));
+    }
+
+    /// Call after `on_event()`. Returns true if a stick has just been tilted out of its deadzone, and the
+    /// caller should start calling `tick()`.
+    pub(crate) fn start_ticking(&mut self, now: Instant) -> bool {
+        if self.ticking || !self.is_moving() {
+            return false;
+        }
+        self.ticking = true;
+        self.last_tick = Some(now);
+        return true;
+    }
+
+    pub(crate) fn is_ticking(&self) -> bool {
+        return self.ticking;
+    }
+
+    /// Return the REL events to send for the sticks since the last tick. Stops ticking when all the sticks
+    /// are back in their deadzones.
+    pub(crate) fn tick(&mut self, now: Instant) -> Vec<(i32, i32)> {
+        let ret = self.stick_movements(now);
+        if !self.is_moving() {
+            self.ticking = false;
+            self.last_tick = None;
+        }
+        return ret;
+    }
+
+    fn stick_movements(&mut self, now: Instant) -> Vec<(i32, i32)> {
+        let elapsed = match self.last_tick.replace(now) {
+            Some(last) => now.saturating_duration_since(last).as_secs_f64(),
+            None => return vec![],
+        };
+        let mapping = match self.mapping.as_ref() {
+            Some(mapping) => mapping,
+            None => return vec![],
+        };
+
+        let mut movements: Vec<(i32, f64)> = vec![];
+        for stick in mapping.sticks.iter() {
+            for device in self.devices.values() {
+                let (x, y, magnitude) = device.stick(stick);
+                if magnitude <= stick.deadzone {
+                    continue;
+                }
+                let scaled = ((magnitude - stick.deadzone) / (1.0 - stick.deadzone)).min(1.0);
+                let speed = stick.curve.apply(scaled) * stick.speed * elapsed / magnitude;
+                match stick.output {
+                    StickOutput::Pointer => movements.extend_from_slice(&[(ec::REL_X, x * speed), (ec::REL_Y, y * speed)]),
+                    StickOutput::Wheel => movements.extend_from_slice(&[(ec::REL_HWHEEL, x * speed), (ec::REL_WHEEL, -y * speed)]),
+                }
+            }
+        }
+
+        let mut ret: Vec<(i32, i32)> = vec![];
+        for (code, movement) in movements {
+            let total = self.remainders.get(&code).copied().unwrap_or(0.0) + movement;
+            let value = total.trunc();
+            self.remainders.insert(code, total - value);
+            if value != 0.0 {
+                match ret.iter_mut().find(|(c, _)| *c == code) {
+                    Some((_, v)) => *v += value as i32,
+                    None => ret.push((code, value as i32)),
+                }
+            }
+        }
+        return ret;
+    }
+
+    /// Forget a device, e.g. when it's disconnected. Returns the releases of the keys pressed by its
+    /// triggers and hats, which won't come from the device anymore.
+    pub(crate) fn remove_device(&mut self, device_id: &str) -> Vec<(i32, i32)> {
+        let device = match self.devices.remove(device_id) {
+            Some(device) => device,
+            None => return vec![],
+        };
+        let mut ret = vec![];
+        if let Some(mapping) = self.mapping.as_ref() {
+            for (trigger, on) in mapping.triggers.iter().zip(device.triggers_on) {
+                if on {
+                    ret.push((trigger.code, 0));
+                }
+            }
+        }
+        let mut hat_keys: Vec<(i32, i32)> = device.hat_keys.into_iter().collect();
+        hat_keys.sort_unstable();
+        ret.extend(hat_keys.into_iter().map(|(_, key)| (key, 0)));
+        return ret;
+    }
+
+    /// Forget all the devices. A scheduled `tick()` still runs, and stops the ticking.
+    pub(crate) fn reset(&mut self) {
+        self.devices.clear();
+        self.last_tick = None;
+        self.remainders.clear();
+    }
+}
+
+#[test]
+fn test_gamepad_state() {
+    let stick_info = EvdevAbsInfo {
+        minimum: -32768,
+        maximum: 32767,
+        flat: 128,
+        ..Default::default()
+    };
+    let trigger_info = EvdevAbsInfo {
+        maximum: 255,
+        ..Default::default()
+    };
+    assert_eq!(0.0, normalize_axis(100, &stick_info));
+    assert_eq!(1.0, normalize_axis(32767, &stick_info));
+    assert_eq!(-1.0, normalize_axis(-32768, &stick_info));
+
+    let mut mapping = GamepadMapping::new();
+    mapping
+        .add_stick(StickMapping::new(ec::ABS_X, ec::ABS_Y, StickOutput::Pointer, 1000.0).set_curve(ResponseCurve::Linear))
+        .add_trigger(TriggerMapping::new(ec::ABS_RZ, ec::BTN_LEFT).set_thresholds(0.6, 0.4))
+        .map_dpad_to_arrows();
+    let mut state = GamepadState::new(Some(mapping));
+    let events = || {
+        let mut ret = EventsDescriptor::default();
+        ret.abs_info.insert(ec::ABS_X, stick_info);
+        ret.abs_info.insert(ec::ABS_Y, stick_info);
+        ret.abs_info.insert(ec::ABS_RZ, trigger_info);
+        ret
+    };
+    let abs = |code, value| InputEvent::new(EventType::EV_ABS, code, value);
+    let mut on_event = |ev: &InputEvent| state.on_event("pad", events, ev);
+
+    // The trigger has hysteresis.
+    assert_eq!(Some(vec![]), on_event(&abs(ec::ABS_RZ, 140)));
+    assert_eq!(Some(vec![(ec::BTN_LEFT, 1)]), on_event(&abs(ec::ABS_RZ, 160)));
+    assert_eq!(Some(vec![]), on_event(&abs(ec::ABS_RZ, 120)));
+    assert_eq!(Some(vec![(ec::BTN_LEFT, 0)]), on_event(&abs(ec::ABS_RZ, 90)));
+
+    // The hat and the d-pad buttons.
+    assert_eq!(Some(vec![(ec::KEY_LEFT, 1)]), on_event(&abs(ec::ABS_HAT0X, -1)));
+    assert_eq!(Some(vec![(ec::KEY_LEFT, 0), (ec::KEY_RIGHT, 1)]), on_event(&abs(ec::ABS_HAT0X, 1)));
+    assert_eq!(Some(vec![(ec::KEY_RIGHT, 0)]), on_event(&abs(ec::ABS_HAT0X, 0)));
+    assert_eq!(Some(vec![(ec::KEY_UP, 1)]), on_event(&InputEvent::new_key_event(ec::BTN_DPAD_UP, 1)));
+    assert_eq!(None, on_event(&InputEvent::new_key_event(ec::BTN_SOUTH, 1)));
+
+    // The stick moves the pointer at 1000px/s at the full deflection, keeping the fractions.
+    assert_eq!(Some(vec![]), on_event(&abs(ec::ABS_X, 32767)));
+    let start = Instant::now();
+    assert!(state.start_ticking(start));
+    assert_eq!(vec![(ec::REL_X, 10)], state.tick(start + Duration::from_millis(10)));
+    assert_eq!(vec![(ec::REL_X, 2)], state.tick(start + Duration::from_micros(12500)));
+    assert_eq!(vec![(ec::REL_X, 5)], state.tick(start + Duration::from_micros(17500)));
+    assert!(state.is_ticking());
+
+    // It stops ticking when the stick is released.
+    state.on_event("pad", events, &abs(ec::ABS_X, 0));
+    assert!(!state.start_ticking(start + Duration::from_millis(20)));
+    assert!(state.tick(start + Duration::from_millis(30)).is_empty());
+    assert!(!state.is_ticking());
+
+    // And starts again when it's tilted.
+    state.on_event("pad", events, &abs(ec::ABS_Y, -32768));
+    assert!(state.start_ticking(start + Duration::from_millis(40)));
+    assert!(!state.start_ticking(start + Duration::from_millis(45)));
+    assert_eq!(vec![(ec::REL_Y, -10)], state.tick(start + Duration::from_millis(50)));
+}
+
+#[test]
+fn test_gamepad_remove_device() {
+    let trigger_info = EvdevAbsInfo {
+        maximum: 255,
+        ..Default::default()
+    };
+    let mut mapping = GamepadMapping::new();
+    mapping.add_trigger(&TriggerMapping::new(ec::ABS_RZ, ec::BTN_LEFT)).map_dpad_to_arrows();
+    let mut state = GamepadState::new(Some(mapping));
+    let events = || {
+        let mut ret = EventsDescriptor::default();
+        ret.abs_info.insert(ec::ABS_RZ, trigger_info);
+        ret
+    };
+    let abs = |code, value| InputEvent::new(EventType::EV_ABS, code, value);
+
+    state.on_event("pad", events, &abs(ec::ABS_RZ, 255));
+    state.on_event("pad", events, &abs(ec::ABS_HAT0X, -1));
+    state.on_event("pad", events, &abs(ec::ABS_HAT0Y, 1));
+    state.on_event("pad", events, &abs(ec::ABS_HAT0Y, 0));
+
+    // The keys still pressed by the device are released.
+    assert_eq!(vec![(ec::BTN_LEFT, 0), (ec::KEY_LEFT, 0)], state.remove_device("pad"));
+    assert_eq!(Vec::<(i32, i32)>::new(), state.remove_device("pad"));
+}
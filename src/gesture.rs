@@ -0,0 +1,410 @@
+//! Touchpad gestures, i.e. swipes, pinches and taps with multiple fingers.
+//! See `KeyRemapperConfiguration::add_gesture()`.
+use core::fmt::Debug;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    evdev::{
+        ec::{self, EventType},
+        multitouch::MultitouchState,
+        EventsDescriptor, InputEvent,
+    },
+    modifiers::{ModifierRegistry, ModifierSpec},
+    validate_modifiers, KeyRemapper,
+};
+
+pub(crate) type GestureCallback = Arc<dyn Fn(&KeyRemapper) + Send + Sync + 'static>;
+
+/// The direction of a swipe. The Y axis of touchpads grows downwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// `In` when the fingers get closer, `Out` when they spread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinchDirection {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    Swipe { fingers: usize, direction: SwipeDirection },
+    Pinch { fingers: usize, direction: PinchDirection },
+    Tap { fingers: usize },
+}
+
+/// What to do when a gesture is detected.
+#[derive(Clone)]
+pub enum GestureAction {
+    /// Press a key with the given modifiers, using `KeyRemapper::press_key()`.
+    Press { code: i32, modifiers: String },
+
+    /// Call a callback.
+    Callback(GestureCallback),
+}
+
+impl Debug for GestureAction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GestureAction::Press { code, modifiers } => write!(f, "Press{{code: {}, modifiers: {:?}}}", code, modifiers),
+            GestureAction::Callback(_) => write!(f, "Callback{{...}}"),
+        }
+    }
+}
+
+/// A gesture bound to an action.
+#[derive(Debug, Clone)]
+pub struct GestureBinding {
+    pub(crate) gesture: Gesture,
+    pub(crate) action: GestureAction,
+}
+
+impl GestureBinding {
+    fn with_action(gesture: Gesture, action: GestureAction) -> GestureBinding {
+        let (fingers, min_fingers) = match gesture {
+            Gesture::Swipe { fingers, .. } | Gesture::Pinch { fingers, .. } => (fingers, 2),
+            Gesture::Tap { fingers } => (fingers, 1),
+        };
+        if fingers < min_fingers || fingers > 5 {
+            panic!("Invalid number of fingers for {:?}", gesture);
+        }
+        return GestureBinding { gesture, action };
+    }
+
+    /// When `gesture` is detected, press `code` with `modifiers`, using `KeyRemapper::press_key()`.
+    pub fn new(gesture: Gesture, code: i32, modifiers: &str) -> GestureBinding {
+        validate_modifiers(modifiers, "acsw<>*");
+        ModifierSpec::parse(ModifierRegistry::builtin(), modifiers);
+        return GestureBinding::with_action(
+            gesture,
+            GestureAction::Press {
+                code,
+                modifiers: modifiers.to_string(),
+            },
+        );
+    }
+
+    /// When `gesture` is detected, call `callback`.
+    pub fn with_callback<F: Fn(&KeyRemapper) + Send + Sync + 'static>(gesture: Gesture, callback: F) -> GestureBinding {
+        return GestureBinding::with_action(gesture, GestureAction::Callback(Arc::new(callback)));
+    }
+}
+
+/// Thresholds of the gestures. The distances are relative to the touchpad size, i.e. 1.0 is the
+/// width (for X) or height (for Y).
+#[derive(Debug, Clone)]
+pub struct GestureSettings {
+    pub(crate) swipe_distance: f64,
+    pub(crate) pinch_ratio: f64,
+    pub(crate) tap_time: Duration,
+    pub(crate) tap_movement: f64,
+}
+
+impl GestureSettings {
+    /// How far the fingers need to move for a swipe. The default is 0.2.
+    pub fn set_swipe_distance(&mut self, value: f64) -> &mut GestureSettings {
+        self.swipe_distance = value;
+        self
+    }
+
+    /// How much the distance between the fingers needs to change for a pinch, e.g. 0.3 for 30%,
+    /// which is the default.
+    pub fn set_pinch_ratio(&mut self, value: f64) -> &mut GestureSettings {
+        self.pinch_ratio = value;
+        self
+    }
+
+    /// The fingers need to be lifted within this duration for a tap. The default is 200ms.
+    pub fn set_tap_time(&mut self, value: Duration) -> &mut GestureSettings {
+        self.tap_time = value;
+        self
+    }
+
+    /// The fingers can't move more than this for a tap. The default is 0.03.
+    pub fn set_tap_movement(&mut self, value: f64) -> &mut GestureSettings {
+        self.tap_movement = value;
+        self
+    }
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        return GestureSettings {
+            swipe_distance: 0.2,
+            pinch_ratio: 0.3,
+            tap_time: Duration::from_millis(200),
+            tap_movement: 0.03,
+        };
+    }
+}
+
+/// A normalized finger position with its tracking ID.
+type Point = (i32, (f64, f64));
+
+/// From the fingers touching the pad to all of them lifted.
+#[derive(Debug)]
+struct Session {
+    started_at: Instant,
+    max_fingers: usize,
+
+    /// Positions to measure the movement from. Reset when the number of fingers changes.
+    baseline: Vec<Point>,
+
+    /// Only one gesture is detected per session.
+    detected: bool,
+    moved: bool,
+}
+
+/// Detects gestures from the contacts of a single device.
+#[derive(Debug)]
+pub(crate) struct GestureRecognizer {
+    settings: GestureSettings,
+    session: Option<Session>,
+}
+
+impl GestureRecognizer {
+    pub(crate) fn new(settings: GestureSettings) -> GestureRecognizer {
+        return GestureRecognizer { settings, session: None };
+    }
+
+    /// Handle the positions of the fingers on a frame, and return the gesture if one is detected.
+    pub(crate) fn on_frame(&mut self, points: &[Point], now: Instant) -> Option<Gesture> {
+        if points.is_empty() {
+            let session = self.session.take()?;
+            if !session.detected && !session.moved && now.saturating_duration_since(session.started_at) <= self.settings.tap_time {
+                return Some(Gesture::Tap { fingers: session.max_fingers });
+            }
+            return None;
+        }
+
+        let session = self.session.get_or_insert_with(|| Session {
+            started_at: now,
+            max_fingers: 0,
+            baseline: points.to_vec(),
+            detected: false,
+            moved: false,
+        });
+        session.max_fingers = session.max_fingers.max(points.len());
+
+        let same_fingers = session.baseline.len() == points.len() && session.baseline.iter().zip(points).all(|(a, b)| a.0 == b.0);
+        if !same_fingers {
+            session.baseline = points.to_vec();
+            return None;
+        }
+        if session.detected {
+            return None;
+        }
+
+        let max_movement = session.baseline.iter().zip(points).map(|(a, b)| distance(a.1, b.1)).fold(0.0, f64::max);
+        if max_movement > self.settings.tap_movement {
+            session.moved = true;
+        }
+        if points.len() < 2 {
+            return None;
+        }
+
+        let fingers = points.len();
+        let (start_center, start_spread) = center_and_spread(&session.baseline);
+        let (center, spread) = center_and_spread(points);
+        let ratio = if start_spread > 0.0 { spread / start_spread } else { 1.0 };
+        let (dx, dy) = (center.0 - start_center.0, center.1 - start_center.1);
+
+        let gesture = if (ratio - 1.0).abs() >= self.settings.pinch_ratio {
+            let direction = if ratio > 1.0 { PinchDirection::Out } else { PinchDirection::In };
+            Gesture::Pinch { fingers, direction }
+        } else if distance((0.0, 0.0), (dx, dy)) >= self.settings.swipe_distance {
+            let direction = match (dx.abs() > dy.abs(), dx > 0.0, dy > 0.0) {
+                (true, true, _) => SwipeDirection::Right,
+                (true, false, _) => SwipeDirection::Left,
+                (false, _, true) => SwipeDirection::Down,
+                (false, _, false) => SwipeDirection::Up,
+            };
+            Gesture::Swipe { fingers, direction }
+        } else {
+            return None;
+        };
+        session.detected = true;
+        return Some(gesture);
+    }
+
+    /// Forget the current session, e.g. when events were dropped.
+    pub(crate) fn reset(&mut self) {
+        self.session = None;
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    return ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+}
+
+/// Return the center of the fingers and their average distance from it.
+fn center_and_spread(points: &[Point]) -> ((f64, f64), f64) {
+    let n = points.len() as f64;
+    let center = (
+        points.iter().map(|(_, p)| p.0).sum::<f64>() / n,
+        points.iter().map(|(_, p)| p.1).sum::<f64>() / n,
+    );
+    let spread = points.iter().map(|(_, p)| distance(center, *p)).sum::<f64>() / n;
+    return (center, spread);
+}
+
+/// Multitouch states and gesture recognizers of all the devices. `usize`s are indexes to the bindings.
+#[derive(Debug)]
+pub(crate) struct GestureState {
+    bindings: Vec<GestureBinding>,
+    settings: GestureSettings,
+
+    /// `None` for devices that aren't multitouch.
+    devices: HashMap<String, Option<(MultitouchState, GestureRecognizer)>>,
+}
+
+impl GestureState {
+    pub(crate) fn new(bindings: Vec<GestureBinding>, settings: GestureSettings) -> GestureState {
+        return GestureState {
+            bindings,
+            settings,
+            devices: HashMap::new(),
+        };
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        return !self.bindings.is_empty();
+    }
+
+    pub(crate) fn binding(&self, index: usize) -> &GestureBinding {
+        return &self.bindings[index];
+    }
+
+    /// Handle an event from a device, and return the bindings of the detected gesture. `supported_events`
+    /// is called for the first event of the device.
+    pub(crate) fn on_event<F: FnOnce() -> EventsDescriptor>(&mut self, device_id: &str, supported_events: F, ev: &InputEvent, now: Instant) -> Vec<usize> {
+        let settings = &self.settings;
+        let state = self
+            .devices
+            .entry(device_id.to_string())
+            .or_insert_with(|| MultitouchState::from_events(&supported_events()).map(|mt| (mt, GestureRecognizer::new(settings.clone()))));
+        let (mt, recognizer) = match state {
+            Some(state) => state,
+            None => return vec![],
+        };
+        if ev.event_type == EventType::EV_SYN && ev.code == ec::SYN_DROPPED {
+            // The fingers may still be down, so it's not a lift; start over without detecting a tap.
+            recognizer.reset();
+        }
+        if !mt.on_event(ev) {
+            return vec![];
+        }
+        let points: Vec<Point> = mt.contacts().iter().map(|c| (c.tracking_id, mt.normalized_position(c))).collect();
+        let gesture = match recognizer.on_frame(&points, now) {
+            Some(gesture) => gesture,
+            None => return vec![],
+        };
+        log::debug!("Gesture detected: {:?}", gesture);
+        return self.bindings.iter().enumerate().filter(|(_, b)| b.gesture == gesture).map(|(i, _)| i).collect();
+    }
+
+    pub(crate) fn remove_device(&mut self, device_id: &str) {
+        self.devices.remove(device_id);
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.devices.clear();
+    }
+}
+
+#[test]
+fn test_gesture_recognizer() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut recognizer = GestureRecognizer::new(GestureSettings::default());
+
+    // Three-finger swipe up; only one gesture per session.
+    assert_eq!(None, recognizer.on_frame(&[(1, (0.4, 0.8)), (2, (0.5, 0.8)), (3, (0.6, 0.8))], at(0)));
+    assert_eq!(None, recognizer.on_frame(&[(1, (0.4, 0.7)), (2, (0.5, 0.7)), (3, (0.6, 0.7))], at(20)));
+    assert_eq!(
+        Some(Gesture::Swipe {
+            fingers: 3,
+            direction: SwipeDirection::Up
+        }),
+        recognizer.on_frame(&[(1, (0.4, 0.5)), (2, (0.5, 0.5)), (3, (0.6, 0.5))], at(40))
+    );
+    assert_eq!(None, recognizer.on_frame(&[(1, (0.4, 0.2)), (2, (0.5, 0.2)), (3, (0.6, 0.2))], at(60)));
+    assert_eq!(None, recognizer.on_frame(&[], at(80)));
+
+    // Pinch out.
+    recognizer.on_frame(&[(4, (0.45, 0.5)), (5, (0.55, 0.5))], at(100));
+    assert_eq!(
+        Some(Gesture::Pinch {
+            fingers: 2,
+            direction: PinchDirection::Out
+        }),
+        recognizer.on_frame(&[(4, (0.4, 0.5)), (5, (0.6, 0.5))], at(120))
+    );
+    recognizer.on_frame(&[], at(140));
+
+    // Two-finger tap, with the fingers landing and lifting at slightly different times.
+    recognizer.on_frame(&[(6, (0.3, 0.3))], at(200));
+    recognizer.on_frame(&[(6, (0.3, 0.3)), (7, (0.5, 0.3))], at(210));
+    recognizer.on_frame(&[(7, (0.5, 0.3))], at(300));
+    assert_eq!(Some(Gesture::Tap { fingers: 2 }), recognizer.on_frame(&[], at(310)));
+
+    // Too slow for a tap.
+    recognizer.on_frame(&[(8, (0.3, 0.3))], at(400));
+    assert_eq!(None, recognizer.on_frame(&[], at(700)));
+}
+
+#[test]
+fn test_gesture_state_syn_dropped() {
+    use crate::evdev::EvdevAbsInfo;
+
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let abs = |code, value| InputEvent::new(EventType::EV_ABS, code, value);
+    let syn = InputEvent::new_syn_report();
+    let touchpad = || {
+        let mut events = EventsDescriptor::new();
+        for (code, maximum) in [(ec::ABS_MT_SLOT, 4), (ec::ABS_MT_POSITION_X, 1000), (ec::ABS_MT_POSITION_Y, 500)] {
+            events.abs_info.insert(code, EvdevAbsInfo { maximum, ..Default::default() });
+        }
+        return events;
+    };
+    let mut state = GestureState::new(
+        vec![GestureBinding::new(Gesture::Tap { fingers: 1 }, ec::KEY_A, "")],
+        GestureSettings::default(),
+    );
+
+    // A tap.
+    for ev in [
+        abs(ec::ABS_MT_TRACKING_ID, 1),
+        abs(ec::ABS_MT_POSITION_X, 100),
+        abs(ec::ABS_MT_POSITION_Y, 100),
+        syn,
+    ] {
+        assert_eq!(Vec::<usize>::new(), state.on_event("touchpad", touchpad, &ev, at(0)));
+    }
+    state.on_event("touchpad", touchpad, &abs(ec::ABS_MT_TRACKING_ID, -1), at(50));
+    assert_eq!(vec![0], state.on_event("touchpad", touchpad, &syn, at(50)));
+
+    // Dropped events while the finger is down aren't a tap, and neither is the lift after them.
+    for ev in [
+        abs(ec::ABS_MT_TRACKING_ID, 2),
+        abs(ec::ABS_MT_POSITION_X, 100),
+        abs(ec::ABS_MT_POSITION_Y, 100),
+        syn,
+    ] {
+        state.on_event("touchpad", touchpad, &ev, at(100));
+    }
+    let dropped = InputEvent::new(EventType::EV_SYN, ec::SYN_DROPPED, 0);
+    assert_eq!(Vec::<usize>::new(), state.on_event("touchpad", touchpad, &dropped, at(120)));
+    state.on_event("touchpad", touchpad, &abs(ec::ABS_MT_TRACKING_ID, -1), at(150));
+    assert_eq!(Vec::<usize>::new(), state.on_event("touchpad", touchpad, &syn, at(150)));
+}
@@ -0,0 +1,91 @@
+//! Hold-through key mappings, where a target key is held as long as a source key is.
+//! See `KeyRemapper::hold_key()`.
+use std::collections::HashMap;
+
+use crate::evdev::input_event::Modifers;
+
+/// A target key held on behalf of a source key. `S` is the saved out-modifier state, which is only
+/// set when the hold replaces the modifiers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ActiveHold<S> {
+    pub(crate) code: i32,
+    pub(crate) source_modifiers: Modifers,
+    pub(crate) saved_out_modifiers: Option<S>,
+}
+
+/// Active holds, indexed by the source key.
+#[derive(Debug)]
+pub(crate) struct HoldState<S> {
+    holds: HashMap<i32, ActiveHold<S>>,
+}
+
+impl<S> HoldState<S> {
+    pub(crate) fn new() -> HoldState<S> {
+        return HoldState { holds: HashMap::new() };
+    }
+
+    pub(crate) fn get(&self, source: i32) -> Option<&ActiveHold<S>> {
+        return self.holds.get(&source);
+    }
+
+    pub(crate) fn insert(&mut self, source: i32, hold: ActiveHold<S>) {
+        self.holds.insert(source, hold);
+    }
+
+    pub(crate) fn take(&mut self, source: i32) -> Option<ActiveHold<S>> {
+        return self.holds.remove(&source);
+    }
+
+    /// Remove and return the holds that need to be released before starting a new hold of `source`:
+    /// the existing hold of the same source, and if the new hold replaces the modifiers, the other
+    /// holds that do too, because the modifiers can only be replaced by one hold at a time.
+    pub(crate) fn take_conflicts(&mut self, source: i32, replaces_modifiers: bool) -> Vec<ActiveHold<S>> {
+        return self.take_if(|s, hold| s == source || (replaces_modifiers && hold.saved_out_modifiers.is_some()));
+    }
+
+    /// Remove and return the holds that were started with different modifiers than `modifiers`.
+    pub(crate) fn take_changed(&mut self, modifiers: Modifers) -> Vec<ActiveHold<S>> {
+        return self.take_if(|_, hold| hold.source_modifiers != modifiers);
+    }
+
+    pub(crate) fn take_all(&mut self) -> Vec<ActiveHold<S>> {
+        return self.take_if(|_, _| true);
+    }
+
+    /// Remove and return the matching holds, ordered by the source key.
+    fn take_if<F: Fn(i32, &ActiveHold<S>) -> bool>(&mut self, pred: F) -> Vec<ActiveHold<S>> {
+        let mut sources: Vec<i32> = self.holds.iter().filter(|(s, hold)| pred(**s, hold)).map(|(s, _)| *s).collect();
+        sources.sort_unstable();
+        return sources.into_iter().filter_map(|s| self.holds.remove(&s)).collect();
+    }
+}
+
+#[test]
+fn test_hold_state() {
+    use crate::{evdev::ec, modifiers::ModifierRegistry};
+
+    let modifier_esc = ModifierRegistry::new().build(|name, _, _| name == 'e');
+
+    let hold = |code: i32, source_modifiers: Modifers, saved: Option<u8>| ActiveHold {
+        code,
+        source_modifiers,
+        saved_out_modifiers: saved,
+    };
+
+    let mut state: HoldState<u8> = HoldState::new();
+    state.insert(ec::KEY_LEFT, hold(ec::KEY_VOLUMEDOWN, modifier_esc, None));
+    state.insert(ec::KEY_A, hold(ec::KEY_HOME, 0, Some(1)));
+    assert_eq!(Some(ec::KEY_VOLUMEDOWN), state.get(ec::KEY_LEFT).map(|h| h.code));
+
+    // Holds that don't replace the modifiers can coexist.
+    assert!(state.take_conflicts(ec::KEY_RIGHT, false).is_empty());
+    assert_eq!(vec![hold(ec::KEY_HOME, 0, Some(1))], state.take_conflicts(ec::KEY_B, true));
+    state.insert(ec::KEY_B, hold(ec::KEY_END, modifier_esc, Some(2)));
+
+    // ESC is released. The holds are ordered by the source key.
+    assert_eq!(
+        vec![hold(ec::KEY_END, modifier_esc, Some(2)), hold(ec::KEY_VOLUMEDOWN, modifier_esc, None)],
+        state.take_changed(0)
+    );
+    assert!(state.take_all().is_empty());
+}
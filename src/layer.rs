@@ -0,0 +1,237 @@
+//! Layer stack, i.e. modes that change what keys do. See `KeyRemapperConfiguration::add_layer()`.
+//!
+//! Active layers form a stack. Each event goes to the top layer's handler first, and if it returns
+//! false, to the next layer down, and finally to the base layer, which is `on_event`.
+use core::fmt::Debug;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    evdev::{EvdevDevice, InputEvent},
+    rules::Rules,
+    KeyRemapper,
+};
+
+/// Handles an event on a layer, and returns true if it handled it, or false to let it fall through
+/// to the layer below.
+pub(crate) type LayerHandler = Arc<dyn Fn(&KeyRemapper, &EvdevDevice, &InputEvent) -> bool + Send + Sync + 'static>;
+
+/// Configuration of a single layer.
+#[derive(Clone)]
+pub struct Layer {
+    pub(crate) name: String,
+    pub(crate) icon: Option<PathBuf>,
+    pub(crate) notification: Option<(String, Duration)>,
+    pub(crate) momentary_key: Option<i32>,
+    pub(crate) on_event: Option<LayerHandler>,
+}
+
+impl Debug for Layer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Layer{{name: {:?}, icon: {:?}, momentary_key: {:?}}}",
+            self.name, self.icon, self.momentary_key
+        )
+    }
+}
+
+impl Layer {
+    pub fn new(name: &str) -> Layer {
+        return Layer {
+            name: name.to_string(),
+            icon: None,
+            notification: None,
+            momentary_key: None,
+            on_event: None,
+        };
+    }
+
+    /// Tray icon shown while the layer is the top layer.
+    pub fn set_icon<T: Into<PathBuf>>(&mut self, path: T) -> &mut Layer {
+        self.icon = Some(path.into());
+        self
+    }
+
+    /// Notification shown when the layer becomes the top layer.
+    pub fn set_notification(&mut self, message: &str, timeout: Duration) -> &mut Layer {
+        self.notification = Some((message.to_string(), timeout));
+        self
+    }
+
+    /// Activate the layer only while `code` is pressed. Events of the key itself are swallowed.
+    pub fn set_momentary_key(&mut self, code: i32) -> &mut Layer {
+        self.momentary_key = Some(code);
+        self
+    }
+
+    /// Handle events with `callback`. When it returns false, the event falls through to the layer below.
+    /// Without a handler, all the events fall through.
+    pub fn on_event<F: Fn(&KeyRemapper, &EvdevDevice, &InputEvent) -> bool + Send + Sync + 'static>(&mut self, callback: F) -> &mut Layer {
+        self.on_event = Some(Arc::new(callback));
+        self
+    }
+
+    /// Handle events with `Rules`. Events that don't match any rule fall through.
+    pub fn set_rules(&mut self, rules: Rules) -> &mut Layer {
+        self.on_event(move |km, _device, ev| rules.handle_event(km, ev))
+    }
+}
+
+/// Where an event should go. `None` means the base layer.
+pub(crate) type LayerTarget = Option<usize>;
+
+/// State of the layer stack. Layers are referred to by their indexes.
+#[derive(Debug)]
+pub(crate) struct LayerStack {
+    layers: Vec<Layer>,
+
+    /// Active layers, from the bottom to the top.
+    active: Vec<usize>,
+
+    /// Where the key down event of each pressed key went.
+    key_owners: HashMap<i32, LayerTarget>,
+}
+
+impl LayerStack {
+    pub(crate) fn new(layers: Vec<Layer>) -> LayerStack {
+        return LayerStack {
+            layers,
+            active: vec![],
+            key_owners: HashMap::new(),
+        };
+    }
+
+    pub(crate) fn layer(&self, index: usize) -> &Layer {
+        return &self.layers[index];
+    }
+
+    /// Find a layer by name. Panics if there's no such layer.
+    pub(crate) fn find(&self, name: &str) -> usize {
+        match self.layers.iter().position(|l| l.name == name) {
+            Some(index) => return index,
+            None => panic!("Unknown layer: {}", name),
+        }
+    }
+
+    pub(crate) fn top(&self) -> Option<usize> {
+        return self.active.last().copied();
+    }
+
+    pub(crate) fn is_active(&self, index: usize) -> bool {
+        return self.active.contains(&index);
+    }
+
+    pub(crate) fn active_names(&self) -> Vec<String> {
+        return self.active.iter().map(|i| self.layers[*i].name.clone()).collect();
+    }
+
+    /// Activate a layer. If it's already active, it's moved to the top.
+    pub(crate) fn push(&mut self, index: usize) {
+        self.active.retain(|i| *i != index);
+        self.active.push(index);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<usize> {
+        return self.active.pop();
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) {
+        self.active.retain(|i| *i != index);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.active.clear();
+    }
+
+    /// Return the layer whose momentary key is `code`.
+    pub(crate) fn find_momentary(&self, code: i32) -> Option<usize> {
+        return self.layers.iter().position(|l| l.momentary_key == Some(code));
+    }
+
+    /// Return where to try an event, in order.
+    ///
+    /// Key repeat and up events go to where the key down event went, even if the layer has been
+    /// deactivated since, so keys don't get stuck when the layer changes while they're pressed.
+    pub(crate) fn route(&self, ev: &InputEvent) -> Vec<LayerTarget> {
+        if ev.is_key_event() && !ev.is_key_down_event() {
+            match self.key_owners.get(&ev.code) {
+                Some(None) => return vec![None],
+                Some(owner) => return vec![*owner, None],
+                None => {}
+            }
+        }
+        let mut ret: Vec<LayerTarget> = self.active.iter().rev().map(|i| Some(*i)).collect();
+        ret.push(None);
+        return ret;
+    }
+
+    /// Record where an event went.
+    pub(crate) fn set_owner(&mut self, ev: &InputEvent, owner: LayerTarget) {
+        if ev.is_key_down_event() {
+            self.key_owners.insert(ev.code, owner);
+        } else if ev.is_key_up_event() {
+            self.key_owners.remove(&ev.code);
+        }
+    }
+
+    /// Forget the pressed keys and deactivate the momentary layers, e.g. when the devices are lost.
+    pub(crate) fn reset_keys(&mut self) {
+        self.key_owners.clear();
+        let layers = &self.layers;
+        self.active.retain(|i| layers[*i].momentary_key.is_none());
+    }
+}
+
+#[test]
+fn test_layer_stack() {
+    use crate::evdev::ec;
+
+    let mut stack = LayerStack::new(vec![
+        Layer::new("a"),
+        Layer::new("b"),
+        Layer::new("m").set_momentary_key(ec::KEY_CAPSLOCK).clone(),
+    ]);
+    assert_eq!(None, stack.top());
+    assert_eq!(Some(2), stack.find_momentary(ec::KEY_CAPSLOCK));
+    assert_eq!(None, stack.find_momentary(ec::KEY_A));
+
+    stack.push(stack.find("a"));
+    stack.push(stack.find("b"));
+    assert_eq!(vec!["a", "b"], stack.active_names());
+    stack.push(stack.find("a"));
+    assert_eq!(vec!["b", "a"], stack.active_names());
+    assert_eq!(Some(0), stack.pop());
+    assert_eq!(Some(1), stack.top());
+
+    stack.push(2);
+    stack.reset_keys();
+    assert_eq!(vec!["b"], stack.active_names());
+}
+
+#[test]
+fn test_layer_stack_route() {
+    use crate::evdev::ec;
+
+    let mut stack = LayerStack::new(vec![Layer::new("a"), Layer::new("b")]);
+    stack.push(0);
+    stack.push(1);
+
+    let down = InputEvent::new_key_event(ec::KEY_A, 1);
+    let repeat = InputEvent::new_key_event(ec::KEY_A, 2);
+    let up = InputEvent::new_key_event(ec::KEY_A, 0);
+    assert_eq!(vec![Some(1), Some(0), None], stack.route(&down));
+
+    // "a" handles the key down, and then the layer changes; the rest still goes to "a".
+    stack.set_owner(&down, Some(0));
+    stack.remove(0);
+    assert_eq!(vec![Some(0), None], stack.route(&repeat));
+    assert_eq!(vec![Some(0), None], stack.route(&up));
+    stack.set_owner(&up, Some(0));
+    assert_eq!(vec![Some(1), None], stack.route(&up));
+
+    stack.set_owner(&down, None);
+    assert_eq!(vec![None], stack.route(&up));
+
+    // Non-key events always go through the whole stack.
+    assert_eq!(vec![Some(1), None], stack.route(&InputEvent::new(ec::EventType::EV_REL, ec::REL_X, 1)));
+}
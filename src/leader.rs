@@ -0,0 +1,475 @@
+//! Vim-style leader key sequences, e.g. ESC, then G, C -> run an action.
+//! See `KeyRemapperConfiguration::set_leader()`.
+use core::fmt::Debug;
+use std::{
+    collections::{BTreeMap, HashSet},
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    evdev::{ec, InputEvent},
+    KeyRemapper,
+};
+
+pub(crate) type LeaderCallback = Arc<dyn Fn(&KeyRemapper) + Send + Sync + 'static>;
+
+/// A single sequence following the leader key.
+#[derive(Clone)]
+pub struct LeaderSequence {
+    pub(crate) keys: Vec<i32>,
+    pub(crate) description: String,
+    pub(crate) callback: LeaderCallback,
+}
+
+impl Debug for LeaderSequence {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "LeaderSequence{{keys: {:?}, description: {:?}}}", self.keys, self.description)
+    }
+}
+
+/// Trie of the sequences. `sequence` is an index to `Leader::sequences`.
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: BTreeMap<i32, TrieNode>,
+    sequence: Option<usize>,
+}
+
+/// Configuration of the leader key and the sequences following it.
+#[derive(Debug, Clone)]
+pub struct Leader {
+    pub(crate) code: i32,
+    pub(crate) timeout: Duration,
+    pub(crate) sequences: Vec<LeaderSequence>,
+    root: TrieNode,
+}
+
+fn key_name(code: i32) -> String {
+    let name = ec::get_code_name(ec::EV_KEY, code);
+    return name.strip_prefix("KEY_").unwrap_or(name).to_string();
+}
+
+impl Leader {
+    /// `code` is the leader key.
+    pub fn new(code: i32) -> Leader {
+        return Leader {
+            code,
+            timeout: Duration::from_secs(1),
+            sequences: vec![],
+            root: TrieNode::default(),
+        };
+    }
+
+    /// If no key is pressed within this duration after the previous one, the sequence is aborted,
+    /// or completed if the keys so far form a sequence. The default is 1 second.
+    pub fn set_timeout(&mut self, value: Duration) -> &mut Leader {
+        self.timeout = value;
+        self
+    }
+
+    /// Call `callback` when the leader key is followed by `keys`. `description` is shown in the hint.
+    pub fn add_sequence<F: Fn(&KeyRemapper) + Send + Sync + 'static>(&mut self, keys: &[i32], description: &str, callback: F) -> &mut Leader {
+        if keys.is_empty() {
+            panic!("A leader sequence needs at least 1 key");
+        }
+        let mut node = &mut self.root;
+        for key in keys {
+            node = node.children.entry(*key).or_default();
+        }
+        if node.sequence.is_some() {
+            panic!("Leader sequence {:?} is already added", keys);
+        }
+        node.sequence = Some(self.sequences.len());
+        self.sequences.push(LeaderSequence {
+            keys: keys.to_vec(),
+            description: description.to_string(),
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    fn find_node(&self, keys: &[i32]) -> Option<&TrieNode> {
+        let mut node = &self.root;
+        for key in keys {
+            node = node.children.get(key)?;
+        }
+        return Some(node);
+    }
+}
+
+/// Output of `LeaderState`. `usize`s are indexes to the `LeaderSequence`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LeaderOutput<P> {
+    /// Pass the event to the next stage.
+    Event(InputEvent, P),
+
+    /// Send the swallowed events to the uinput device as-is, because the sequence didn't match.
+    /// The later repeat and up events of the keys that were still pressed are sent the same way.
+    Replay(Vec<InputEvent>),
+
+    /// Show a hint of the pending sequence for the given duration.
+    Hint(String, Duration),
+
+    /// Close the hint, because the sequence is finished.
+    ClearHint,
+
+    Sequence(usize),
+}
+
+/// State machine of the leader sequences. Like `TapHoldState`, it's generic over the payload `P`.
+///
+/// Non-key events, and events of the keys that were already pressed before the leader key,
+/// always go through.
+#[derive(Debug)]
+pub(crate) struct LeaderState<P> {
+    leader: Option<Leader>,
+
+    /// Keys pressed after the leader key, or `None` if no sequence is pending.
+    prefix: Option<Vec<i32>>,
+    last_key_at: Option<Instant>,
+    swallowed: Vec<InputEvent>,
+
+    /// Keys pressed since the leader key, including itself, that haven't been released yet.
+    pressed: HashSet<i32>,
+
+    /// Keys of the completed sequence that haven't been released yet.
+    swallowing: HashSet<i32>,
+
+    /// Keys of the aborted sequence that haven't been released yet. Their key down events were replayed,
+    /// so their repeat and up events are replayed too, instead of going to the next stage.
+    replaying: HashSet<i32>,
+
+    _payload: PhantomData<P>,
+}
+
+impl<P> LeaderState<P> {
+    pub(crate) fn new(leader: Option<Leader>) -> LeaderState<P> {
+        return LeaderState {
+            leader,
+            prefix: None,
+            last_key_at: None,
+            swallowed: vec![],
+            pressed: HashSet::new(),
+            swallowing: HashSet::new(),
+            replaying: HashSet::new(),
+            _payload: PhantomData,
+        };
+    }
+
+    pub(crate) fn sequence(&self, index: usize) -> &LeaderSequence {
+        return &self.leader.as_ref().unwrap().sequences[index];
+    }
+
+    /// Deadline of the pending sequence, if any.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.prefix.as_ref()?;
+        return Some(self.last_key_at? + self.leader.as_ref()?.timeout);
+    }
+
+    pub(crate) fn on_event(&mut self, ev: &InputEvent, payload: P, now: Instant) -> Vec<LeaderOutput<P>> {
+        let mut out = vec![];
+        self.check_timeout(now, &mut out);
+        self.process(ev, payload, now, &mut out);
+        return out;
+    }
+
+    /// Finish the pending sequence if the timeout has passed.
+    pub(crate) fn on_timeout(&mut self, now: Instant) -> Vec<LeaderOutput<P>> {
+        let mut out = vec![];
+        self.check_timeout(now, &mut out);
+        return out;
+    }
+
+    /// Forget all the state, e.g. when the devices are lost. The swallowed events are dropped.
+    pub(crate) fn reset(&mut self) {
+        self.prefix = None;
+        self.last_key_at = None;
+        self.swallowed.clear();
+        self.pressed.clear();
+        self.swallowing.clear();
+        self.replaying.clear();
+    }
+
+    /// Return the hint text, e.g. "ESC G ...", followed by the possible next keys.
+    fn hint(&self) -> String {
+        let leader = self.leader.as_ref().unwrap();
+        let prefix = self.prefix.as_ref().unwrap();
+
+        let mut ret = key_name(leader.code);
+        for key in prefix {
+            ret.push(' ');
+            ret.push_str(&key_name(*key));
+        }
+        ret.push_str(" ...");
+
+        if let Some(node) = leader.find_node(prefix) {
+            for (key, child) in &node.children {
+                ret.push_str(&format!("\n{}: ", key_name(*key)));
+                match child.sequence {
+                    Some(index) => ret.push_str(&leader.sequences[index].description),
+                    None => ret.push_str("..."),
+                }
+            }
+        }
+        return ret;
+    }
+
+    fn check_timeout(&mut self, now: Instant, out: &mut Vec<LeaderOutput<P>>) {
+        if let Some(deadline) = self.next_deadline() {
+            if now >= deadline {
+                // If the keys so far form a sequence, run it even though longer ones exist.
+                let leader = self.leader.as_ref().unwrap();
+                match leader.find_node(self.prefix.as_ref().unwrap()).and_then(|node| node.sequence) {
+                    Some(index) => self.complete(index, out),
+                    None => self.abort(out),
+                }
+            }
+        }
+    }
+
+    fn complete(&mut self, index: usize, out: &mut Vec<LeaderOutput<P>>) {
+        out.push(LeaderOutput::ClearHint);
+        out.push(LeaderOutput::Sequence(index));
+        self.swallowing.extend(self.pressed.drain());
+        self.swallowed.clear();
+        self.prefix = None;
+    }
+
+    fn abort(&mut self, out: &mut Vec<LeaderOutput<P>>) {
+        let swallowed: Vec<InputEvent> = self.swallowed.drain(..).collect();
+        out.push(LeaderOutput::ClearHint);
+        out.push(LeaderOutput::Replay(swallowed));
+        self.replaying.extend(self.pressed.drain());
+        self.prefix = None;
+    }
+
+    fn process(&mut self, ev: &InputEvent, payload: P, now: Instant, out: &mut Vec<LeaderOutput<P>>) {
+        let leader = match &self.leader {
+            Some(leader) => leader,
+            None => {
+                out.push(LeaderOutput::Event(*ev, payload));
+                return;
+            }
+        };
+
+        if !ev.is_key_event() {
+            out.push(LeaderOutput::Event(*ev, payload));
+            return;
+        }
+
+        if self.replaying.contains(&ev.code) {
+            if !ev.is_key_down_event() {
+                if ev.is_key_up_event() {
+                    self.replaying.remove(&ev.code);
+                }
+                out.push(LeaderOutput::Replay(vec![*ev]));
+                return;
+            }
+            self.replaying.remove(&ev.code);
+        }
+
+        let prefix = match &self.prefix {
+            Some(prefix) => prefix,
+            None => {
+                if !ev.is_key_down_event() && self.swallowing.contains(&ev.code) {
+                    if ev.is_key_up_event() {
+                        self.swallowing.remove(&ev.code);
+                    }
+                } else if ev.is_key_down_event() && ev.code == leader.code {
+                    self.swallowing.remove(&ev.code);
+                    self.prefix = Some(vec![]);
+                    self.last_key_at = Some(now);
+                    self.swallowed.push(*ev);
+                    self.pressed.insert(ev.code);
+                    out.push(LeaderOutput::Hint(self.hint(), leader.timeout));
+                } else {
+                    self.swallowing.remove(&ev.code);
+                    out.push(LeaderOutput::Event(*ev, payload));
+                }
+                return;
+            }
+        };
+
+        if !ev.is_key_down_event() {
+            if self.pressed.contains(&ev.code) {
+                self.swallowed.push(*ev);
+                if ev.is_key_up_event() {
+                    self.pressed.remove(&ev.code);
+                }
+            } else {
+                out.push(LeaderOutput::Event(*ev, payload));
+            }
+            return;
+        }
+
+        let mut keys = prefix.clone();
+        keys.push(ev.code);
+        let node = match leader.find_node(&keys) {
+            Some(node) => node,
+            None => {
+                // Not a sequence. The key itself goes to the next stage.
+                self.abort(out);
+                out.push(LeaderOutput::Event(*ev, payload));
+                return;
+            }
+        };
+        let complete = if node.children.is_empty() { node.sequence } else { None };
+        let timeout = leader.timeout;
+
+        self.prefix = Some(keys);
+        self.last_key_at = Some(now);
+        self.swallowed.push(*ev);
+        self.pressed.insert(ev.code);
+        match complete {
+            Some(index) => self.complete(index, out),
+            None => out.push(LeaderOutput::Hint(self.hint(), timeout)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl crate::testing::TimedKeyState for LeaderState<()> {
+    type Output = LeaderOutput<()>;
+
+    fn on_event(&mut self, ev: &InputEvent, now: Instant) -> Vec<LeaderOutput<()>> {
+        return LeaderState::on_event(self, ev, (), now);
+    }
+
+    fn on_timeout(&mut self, now: Instant) -> Vec<LeaderOutput<()>> {
+        return LeaderState::on_timeout(self, now);
+    }
+}
+
+/// Run `steps`, without the hints, which are tested separately.
+#[cfg(test)]
+fn leader_test_events(state: &mut LeaderState<()>, start: Instant, steps: &[crate::testing::TestStep]) -> Vec<LeaderOutput<()>> {
+    let mut ret = crate::testing::run_timed_key_state(state, start, steps);
+    ret.retain(|o| !matches!(o, LeaderOutput::Hint(..) | LeaderOutput::ClearHint));
+    return ret;
+}
+
+#[test]
+fn test_leader() {
+    use crate::testing::TestStep::*;
+    use LeaderOutput::*;
+
+    let start = Instant::now();
+    let key = |code: i32, value: i32| InputEvent::new_key_event(code, value);
+    let mut leader = Leader::new(ec::KEY_ESC);
+    leader
+        .add_sequence(&[ec::KEY_G, ec::KEY_C], "Chrome", |_| {})
+        .add_sequence(&[ec::KEY_G], "Go", |_| {})
+        .add_sequence(&[ec::KEY_T], "Terminal", |_| {});
+    let mut state = LeaderState::new(Some(leader));
+
+    // Complete, and the key up events are swallowed.
+    assert_eq!(
+        vec![Sequence(0)],
+        leader_test_events(
+            &mut state,
+            start,
+            &[
+                Key(0, ec::KEY_ESC, 1),
+                Key(10, ec::KEY_ESC, 0),
+                Key(100, ec::KEY_G, 1),
+                Key(200, ec::KEY_C, 1),
+                Key(210, ec::KEY_C, 0),
+                Key(220, ec::KEY_G, 0)
+            ]
+        )
+    );
+    assert_eq!(None, state.next_deadline());
+
+    // "G" is a prefix of "G C", so it only completes on the timeout.
+    assert_eq!(
+        vec![Sequence(1)],
+        leader_test_events(
+            &mut state,
+            start,
+            &[Key(0, ec::KEY_ESC, 1), Key(100, ec::KEY_G, 1), Timeout(1100), Key(1200, ec::KEY_G, 0)]
+        )
+    );
+
+    // No match -> replay, and the key itself goes through.
+    assert_eq!(
+        vec![Replay(vec![key(ec::KEY_ESC, 1), key(ec::KEY_ESC, 0)]), Event(key(ec::KEY_A, 1), ())],
+        leader_test_events(&mut state, start, &[Key(0, ec::KEY_ESC, 1), Key(10, ec::KEY_ESC, 0), Key(100, ec::KEY_A, 1)])
+    );
+
+    // Timeout without a match.
+    assert_eq!(
+        vec![Replay(vec![key(ec::KEY_ESC, 1)]), Replay(vec![key(ec::KEY_ESC, 0)])],
+        leader_test_events(&mut state, start, &[Key(0, ec::KEY_ESC, 1), Timeout(1000), Key(1100, ec::KEY_ESC, 0)])
+    );
+}
+
+#[test]
+fn test_leader_hint() {
+    let mut leader = Leader::new(ec::KEY_ESC);
+    leader
+        .add_sequence(&[ec::KEY_G, ec::KEY_C], "Chrome", |_| {})
+        .add_sequence(&[ec::KEY_T], "Terminal", |_| {});
+    let mut state: LeaderState<()> = LeaderState::new(Some(leader));
+
+    let now = Instant::now();
+    let out = state.on_event(&InputEvent::new_key_event(ec::KEY_ESC, 1), (), now);
+    assert_eq!(
+        vec![LeaderOutput::Hint("ESC ...\nT: Terminal\nG: ...".to_string(), Duration::from_secs(1))],
+        out
+    );
+
+    let out = state.on_event(&InputEvent::new_key_event(ec::KEY_G, 1), (), now);
+    assert_eq!(vec![LeaderOutput::Hint("ESC G ...\nC: Chrome".to_string(), Duration::from_secs(1))], out);
+
+    // The hint is closed when the sequence completes.
+    let out = state.on_event(&InputEvent::new_key_event(ec::KEY_C, 1), (), now);
+    assert_eq!(vec![LeaderOutput::ClearHint, LeaderOutput::Sequence(0)], out);
+
+    // And when it's aborted.
+    state.on_event(&InputEvent::new_key_event(ec::KEY_ESC, 1), (), now);
+    let out = state.on_event(&InputEvent::new_key_event(ec::KEY_A, 1), (), now);
+    assert_eq!(
+        vec![
+            LeaderOutput::ClearHint,
+            LeaderOutput::Replay(vec![InputEvent::new_key_event(ec::KEY_ESC, 1)]),
+            LeaderOutput::Event(InputEvent::new_key_event(ec::KEY_A, 1), ())
+        ],
+        out
+    );
+}
+
+#[test]
+fn test_leader_replay_skips_on_event() {
+    use crate::{testing::TestRemapper, KeyRemapperConfiguration};
+
+    let mut leader = Leader::new(ec::KEY_ESC);
+    leader.add_sequence(&[ec::KEY_G], "Go", |_| {});
+    let mut config = KeyRemapperConfiguration::new("Test", "");
+    config.set_leader(&leader).on_event(|km, _device, ev| {
+        // Swallow ESC, e.g. to send it on release instead.
+        if ev.is_key_event() && ev.code == ec::KEY_ESC {
+            return;
+        }
+        km.send_event(ev);
+    });
+
+    let t = TestRemapper::new(&config);
+    let kbd = t.add_keyboard("keyboard");
+    t.start();
+
+    // Timeout. The replayed ESC is released as-is too, rather than going to on_event.
+    t.press(&kbd, ec::KEY_ESC);
+    t.advance(Duration::from_secs(1));
+    assert_eq!(vec![(ec::KEY_ESC, 1)], t.take_output_keys());
+    t.release(&kbd, ec::KEY_ESC);
+    assert_eq!(vec![(ec::KEY_ESC, 0)], t.take_output_keys());
+
+    // Another key. It goes to on_event, but ESC doesn't.
+    t.press(&kbd, ec::KEY_ESC);
+    t.press(&kbd, ec::KEY_A);
+    t.release(&kbd, ec::KEY_ESC);
+    t.release(&kbd, ec::KEY_A);
+    assert_eq!(vec![(ec::KEY_ESC, 1), (ec::KEY_A, 1), (ec::KEY_ESC, 0), (ec::KEY_A, 0)], t.take_output_keys());
+    assert!(!t.remapper().is_out_key_on(ec::KEY_ESC));
+}
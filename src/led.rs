@@ -0,0 +1,74 @@
+//! Keyboard LED state. See `KeyRemapper::set_led()`.
+//!
+//! The host (e.g. X or the compositor) sets the LEDs, such as Caps Lock, on the uinput device,
+//! because that's the keyboard it sees. The grabbed source devices never see them, so they're
+//! forwarded. `KeyRemapper::set_led()` overrides the host's state of an LED.
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub(crate) struct LedState {
+    /// LED states set by the host on the uinput device.
+    host: HashMap<i32, bool>,
+
+    /// LED states set with `KeyRemapper::set_led()`, which take precedence.
+    overrides: HashMap<i32, bool>,
+}
+
+impl LedState {
+    pub(crate) fn new() -> LedState {
+        return LedState::default();
+    }
+
+    pub(crate) fn led(&self, code: i32) -> bool {
+        return self.overrides.get(&code).or_else(|| self.host.get(&code)).copied().unwrap_or(false);
+    }
+
+    /// Update the state, and return the new state of the LED if it changed.
+    fn update<F: FnOnce(&mut LedState)>(&mut self, code: i32, f: F) -> Option<bool> {
+        let old = self.led(code);
+        f(self);
+        let new = self.led(code);
+        return if old != new { Some(new) } else { None };
+    }
+
+    pub(crate) fn set_host_led(&mut self, code: i32, on: bool) -> Option<bool> {
+        return self.update(code, |s| {
+            s.host.insert(code, on);
+        });
+    }
+
+    /// Override an LED, or stop overriding it with `None`.
+    pub(crate) fn set_override(&mut self, code: i32, on: Option<bool>) -> Option<bool> {
+        return self.update(code, |s| match on {
+            Some(on) => {
+                s.overrides.insert(code, on);
+            }
+            None => {
+                s.overrides.remove(&code);
+            }
+        });
+    }
+
+    /// Return the state of all the known LEDs, sorted, e.g. to set them on a new device.
+    pub(crate) fn all(&self) -> Vec<(i32, bool)> {
+        let mut codes: Vec<i32> = self.host.keys().chain(self.overrides.keys()).copied().collect();
+        codes.sort_unstable();
+        codes.dedup();
+        return codes.into_iter().map(|code| (code, self.led(code))).collect();
+    }
+}
+
+#[test]
+fn test_led_state() {
+    use crate::evdev::ec;
+
+    let mut leds = LedState::new();
+    assert_eq!(Some(true), leds.set_host_led(ec::LED_CAPSL, true));
+    assert_eq!(None, leds.set_host_led(ec::LED_CAPSL, true));
+
+    // Overridden LEDs don't follow the host until the override is removed.
+    assert_eq!(Some(true), leds.set_override(ec::LED_SCROLLL, Some(true)));
+    assert_eq!(None, leds.set_host_led(ec::LED_SCROLLL, false));
+    assert_eq!(vec![(ec::LED_CAPSL, true), (ec::LED_SCROLLL, true)], leds.all());
+    assert_eq!(Some(false), leds.set_override(ec::LED_SCROLLL, None));
+}
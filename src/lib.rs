@@ -1,10 +1,27 @@
+pub mod chord;
 pub mod config;
+pub mod control;
 pub mod core;
 pub mod evdev;
+pub mod gamepad;
+pub mod gesture;
+pub(crate) mod hold;
+pub mod layer;
+pub mod leader;
+pub(crate) mod led;
+pub mod macros;
+pub(crate) mod modifiers;
 pub(crate) mod native;
+pub mod pointer;
 pub mod res;
+pub mod rules;
+pub mod scroll;
 pub(crate) mod select;
 pub(crate) mod singleton;
+pub mod taphold;
+pub mod testing;
+pub mod text;
+pub mod timer;
 pub(crate) mod udev;
 pub mod ui;
 
@@ -0,0 +1,264 @@
+//! Recording and playback of macros, i.e. the events sent to the uinput device.
+//! See `KeyRemapper::start_recording_macro()` and `KeyRemapper::play_macro()`.
+//!
+//! A macro file has one event per line, in the form of "DELAY_USEC TYPE CODE VALUE", where DELAY_USEC
+//! is the delay since the previous event in microseconds. Anything after a '#' is a comment.
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::evdev::{ec, InputEvent};
+
+/// How to time the events of a macro on playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroTiming {
+    /// Use the intervals as recorded.
+    Original,
+
+    /// Use the same interval between all the events.
+    Fixed(Duration),
+}
+
+/// A single event in a macro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroEvent {
+    /// Delay since the previous event.
+    pub delay: Duration,
+    pub event: InputEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    pub name: String,
+    pub events: Vec<MacroEvent>,
+}
+
+impl Macro {
+    pub fn new(name: &str) -> Macro {
+        return Macro {
+            name: name.to_string(),
+            events: vec![],
+        };
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Macro> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).with_context(|| format!("Unable to read macro file {:?}", path))?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        return Macro::parse(&content, &name).with_context(|| format!("Unable to parse macro file {:?}", path));
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Unable to create directory {:?}", dir))?;
+        }
+        return fs::write(path, self.format()).with_context(|| format!("Unable to write macro file {:?}", path));
+    }
+
+    pub fn parse(content: &str, name: &str) -> anyhow::Result<Macro> {
+        let mut ret = Macro::new(name);
+        for (i, line) in content.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => line,
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if fields.len() != 4 {
+                bail!("Line {}: expected 4 fields, found {}", i + 1, fields.len());
+            }
+            let parse = |s: &str| -> anyhow::Result<i64> { s.parse().map_err(|_| anyhow!(r#"Line {}: invalid number "{}""#, i + 1, s)) };
+            let delay = parse(fields[0])?;
+            if delay < 0 {
+                bail!("Line {}: negative delay", i + 1);
+            }
+            ret.events.push(MacroEvent {
+                delay: Duration::from_micros(delay as u64),
+                event: InputEvent::new(
+                    ec::EventType::from_i32(parse(fields[1])? as i32),
+                    parse(fields[2])? as i32,
+                    parse(fields[3])? as i32,
+                ),
+            });
+        }
+        return Ok(ret);
+    }
+
+    pub fn format(&self) -> String {
+        let mut ret = String::new();
+        for e in &self.events {
+            ret.push_str(&format!(
+                "{} {} {} {} # {}\n",
+                e.delay.as_micros(),
+                e.event.event_type as i32,
+                e.event.code,
+                e.event.value,
+                e.event.code_name()
+            ));
+        }
+        return ret;
+    }
+
+    /// Return the events along with their offsets from the start of the playback.
+    pub(crate) fn timeline(&self, timing: MacroTiming) -> Vec<(Duration, InputEvent)> {
+        let mut offset = Duration::from_secs(0);
+        let mut ret = vec![];
+        for (i, e) in self.events.iter().enumerate() {
+            if i > 0 {
+                offset += match timing {
+                    MacroTiming::Original => e.delay,
+                    MacroTiming::Fixed(interval) => interval,
+                };
+            }
+            ret.push((offset, e.event));
+        }
+        return ret;
+    }
+}
+
+/// Records events into a `Macro`.
+#[derive(Debug)]
+pub(crate) struct MacroRecorder {
+    recording: Macro,
+    last_at: Option<Instant>,
+    pressed: HashSet<i32>,
+}
+
+impl MacroRecorder {
+    pub(crate) fn new(name: &str) -> MacroRecorder {
+        return MacroRecorder {
+            recording: Macro::new(name),
+            last_at: None,
+            pressed: HashSet::new(),
+        };
+    }
+
+    pub(crate) fn record(&mut self, ev: &InputEvent, now: Instant) {
+        // SYN_REPORTs are added on playback.
+        if ev.is_syn_report() {
+            return;
+        }
+        if ev.is_key_down_event() {
+            self.pressed.insert(ev.code);
+        } else if ev.is_key_up_event() {
+            self.pressed.remove(&ev.code);
+        }
+        let delay = match self.last_at {
+            Some(last_at) => now.saturating_duration_since(last_at),
+            None => Duration::from_secs(0),
+        };
+        self.last_at = Some(now);
+        // Drop the timestamp and the modifiers, which aren't stored.
+        let event = InputEvent::new(ev.event_type, ev.code, ev.value);
+        self.recording.events.push(MacroEvent { delay, event });
+    }
+
+    /// Finish the recording. Keys that are still pressed are released at the end.
+    pub(crate) fn finish(mut self) -> Macro {
+        let mut pressed: Vec<i32> = self.pressed.drain().collect();
+        pressed.sort_unstable();
+        for code in pressed {
+            self.recording.events.push(MacroEvent {
+                delay: Duration::from_secs(0),
+                event: InputEvent::new_key_event(code, 0),
+            });
+        }
+        return self.recording;
+    }
+}
+
+/// Directory to store macros in, one file per macro.
+#[derive(Debug, Clone)]
+pub struct MacroStore {
+    dir: PathBuf,
+}
+
+impl MacroStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> MacroStore {
+        return MacroStore { dir: dir.into() };
+    }
+
+    /// The default store for an app, i.e. "$XDG_DATA_HOME/keyremapper/APP_NAME/macros".
+    pub fn for_app(app_name: &str) -> MacroStore {
+        let mut dir = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                let mut dir = PathBuf::from(std::env::var_os("HOME").unwrap_or_default());
+                dir.push(".local/share");
+                dir
+            }
+        };
+        dir.push("keyremapper");
+        dir.push(app_name);
+        dir.push("macros");
+        return MacroStore::new(dir);
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        let mut ret = self.dir.clone();
+        ret.push(format!("{}.macro", name));
+        return ret;
+    }
+
+    pub fn load(&self, name: &str) -> anyhow::Result<Macro> {
+        return Macro::load(self.path(name));
+    }
+
+    pub fn save(&self, m: &Macro) -> anyhow::Result<()> {
+        return m.save(self.path(&m.name));
+    }
+
+    /// Return the names of the stored macros, sorted.
+    pub fn list(&self) -> Vec<String> {
+        let mut ret: Vec<String> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "macro"))
+                .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+                .collect(),
+            Err(_) => vec![],
+        };
+        ret.sort();
+        return ret;
+    }
+}
+
+#[test]
+fn test_macro_recorder() {
+    let start = Instant::now();
+    let ms = |millis: u64| Duration::from_millis(millis);
+
+    let mut recorder = MacroRecorder::new("test");
+    recorder.record(&InputEvent::new_key_event(ec::KEY_A, 1), start);
+    recorder.record(&InputEvent::new_syn_report(), start);
+    recorder.record(&InputEvent::new_key_event(ec::KEY_A, 0), start + ms(50));
+    recorder.record(&InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 1), start + ms(80));
+    let m = recorder.finish();
+
+    assert_eq!("test", m.name);
+    assert_eq!(
+        vec![
+            (ms(0), InputEvent::new_key_event(ec::KEY_A, 1)),
+            (ms(50), InputEvent::new_key_event(ec::KEY_A, 0)),
+            (ms(80), InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 1)),
+            (ms(80), InputEvent::new_key_event(ec::KEY_LEFTSHIFT, 0)),
+        ],
+        m.timeline(MacroTiming::Original)
+    );
+    assert_eq!(ms(30), m.timeline(MacroTiming::Fixed(ms(10)))[3].0);
+
+    // Round trip.
+    assert_eq!(m, Macro::parse(&m.format(), "test").unwrap());
+
+    assert!(Macro::parse("0 1 30", "x").is_err());
+    assert!(Macro::parse("-1 1 30 1", "x").is_err());
+}
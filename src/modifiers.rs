@@ -0,0 +1,306 @@
+//! Modifier registry, i.e. which keys act as modifiers for `InputEvent::with_modifiers()`,
+//! `KeyRemapper::press_key()` and `KeyRemapper::is_modifier_on()`.
+//!
+//! A modifier has a single character name and one or more keys. The built-in ones are `a` (Alt),
+//! `c` (Ctrl), `s` (Shift), `w` (Meta / Windows key) and `e` (ESC). Other keys, e.g. CapsLock or a
+//! foot pedal button, can be added with `KeyRemapperConfiguration::add_modifier()`.
+//!
+//! In a modifier string, a name matches any of the modifier's keys. `<` or `>` before a name matches
+//! only its first (left) or second (right) key, e.g. `">a"` is the right Alt only. Unless the string
+//! contains `*`, all the other modifier keys need to be released.
+use anyhow::{anyhow, bail};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use crate::evdev::{ec, input_event::Modifers};
+
+/// Which keys of a modifier a modifier string refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Any,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Modifier {
+    name: char,
+    keys: Vec<i32>,
+}
+
+/// The modifiers of a `KeyRemapperConfiguration`. Each modifier key has its own bit in `Modifers`,
+/// in the order of registration, so `Modifers` can only be interpreted with the registry that built it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ModifierRegistry {
+    modifiers: Vec<Modifier>,
+}
+
+lazy_static! {
+    /// Registries handed out by `intern()`. They're never freed, but equal registries share an entry,
+    /// so there's only one per distinct set of modifiers.
+    static ref INTERNED: Mutex<Vec<&'static ModifierRegistry>> = Mutex::new(vec![]);
+
+    /// See `ModifierRegistry::builtin()`.
+    static ref BUILTIN: &'static ModifierRegistry = ModifierRegistry::new().intern();
+}
+
+impl ModifierRegistry {
+    /// Create a registry with the built-in modifiers.
+    pub(crate) fn new() -> ModifierRegistry {
+        let mut ret = ModifierRegistry { modifiers: vec![] };
+        ret.register('a', &[ec::KEY_LEFTALT, ec::KEY_RIGHTALT]);
+        ret.register('c', &[ec::KEY_LEFTCTRL, ec::KEY_RIGHTCTRL]);
+        ret.register('s', &[ec::KEY_LEFTSHIFT, ec::KEY_RIGHTSHIFT]);
+        ret.register('w', &[ec::KEY_LEFTMETA, ec::KEY_RIGHTMETA]);
+        ret.register('e', &[ec::KEY_ESC]);
+        return ret;
+    }
+
+    /// The registry with only the built-in modifiers, e.g. for the events that no `KeyRemapper` has set
+    /// the modifiers of, and for the modifiers of `KeyRemapper::press_key()`.
+    pub(crate) fn builtin() -> &'static ModifierRegistry {
+        return *BUILTIN;
+    }
+
+    /// Return a `'static` copy, which `InputEvent` can refer to while staying `Copy`.
+    pub(crate) fn intern(&self) -> &'static ModifierRegistry {
+        let mut interned = INTERNED.lock();
+        if let Some(r) = interned.iter().find(|r| **r == self) {
+            return r;
+        }
+        let ret: &'static ModifierRegistry = Box::leak(Box::new(self.clone()));
+        interned.push(ret);
+        return ret;
+    }
+
+    fn key_count(&self) -> usize {
+        return self.modifiers.iter().map(|m| m.keys.len()).sum();
+    }
+
+    /// Return the modifiers along with the bit of their first key.
+    fn with_bits(&self) -> impl Iterator<Item = (usize, &Modifier)> {
+        let mut bit = 0;
+        return self.modifiers.iter().map(move |m| {
+            let ret = (bit, m);
+            bit += m.keys.len();
+            ret
+        });
+    }
+
+    fn find(&self, name: char) -> Option<&Modifier> {
+        return self.modifiers.iter().find(|m| m.name == name);
+    }
+
+    /// Register a new modifier. The first and second keys are the left and right keys for `<` and `>`.
+    /// Returns an error if the name or any of the keys is already used.
+    pub(crate) fn try_register(&mut self, name: char, keys: &[i32]) -> anyhow::Result<()> {
+        if "*<>+ ".contains(name) {
+            bail!(r#"Modifier name "{}" is reserved"#, name);
+        }
+        if keys.is_empty() {
+            bail!(r#"Modifier "{}" needs at least one key"#, name);
+        }
+        if self.find(name).is_some() {
+            bail!(r#"Modifier "{}" is already registered"#, name);
+        }
+        if let Some(key) = keys.iter().find(|k| self.modifiers.iter().any(|m| m.keys.contains(k))) {
+            bail!("Key {} is already a modifier", key);
+        }
+        if self.key_count() + keys.len() > Modifers::BITS as usize {
+            bail!("Too many modifier keys");
+        }
+        self.modifiers.push(Modifier { name, keys: keys.to_vec() });
+        return Ok(());
+    }
+
+    /// Same as `try_register()`, but panics if the name or any of the keys is already used.
+    pub(crate) fn register(&mut self, name: char, keys: &[i32]) {
+        if let Err(e) = self.try_register(name, keys) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Return true if `name` is a registered modifier.
+    pub(crate) fn is_registered(&self, name: char) -> bool {
+        return self.find(name).is_some();
+    }
+
+    /// Return the keys of the modifier `name`, or an empty slice if there's no such modifier.
+    pub(crate) fn keys(&self, name: char) -> &[i32] {
+        return self.find(name).map_or(&[], |m| &m.keys);
+    }
+
+    /// Build `Modifers` by calling `is_on(name, key, is_first_key)` for each modifier key.
+    pub(crate) fn build<F: Fn(char, i32, bool) -> bool>(&self, is_on: F) -> Modifers {
+        let mut ret = 0;
+        for (bit, m) in self.with_bits() {
+            for (i, key) in m.keys.iter().enumerate() {
+                if is_on(m.name, *key, i == 0) {
+                    ret |= 1 << (bit + i);
+                }
+            }
+        }
+        return ret;
+    }
+
+    /// Return the names of the modifiers that are on, e.g. "ae".
+    pub(crate) fn format(&self, modifiers: Modifers) -> String {
+        let mut ret = String::new();
+        for (bit, m) in self.with_bits() {
+            if (modifiers >> bit) & ((1 << m.keys.len()) - 1) != 0 {
+                ret.push(m.name);
+            }
+        }
+        return ret;
+    }
+}
+
+/// A parsed modifier string, e.g. `"<ae*"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ModifierSpec {
+    terms: Vec<(char, Side)>,
+    ignore_others: bool,
+}
+
+impl ModifierSpec {
+    pub(crate) fn try_parse(registry: &ModifierRegistry, spec: &str) -> anyhow::Result<ModifierSpec> {
+        let mut ret = ModifierSpec {
+            terms: vec![],
+            ignore_others: false,
+        };
+        let mut side = Side::Any;
+        for c in spec.chars() {
+            match c {
+                '*' if side == Side::Any => ret.ignore_others = true,
+                '<' if side == Side::Any => side = Side::Left,
+                '>' if side == Side::Any => side = Side::Right,
+                _ => {
+                    let m = registry
+                        .find(c)
+                        .ok_or_else(|| anyhow!(r#"Modifier "{}" contains an invalid character "{}""#, spec, c))?;
+                    if side != Side::Any && m.keys.len() < 2 {
+                        bail!(r#"Modifier "{}": "{}" doesn't have left and right keys"#, spec, c);
+                    }
+                    ret.terms.push((c, side));
+                    side = Side::Any;
+                }
+            }
+        }
+        if side != Side::Any {
+            bail!(r#"Modifier "{}" ends with "<" or ">""#, spec);
+        }
+        return Ok(ret);
+    }
+
+    /// Same as `try_parse()`, but panics on an invalid modifier string.
+    pub(crate) fn parse(registry: &ModifierRegistry, spec: &str) -> ModifierSpec {
+        match ModifierSpec::try_parse(registry, spec) {
+            Ok(ret) => return ret,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Don't require the other modifiers to be released, as if the string contained `*`.
+    pub(crate) fn ignoring_others(mut self) -> ModifierSpec {
+        self.ignore_others = true;
+        self
+    }
+
+    /// Return true if `modifiers`, which `registry` has built, match.
+    pub(crate) fn matches(&self, registry: &ModifierRegistry, modifiers: Modifers) -> bool {
+        for (bit, m) in registry.with_bits() {
+            let on = (modifiers >> bit) & ((1 << m.keys.len()) - 1);
+            let mut any = false;
+            let mut required = 0;
+            for (_, side) in self.terms.iter().filter(|(name, _)| *name == m.name) {
+                match side {
+                    Side::Any => any = true,
+                    Side::Left => required |= 1,
+                    Side::Right => required |= 2,
+                }
+            }
+            if on & required != required || (any && on == 0) {
+                return false;
+            }
+            if !self.ignore_others && !any && on & !required != 0 {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Return the keys the modifiers refer to, e.g. both Alt keys for `"a"` and only the right one for `">a"`.
+    pub(crate) fn keys(&self, registry: &ModifierRegistry) -> Vec<i32> {
+        let mut ret = vec![];
+        for (name, side) in &self.terms {
+            let m = registry.find(*name).unwrap();
+            let keys = match side {
+                Side::Any => &m.keys[..],
+                Side::Left => &m.keys[..1],
+                Side::Right => &m.keys[1..2],
+            };
+            for key in keys {
+                if !ret.contains(key) {
+                    ret.push(*key);
+                }
+            }
+        }
+        return ret;
+    }
+
+    /// Return the keys to press for the modifiers, i.e. the left key unless `>` is used.
+    pub(crate) fn keys_to_press(&self, registry: &ModifierRegistry) -> Vec<i32> {
+        let mut ret = vec![];
+        for (name, side) in &self.terms {
+            let m = registry.find(*name).unwrap();
+            let key = if *side == Side::Right { m.keys[1] } else { m.keys[0] };
+            if !ret.contains(&key) {
+                ret.push(key);
+            }
+        }
+        return ret;
+    }
+}
+
+#[test]
+fn test_modifier_spec() {
+    let mut registry = ModifierRegistry::new();
+    registry.register('p', &[ec::KEY_CAPSLOCK]);
+
+    let keys = |keys: &[i32]| registry.build(|_, key, _| keys.contains(&key));
+    let matches = |spec: &str, modifiers: Modifers| ModifierSpec::parse(&registry, spec).matches(&registry, modifiers);
+
+    let left_alt = keys(&[ec::KEY_LEFTALT]);
+    let right_alt = keys(&[ec::KEY_RIGHTALT]);
+    assert!(matches("a", left_alt));
+    assert!(matches("a", right_alt));
+    assert!(matches("<a", left_alt));
+    assert!(!matches(">a", left_alt));
+    assert!(!matches("<a", keys(&[ec::KEY_LEFTALT, ec::KEY_RIGHTALT])));
+    assert!(matches("<a*", keys(&[ec::KEY_LEFTALT, ec::KEY_RIGHTALT])));
+    assert!(matches("<a>a", keys(&[ec::KEY_LEFTALT, ec::KEY_RIGHTALT])));
+    assert!(!matches("", left_alt));
+
+    let caps_esc = keys(&[ec::KEY_CAPSLOCK, ec::KEY_ESC]);
+    assert!(matches("ep", caps_esc));
+    assert!(!matches("p", caps_esc));
+    assert!(ModifierSpec::parse(&registry, "p").ignoring_others().matches(&registry, caps_esc));
+    assert_eq!("ep", registry.format(caps_esc));
+
+    assert_eq!(
+        vec![ec::KEY_LEFTCTRL, ec::KEY_RIGHTALT],
+        ModifierSpec::parse(&registry, "c>a").keys_to_press(&registry)
+    );
+
+    assert!(ModifierSpec::try_parse(&registry, "x").is_err());
+    assert!(ModifierSpec::try_parse(&registry, ">e").is_err());
+    assert!(ModifierSpec::try_parse(&registry, "a<").is_err());
+
+    // Other registries, e.g. of other configurations, don't see "p", and the same name can be used again.
+    assert!(ModifierSpec::try_parse(ModifierRegistry::builtin(), "p").is_err());
+    let mut other = ModifierRegistry::new();
+    other.register('p', &[ec::KEY_F13]);
+    assert_eq!("p", other.format(other.build(|_, key, _| key == ec::KEY_F13)));
+    assert!(std::ptr::eq(other.intern(), other.clone().intern()));
+    assert!(!std::ptr::eq(other.intern(), registry.intern()));
+}
@@ -43,3 +43,16 @@ extern "C" {
 
     pub fn XInitThreads() -> libc::c_uint;
 }
+
+// XTEST extension, from libXtst. `Bool`s are `c_int`s.
+#[cfg(feature = "xtest")]
+extern "C" {
+    pub fn XTestQueryExtension(display: *mut x11::xlib::Display, event_base: *mut c_int, error_base: *mut c_int, major: *mut c_int, minor: *mut c_int)
+        -> c_int;
+
+    pub fn XTestFakeKeyEvent(display: *mut x11::xlib::Display, keycode: libc::c_uint, is_press: c_int, delay: libc::c_ulong) -> c_int;
+
+    pub fn XTestFakeButtonEvent(display: *mut x11::xlib::Display, button: libc::c_uint, is_press: c_int, delay: libc::c_ulong) -> c_int;
+
+    pub fn XTestFakeRelativeMotionEvent(display: *mut x11::xlib::Display, x: c_int, y: c_int, delay: libc::c_ulong) -> c_int;
+}
@@ -0,0 +1,251 @@
+//! Pointer acceleration and transforms for mice, trackpoints and trackballs.
+//!
+//! `PointerAccelerator` buffers the events of a frame, i.e. until SYN_REPORT, and applies the
+//! acceleration to the REL_X / REL_Y movement as a vector, so diagonal movements are accelerated
+//! the same as straight ones. The fractions that can't be sent yet are carried over to the next
+//! frame, so slow movements don't stall.
+use crate::evdev::{
+    ec::{self, EventType},
+    InputEvent,
+};
+
+/// Maps the speed of a frame, i.e. the length of the movement vector, to the new speed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccelProfile {
+    /// No acceleration.
+    Flat,
+
+    /// Multiply the speed by `factor`.
+    Linear { factor: f64 },
+
+    /// The curve of trackpoint-speedup: below `threshold` the speed is unchanged, and above it
+    /// `((1 + (speed - threshold + add) / scale) ^ power - 1) * scale + threshold`.
+    Power { threshold: f64, add: f64, power: f64, scale: f64 },
+
+    /// Linear interpolation between `(in, out)` points, sorted by `in`. The last segment is extrapolated.
+    Table(Vec<(f64, f64)>),
+}
+
+impl AccelProfile {
+    /// Create a `Table` profile. Panics if there are fewer than 2 points.
+    pub fn table(points: &[(f64, f64)]) -> AccelProfile {
+        if points.len() < 2 {
+            panic!("An acceleration table needs at least 2 points");
+        }
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        return AccelProfile::Table(points);
+    }
+
+    pub fn apply(&self, speed: f64) -> f64 {
+        match self {
+            AccelProfile::Flat => return speed,
+            AccelProfile::Linear { factor } => return speed * factor,
+            AccelProfile::Power { threshold, add, power, scale } => {
+                let value = speed - threshold;
+                if value < 1.0 {
+                    return speed;
+                }
+                let value = (value + add) / scale;
+                return ((1.0 + value).powf(*power) - 1.0) * scale + threshold;
+            }
+            AccelProfile::Table(points) => {
+                let i = points.iter().position(|p| p.0 > speed).unwrap_or(points.len() - 1).clamp(1, points.len() - 1);
+                let ((x0, y0), (x1, y1)) = (points[i - 1], points[i]);
+                if x1 == x0 {
+                    return y1;
+                }
+                return (y0 + (speed - x0) * (y1 - y0) / (x1 - x0)).max(0.0);
+            }
+        }
+    }
+}
+
+impl Default for AccelProfile {
+    fn default() -> Self {
+        return AccelProfile::Flat;
+    }
+}
+
+/// Acceleration and axis transforms. They're applied in the order of swap, invert, rotation,
+/// acceleration and scale.
+#[derive(Debug, Clone)]
+pub struct PointerTransform {
+    pub(crate) profile: AccelProfile,
+    pub(crate) scale_x: f64,
+    pub(crate) scale_y: f64,
+    pub(crate) swap_axes: bool,
+    pub(crate) invert_x: bool,
+    pub(crate) invert_y: bool,
+    pub(crate) rotation: f64,
+}
+
+impl PointerTransform {
+    pub fn new(profile: AccelProfile) -> PointerTransform {
+        return PointerTransform {
+            profile,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            swap_axes: false,
+            invert_x: false,
+            invert_y: false,
+            rotation: 0.0,
+        };
+    }
+
+    pub fn set_scale(&mut self, x: f64, y: f64) -> &mut PointerTransform {
+        self.scale_x = x;
+        self.scale_y = y;
+        self
+    }
+
+    pub fn set_swap_axes(&mut self, value: bool) -> &mut PointerTransform {
+        self.swap_axes = value;
+        self
+    }
+
+    pub fn set_invert(&mut self, x: bool, y: bool) -> &mut PointerTransform {
+        self.invert_x = x;
+        self.invert_y = y;
+        self
+    }
+
+    /// Rotate the movement clockwise by `degrees`, e.g. for a trackball mounted at an angle.
+    pub fn set_rotation(&mut self, degrees: f64) -> &mut PointerTransform {
+        self.rotation = degrees;
+        self
+    }
+
+    /// Transform a movement vector.
+    pub fn apply(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let (mut x, mut y) = if self.swap_axes { (dy, dx) } else { (dx, dy) };
+        if self.invert_x {
+            x = -x;
+        }
+        if self.invert_y {
+            y = -y;
+        }
+        if self.rotation != 0.0 {
+            // The Y axis grows downwards, so this is clockwise on the screen.
+            let (sin, cos) = self.rotation.to_radians().sin_cos();
+            let (rx, ry) = (x * cos - y * sin, x * sin + y * cos);
+            x = rx;
+            y = ry;
+        }
+        let speed = (x * x + y * y).sqrt();
+        if speed > 0.0 {
+            let factor = self.profile.apply(speed) / speed;
+            x *= factor;
+            y *= factor;
+        }
+        return (x * self.scale_x, y * self.scale_y);
+    }
+}
+
+impl Default for PointerTransform {
+    fn default() -> Self {
+        return PointerTransform::new(AccelProfile::Flat);
+    }
+}
+
+/// Applies a `PointerTransform` to the events of a pointer device, frame by frame.
+#[derive(Debug, Clone)]
+pub struct PointerAccelerator {
+    transform: PointerTransform,
+
+    /// Events of the current frame other than REL_X / REL_Y.
+    pending: Vec<InputEvent>,
+    dx: i32,
+    dy: i32,
+
+    /// Fractions of the movement that haven't been sent yet.
+    remainder: (f64, f64),
+}
+
+impl PointerAccelerator {
+    pub fn new(transform: &PointerTransform) -> PointerAccelerator {
+        return PointerAccelerator {
+            transform: transform.clone(),
+            pending: vec![],
+            dx: 0,
+            dy: 0,
+            remainder: (0.0, 0.0),
+        };
+    }
+
+    pub fn set_transform(&mut self, transform: &PointerTransform) {
+        self.transform = transform.clone();
+        self.remainder = (0.0, 0.0);
+    }
+
+    /// Feed an event. Returns the events of the frame, ending with the SYN_REPORT, when it's complete,
+    /// or nothing otherwise. The new REL_X / REL_Y are placed just before the SYN_REPORT.
+    pub fn on_event(&mut self, ev: &InputEvent) -> Vec<InputEvent> {
+        match (ev.event_type, ev.code) {
+            (EventType::EV_REL, ec::REL_X) => self.dx += ev.value,
+            (EventType::EV_REL, ec::REL_Y) => self.dy += ev.value,
+            (EventType::EV_SYN, ec::SYN_REPORT) => {
+                let mut ret = std::mem::take(&mut self.pending);
+                ret.extend(self.take_movement());
+                ret.push(*ev);
+                return ret;
+            }
+            _ => self.pending.push(*ev),
+        }
+        return vec![];
+    }
+
+    fn take_movement(&mut self) -> Vec<InputEvent> {
+        let (dx, dy) = (std::mem::take(&mut self.dx), std::mem::take(&mut self.dy));
+        if dx == 0 && dy == 0 {
+            return vec![];
+        }
+        let (x, y) = self.transform.apply(dx as f64, dy as f64);
+        let (x, y) = (x + self.remainder.0, y + self.remainder.1);
+        let (out_x, out_y) = (x.trunc(), y.trunc());
+        self.remainder = (x - out_x, y - out_y);
+        log::debug!("Pointer: ({}, {}) -> ({}, {})", dx, dy, out_x, out_y);
+
+        let mut ret = vec![];
+        if out_x != 0.0 {
+            ret.push(InputEvent::new(EventType::EV_REL, ec::REL_X, out_x as i32));
+        }
+        if out_y != 0.0 {
+            ret.push(InputEvent::new(EventType::EV_REL, ec::REL_Y, out_y as i32));
+        }
+        return ret;
+    }
+}
+
+#[test]
+fn test_pointer_accelerator() {
+    let rel = |code, value| InputEvent::new(EventType::EV_REL, code, value);
+    let syn = InputEvent::new_syn_report();
+
+    let profile = AccelProfile::table(&[(0.0, 0.0), (10.0, 5.0), (20.0, 20.0)]);
+    assert_eq!(2.5, profile.apply(5.0));
+    assert_eq!(12.5, profile.apply(15.0));
+    assert_eq!(27.5, profile.apply(25.0));
+
+    // Slow movements add up instead of being lost.
+    let mut accel = PointerAccelerator::new(&PointerTransform::new(AccelProfile::Linear { factor: 0.5 }));
+    assert!(accel.on_event(&rel(ec::REL_X, 1)).is_empty());
+    assert_eq!(vec![syn], accel.on_event(&syn));
+    accel.on_event(&rel(ec::REL_X, 1));
+    accel.on_event(&InputEvent::new_key_event(ec::BTN_LEFT, 1));
+    assert_eq!(vec![InputEvent::new_key_event(ec::BTN_LEFT, 1), rel(ec::REL_X, 1), syn], accel.on_event(&syn));
+
+    // The speed of a diagonal movement is its length.
+    let mut accel = PointerAccelerator::new(&PointerTransform::new(AccelProfile::table(&[(0.0, 0.0), (5.0, 10.0)])));
+    accel.on_event(&rel(ec::REL_X, 3));
+    accel.on_event(&rel(ec::REL_Y, 4));
+    assert_eq!(vec![rel(ec::REL_X, 6), rel(ec::REL_Y, 8), syn], accel.on_event(&syn));
+
+    // Rotating a trackball by 90 degrees turns right into down.
+    let mut transform = PointerTransform::default();
+    transform.set_rotation(90.0).set_scale(2.0, 2.0);
+    let (x, y) = transform.apply(1.0, 0.0);
+    assert!(x.abs() < 1e-9 && (y - 2.0).abs() < 1e-9);
+    transform.set_rotation(0.0).set_swap_axes(true).set_invert(true, false);
+    assert_eq!((-4.0, 2.0), transform.apply(1.0, 2.0));
+}
@@ -0,0 +1,599 @@
+//! Declarative remapping rules, which can be loaded from a TOML rule file.
+//!
+//! A rule file looks like this:
+//! ```toml
+//! name = "Keyboard remapper"
+//! device_name_regex = "^AT Translated Set 2 keyboard"
+//! id_regex = "^"
+//!
+//! rules = [
+//!     "e+BACKSPACE -> DELETE",          # ESC + BACKSPACE -> DELETE
+//!     "KEY_HOME with e -> LEFT with a", # ESC + HOME -> ALT + LEFT
+//!     "p+J -> LEFT",                    # CAPSLOCK + J -> LEFT
+//!     "e+F10 -> macro:greeting",        # Play a macro saved in `KeyRemapper::macro_store()`.
+//! ]
+//!
+//! # Modifiers other than the built-in ones, like `KeyRemapperConfiguration::add_modifier()`.
+//! modifiers = { p = ["CAPSLOCK"] }
+//! ```
+//!
+//! Each rule is either `MODIFIERS+KEY -> MODIFIERS+KEY` or `KEY with MODIFIERS -> KEY with MODIFIERS`
+//! (the modifiers are optional on both sides.) The left hand side uses the modifier grammar of
+//! `InputEvent::with_modifiers()` with the modifiers of the configuration, i.e. the built-in ones and
+//! the added ones, and the right hand side uses the one of `KeyRemapper::press_key()`.
+//! Key names may omit the "KEY_" prefix.
+//!
+//! The keys of the modifiers other than Alt, Ctrl, Shift and Meta, e.g. ESC for `e`, aren't sent when
+//! they're pressed, so "e+BACKSPACE -> DELETE" doesn't type ESC. Instead, they're tapped when they're
+//! released, unless a rule has fired while they were held. If a key that no rule matches is pressed
+//! first, they're pressed before it, so e.g. ESC+X still works.
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Context};
+use parking_lot::Mutex;
+
+use crate::{
+    evdev::{ec, InputEvent},
+    macros::MacroTiming,
+    modifiers::{ModifierRegistry, ModifierSpec},
+    KeyRemapper, KeyRemapperConfiguration,
+};
+
+/// What to do when a rule matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleAction {
+    /// Press a key with the given modifiers, using `KeyRemapper::press_key()`.
+    Press { code: i32, modifiers: String },
+
+    /// Swallow the event. Written as "NONE" in a rule.
+    Ignore,
+
+    /// Play a saved macro with the original timing, using `KeyRemapper::play_saved_macro()`.
+    /// Written as "macro:NAME" in a rule.
+    PlayMacro(String),
+}
+
+/// A single rule, e.g. "e+BACKSPACE -> DELETE". The modifiers of the left hand side are checked
+/// when the rules are used, as they may include the ones added to the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub code: i32,
+    pub modifiers: String,
+    pub action: RuleAction,
+}
+
+fn parse_modifiers(modifiers: &str, valid_modifiers: &str) -> anyhow::Result<String> {
+    for m in modifiers.chars() {
+        if !valid_modifiers.contains(m) {
+            bail!(r#"Modifier "{}" contains an invalid character "{}""#, modifiers, m);
+        }
+    }
+    ModifierSpec::try_parse(ModifierRegistry::builtin(), modifiers)?;
+    return Ok(modifiers.to_string());
+}
+
+/// Parse one side of a rule and return the key name and the modifiers.
+fn split_key_and_modifiers(side: &str) -> (&str, String) {
+    if let Some(pos) = side.find(" with ") {
+        return (side[..pos].trim(), side[pos + " with ".len()..].trim().to_string());
+    }
+    let mut parts: Vec<&str> = side.split('+').map(|s| s.trim()).collect();
+    let key = parts.pop().unwrap();
+    return (key, parts.concat());
+}
+
+fn parse_key(name: &str) -> anyhow::Result<i32> {
+    return ec::get_key_code_by_name(name).ok_or_else(|| anyhow!(r#"Unknown key "{}""#, name));
+}
+
+impl Rule {
+    pub fn parse(rule: &str) -> anyhow::Result<Rule> {
+        let (from, to) = match rule.find("->") {
+            Some(pos) => (rule[..pos].trim(), rule[pos + 2..].trim()),
+            None => bail!(r#"Rule "{}" doesn't contain "->""#, rule),
+        };
+        if from.is_empty() || to.is_empty() {
+            bail!(r#"Rule "{}" is incomplete"#, rule);
+        }
+
+        let (from_key, modifiers) = split_key_and_modifiers(from);
+        let code = parse_key(from_key).with_context(|| format!(r#"Invalid rule "{}""#, rule))?;
+
+        let action = if to.eq_ignore_ascii_case("NONE") {
+            RuleAction::Ignore
+        } else if let Some(name) = to.strip_prefix("macro:") {
+            if name.trim().is_empty() {
+                bail!(r#"Invalid rule "{}": macro name is missing"#, rule);
+            }
+            RuleAction::PlayMacro(name.trim().to_string())
+        } else {
+            let (to_key, to_modifiers) = split_key_and_modifiers(to);
+            let to_modifiers = parse_modifiers(&to_modifiers, "acsw<>*").with_context(|| format!(r#"Invalid rule "{}""#, rule))?;
+            if to_modifiers.contains('*') && to_modifiers != "*" {
+                bail!(r#"Invalid rule "{}": "*" can't be combined with other modifiers"#, rule);
+            }
+            RuleAction::Press {
+                code: parse_key(to_key).with_context(|| format!(r#"Invalid rule "{}""#, rule))?,
+                modifiers: to_modifiers,
+            }
+        };
+
+        return Ok(Rule { code, modifiers, action });
+    }
+
+    fn matches(&self, ev: &InputEvent) -> bool {
+        return ev.is_key(self.code, &self.modifiers);
+    }
+
+    /// Parse the modifiers of the left hand side with `registry`.
+    fn modifier_spec(&self, registry: &ModifierRegistry) -> anyhow::Result<ModifierSpec> {
+        return ModifierSpec::try_parse(registry, &self.modifiers).with_context(|| format!("Invalid rule for {}", ec::get_code_name(ec::EV_KEY, self.code)));
+    }
+}
+
+/// A compiled set of `Rule`s, indexed by the key code.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: HashMap<i32, Vec<Rule>>,
+}
+
+impl Rules {
+    pub fn new() -> Rules {
+        return Rules::default();
+    }
+
+    /// Compile rules from strings, e.g. `&["e+BACKSPACE -> DELETE"]`.
+    pub fn from_strs(rules: &[&str]) -> anyhow::Result<Rules> {
+        let mut ret = Rules::new();
+        for rule in rules {
+            ret.add(Rule::parse(rule)?);
+        }
+        return Ok(ret);
+    }
+
+    /// Add a rule. When multiple rules match the same event, the one added first wins.
+    pub fn add(&mut self, rule: Rule) -> &mut Rules {
+        self.rules.entry(rule.code).or_default().push(rule);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        return self.rules.values().map(|v| v.len()).sum();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.rules.is_empty();
+    }
+
+    /// Check that the modifiers of the rules are in `registry`.
+    pub(crate) fn validate(&self, registry: &ModifierRegistry) -> anyhow::Result<()> {
+        for rule in self.rules.values().flatten() {
+            rule.modifier_spec(registry)?;
+        }
+        return Ok(());
+    }
+
+    /// Find the rule matching a given event.
+    pub fn find(&self, ev: &InputEvent) -> Option<&Rule> {
+        if !ev.is_key_event() {
+            return None;
+        }
+        return self.rules.get(&ev.code)?.iter().find(|rule| rule.matches(ev));
+    }
+
+    /// Apply the rule matching a given event, and return true if any rule matched.
+    /// Key down and repeat events trigger the action, and key up events are just swallowed.
+    pub fn handle_event(&self, km: &KeyRemapper, ev: &InputEvent) -> bool {
+        let rule = match self.find(ev) {
+            Some(rule) => rule,
+            None => return false,
+        };
+        if ev.is_key_on_event() {
+            match &rule.action {
+                RuleAction::Press { code, modifiers } => km.press_key(*code, modifiers),
+                RuleAction::Ignore => {}
+                RuleAction::PlayMacro(name) => {
+                    // Don't restart the macro on auto-repeat.
+                    if ev.is_key_down_event() {
+                        km.play_saved_macro(name, MacroTiming::Original);
+                    }
+                }
+            }
+        }
+        return true;
+    }
+}
+
+/// Modifiers that the host knows, whose keys are sent as-is.
+const HOST_MODIFIERS: &str = "acsw";
+
+/// State of a held back key that is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeldBackKey {
+    /// Not sent yet. It's tapped when it's released.
+    Pending,
+
+    /// A rule has fired while it's held, so it's not sent at all.
+    Used,
+
+    /// A key that no rule matches was pressed while it's held, so it's been pressed on the output.
+    Sent,
+}
+
+/// Applies `Rules` to the events for `KeyRemapperConfiguration::set_rules()`, holding back the keys
+/// of the other modifiers that the rules use, like keyboard-remapper does for ESC.
+pub(crate) struct RuleRemapper {
+    rules: Rules,
+
+    /// Keys that only act as modifiers, e.g. ESC for "e+BACKSPACE".
+    held_back_keys: HashSet<i32>,
+
+    /// Keys of `HOST_MODIFIERS`, which don't make the held back keys sent, e.g. for "e+s+BACKSPACE".
+    host_modifier_keys: HashSet<i32>,
+
+    /// The held back keys that are pressed.
+    pending: Mutex<HashMap<i32, HeldBackKey>>,
+}
+
+impl RuleRemapper {
+    /// Panics if the rules use a modifier that isn't in `registry`, i.e. the configuration's.
+    pub(crate) fn new(rules: Rules, registry: &ModifierRegistry) -> RuleRemapper {
+        if let Err(e) = rules.validate(registry) {
+            panic!("{:#}", e);
+        }
+        let host_modifier_keys: HashSet<i32> = HOST_MODIFIERS.chars().flat_map(|name| registry.keys(name).iter().copied()).collect();
+        let held_back_keys = rules
+            .rules
+            .values()
+            .flatten()
+            .flat_map(|rule| rule.modifier_spec(registry).unwrap().keys(registry))
+            .filter(|key| !host_modifier_keys.contains(key))
+            // Keys with their own rules are remapped instead.
+            .filter(|key| !rules.rules.contains_key(key))
+            .collect();
+        return RuleRemapper {
+            rules,
+            held_back_keys,
+            host_modifier_keys,
+            pending: Mutex::new(HashMap::new()),
+        };
+    }
+
+    pub(crate) fn on_event(&self, km: &KeyRemapper, ev: &InputEvent) {
+        if ev.is_key_event() && self.held_back_keys.contains(&ev.code) {
+            let mut pending = self.pending.lock();
+            match ev.value {
+                1 => {
+                    pending.insert(ev.code, HeldBackKey::Pending);
+                }
+                0 => match pending.remove(&ev.code) {
+                    Some(HeldBackKey::Pending) => km.press_key(ev.code, "*"),
+                    Some(HeldBackKey::Sent) => km.send_event(ev),
+                    _ => {}
+                },
+                _ => {
+                    // Only repeat it once it's been sent.
+                    if pending.get(&ev.code) == Some(&HeldBackKey::Sent) {
+                        km.send_event(ev);
+                    }
+                }
+            }
+            return;
+        }
+        if self.rules.handle_event(km, ev) {
+            for state in self.pending.lock().values_mut() {
+                if *state == HeldBackKey::Pending {
+                    *state = HeldBackKey::Used;
+                }
+            }
+            return;
+        }
+        if ev.is_key_down_event() && !self.host_modifier_keys.contains(&ev.code) {
+            // Not a rule for the held back keys, so press them first, e.g. ESC+X.
+            let mut pending = self.pending.lock();
+            let mut codes: Vec<i32> = pending
+                .iter()
+                .filter(|(_, state)| **state == HeldBackKey::Pending)
+                .map(|(code, _)| *code)
+                .collect();
+            codes.sort_unstable();
+            for code in codes {
+                km.send_event(&InputEvent::new_key_event(code, 1));
+                pending.insert(code, HeldBackKey::Sent);
+            }
+        }
+        km.send_event(ev);
+    }
+}
+
+/// Content of a rule file.
+#[derive(Debug, Clone)]
+pub struct RuleFile {
+    pub name: String,
+    pub device_name_regex: String,
+    pub id_regex: String,
+    pub icon: Option<PathBuf>,
+    pub rules: Rules,
+
+    /// Modifiers to add to the configuration, with their names and keys.
+    pub modifiers: Vec<(char, Vec<i32>)>,
+}
+
+impl RuleFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<RuleFile> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).with_context(|| format!("Unable to read rule file {:?}", path))?;
+        let default_name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        return RuleFile::parse(&content, &default_name).with_context(|| format!("Unable to parse rule file {:?}", path));
+    }
+
+    pub fn parse(content: &str, default_name: &str) -> anyhow::Result<RuleFile> {
+        let table: toml::Table = content.parse()?;
+
+        let get_str = |key: &str| -> anyhow::Result<Option<String>> {
+            return match table.get(key) {
+                None => Ok(None),
+                Some(toml::Value::String(s)) => Ok(Some(s.clone())),
+                Some(_) => bail!(r#""{}" must be a string"#, key),
+            };
+        };
+
+        let mut rules = Rules::new();
+        match table.get("rules") {
+            None => {}
+            Some(toml::Value::Array(array)) => {
+                for value in array {
+                    match value.as_str() {
+                        Some(rule) => rules.add(Rule::parse(rule)?),
+                        None => bail!(r#""rules" must be an array of strings"#),
+                    };
+                }
+            }
+            Some(_) => bail!(r#""rules" must be an array of strings"#),
+        }
+
+        let mut registry = ModifierRegistry::new();
+        let mut modifiers = vec![];
+        match table.get("modifiers") {
+            None => {}
+            Some(toml::Value::Table(modifier_table)) => {
+                for (name, keys) in modifier_table {
+                    let mut chars = name.chars();
+                    let name = match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => bail!(r#"Modifier name "{}" must be a single character"#, name),
+                    };
+                    let keys = match keys.as_array() {
+                        Some(keys) => keys
+                            .iter()
+                            .map(|key| {
+                                key.as_str()
+                                    .ok_or_else(|| anyhow!(r#"Keys of modifier "{}" must be strings"#, name))
+                                    .and_then(parse_key)
+                            })
+                            .collect::<anyhow::Result<Vec<i32>>>()?,
+                        None => bail!(r#"Keys of modifier "{}" must be an array"#, name),
+                    };
+                    registry.try_register(name, &keys)?;
+                    modifiers.push((name, keys));
+                }
+            }
+            Some(_) => bail!(r#""modifiers" must be a table"#),
+        }
+        rules.validate(&registry)?;
+
+        return Ok(RuleFile {
+            name: get_str("name")?.unwrap_or(default_name.to_string()),
+            device_name_regex: get_str("device_name_regex")?.ok_or_else(|| anyhow!(r#""device_name_regex" is missing"#))?,
+            id_regex: get_str("id_regex")?.unwrap_or("^".to_string()),
+            icon: get_str("icon")?.map(PathBuf::from),
+            rules,
+            modifiers,
+        });
+    }
+
+    /// Create a `KeyRemapperConfiguration` that remaps the devices using the rules.
+    pub fn to_config(&self) -> KeyRemapperConfiguration {
+        let mut config = KeyRemapperConfiguration::new(&self.name, &self.device_name_regex);
+        for (name, keys) in &self.modifiers {
+            config.add_modifier(*name, keys);
+        }
+        config.set_id_regex(&self.id_regex).set_rules(self.rules.clone());
+        if let Some(icon) = &self.icon {
+            config.set_icon(icon);
+        }
+        return config;
+    }
+}
+
+#[test]
+fn test_rule_parse() {
+    assert_eq!(
+        Rule {
+            code: ec::KEY_BACKSPACE,
+            modifiers: "e".to_string(),
+            action: RuleAction::Press {
+                code: ec::KEY_DELETE,
+                modifiers: "".to_string()
+            }
+        },
+        Rule::parse("e+BACKSPACE -> DELETE").unwrap()
+    );
+    assert_eq!(
+        Rule {
+            code: ec::KEY_HOME,
+            modifiers: "e".to_string(),
+            action: RuleAction::Press {
+                code: ec::KEY_LEFT,
+                modifiers: "a".to_string()
+            }
+        },
+        Rule::parse("KEY_HOME with e -> LEFT with a").unwrap()
+    );
+    assert_eq!(
+        Rule {
+            code: ec::KEY_TAB,
+            modifiers: "cs".to_string(),
+            action: RuleAction::Ignore,
+        },
+        Rule::parse("c+s+TAB -> none").unwrap()
+    );
+
+    assert_eq!(
+        RuleAction::PlayMacro("greeting".to_string()),
+        Rule::parse("e+F10 -> macro:greeting").unwrap().action
+    );
+
+    assert!(Rule::parse("BACKSPACE DELETE").is_err());
+    assert!(Rule::parse("F10 -> macro:").is_err());
+    assert!(Rule::parse("BACKSPACE -> DELETE with e").is_err());
+    assert!(Rule::parse("BACKSPACE -> NO_SUCH_KEY").is_err());
+}
+
+#[test]
+fn test_rules_find() {
+    let rules = Rules::from_strs(&["e+BACKSPACE -> DELETE", "*+BACKSPACE -> NONE", "a+ENTER -> F1"]).unwrap();
+    assert_eq!(3, rules.len());
+
+    let registry = ModifierRegistry::builtin();
+    let mut ev = InputEvent::new_key_event(ec::KEY_BACKSPACE, 1);
+    ev.set_modifiers(registry, registry.build(|_, key, _| key == ec::KEY_ESC));
+    assert_eq!(Some(&Rule::parse("e+BACKSPACE -> DELETE").unwrap()), rules.find(&ev));
+
+    ev.set_modifiers(registry, registry.build(|_, key, _| key == ec::KEY_RIGHTCTRL));
+    assert_eq!(Some(&Rule::parse("*+BACKSPACE -> NONE").unwrap()), rules.find(&ev));
+
+    assert_eq!(None, rules.find(&InputEvent::new_key_event(ec::KEY_ENTER, 1)));
+    assert_eq!(None, rules.find(&InputEvent::new(ec::EventType::EV_REL, ec::KEY_BACKSPACE, 1)));
+}
+
+#[test]
+fn test_rules_validate() {
+    let rules = Rules::from_strs(&["x+BACKSPACE -> DELETE"]).unwrap();
+    assert!(rules.validate(ModifierRegistry::builtin()).is_err());
+
+    let mut registry = ModifierRegistry::new();
+    registry.register('x', &[ec::KEY_CAPSLOCK]);
+    assert!(rules.validate(&registry).is_ok());
+    assert!(Rules::from_strs(&["<x+BACKSPACE -> DELETE"]).unwrap().validate(&registry).is_err());
+}
+
+#[test]
+fn test_rule_file_parse() {
+    let file = RuleFile::parse(
+        r#"
+        device_name_regex = "^AT Translated Set 2 keyboard"
+        rules = [
+            "e+BACKSPACE -> DELETE",
+            "KEY_HOME with e -> LEFT with a",
+        ]
+        "#,
+        "default-name",
+    )
+    .unwrap();
+    assert_eq!("default-name", file.name);
+    assert_eq!("^AT Translated Set 2 keyboard", file.device_name_regex);
+    assert_eq!("^", file.id_regex);
+    assert_eq!(None, file.icon);
+    assert_eq!(2, file.rules.len());
+
+    assert!(RuleFile::parse(r#"rules = []"#, "x").is_err());
+    assert!(RuleFile::parse("device_name_regex = \"x\"\nrules = [1]", "x").is_err());
+
+    // Rules can use the modifiers of the file.
+    let file = RuleFile::parse(
+        r#"
+        device_name_regex = "x"
+        rules = ["p+J -> LEFT"]
+        modifiers = { p = ["CAPSLOCK"] }
+        "#,
+        "x",
+    )
+    .unwrap();
+    assert_eq!(vec![('p', vec![ec::KEY_CAPSLOCK])], file.modifiers);
+    assert!(RuleFile::parse("device_name_regex = \"x\"\nrules = [\"p+J -> LEFT\"]", "x").is_err());
+    assert!(RuleFile::parse("device_name_regex = \"x\"\nmodifiers = { e = [\"CAPSLOCK\"] }", "x").is_err());
+}
+
+#[test]
+fn test_rules_hold_back_modifier_keys() {
+    use crate::testing::TestRemapper;
+
+    let mut config = KeyRemapperConfiguration::new("Test", "");
+    config.set_rules(Rules::from_strs(&["e+BACKSPACE -> DELETE", "s+BACKSPACE -> DELETE"]).unwrap());
+    let t = TestRemapper::new(&config);
+    let kbd = t.add_keyboard("keyboard");
+    t.start();
+
+    // ESC + BACKSPACE doesn't type ESC.
+    t.press(&kbd, ec::KEY_ESC);
+    t.send_key(&kbd, ec::KEY_ESC, 2);
+    t.tap(&kbd, ec::KEY_BACKSPACE);
+    t.release(&kbd, ec::KEY_ESC);
+    assert_eq!(vec![(ec::KEY_DELETE, 1), (ec::KEY_DELETE, 0)], t.take_output_keys());
+
+    // ESC alone is tapped on release.
+    t.tap(&kbd, ec::KEY_ESC);
+    assert_eq!(vec![(ec::KEY_ESC, 1), (ec::KEY_ESC, 0)], t.take_output_keys());
+
+    // With a key that no rule matches, ESC is pressed before it, and then it's sent as-is.
+    t.press(&kbd, ec::KEY_ESC);
+    t.tap(&kbd, ec::KEY_X);
+    t.send_key(&kbd, ec::KEY_ESC, 2);
+    t.release(&kbd, ec::KEY_ESC);
+    assert_eq!(
+        vec![(ec::KEY_ESC, 1), (ec::KEY_X, 1), (ec::KEY_X, 0), (ec::KEY_ESC, 2), (ec::KEY_ESC, 0)],
+        t.take_output_keys()
+    );
+
+    // Shift doesn't make ESC sent, so a rule can still fire.
+    t.press(&kbd, ec::KEY_ESC);
+    t.press(&kbd, ec::KEY_LEFTSHIFT);
+    t.release(&kbd, ec::KEY_LEFTSHIFT);
+    t.release(&kbd, ec::KEY_ESC);
+    assert_eq!(
+        vec![(ec::KEY_LEFTSHIFT, 1), (ec::KEY_LEFTSHIFT, 0), (ec::KEY_ESC, 1), (ec::KEY_ESC, 0)],
+        t.take_output_keys()
+    );
+
+    // Shift is sent as-is.
+    t.press(&kbd, ec::KEY_LEFTSHIFT);
+    assert_eq!(vec![(ec::KEY_LEFTSHIFT, 1)], t.take_output_keys());
+    t.release(&kbd, ec::KEY_LEFTSHIFT);
+    assert_eq!(vec![(ec::KEY_LEFTSHIFT, 0)], t.take_output_keys());
+}
+
+#[test]
+fn test_rules_custom_modifiers() {
+    use crate::testing::TestRemapper;
+
+    let mut config = KeyRemapperConfiguration::new("Test", "");
+    config.add_modifier('p', &[ec::KEY_F13, ec::KEY_F14]);
+    config.set_rules(Rules::from_strs(&[">p+J -> LEFT"]).unwrap());
+    let t = TestRemapper::new(&config);
+    let kbd = t.add_keyboard("keyboard");
+    t.start();
+
+    // Only the right key is held back.
+    t.press(&kbd, ec::KEY_F14);
+    t.tap(&kbd, ec::KEY_J);
+    t.release(&kbd, ec::KEY_F14);
+    assert_eq!(vec![(ec::KEY_LEFT, 1), (ec::KEY_LEFT, 0)], t.take_output_keys());
+
+    t.press(&kbd, ec::KEY_F13);
+    t.tap(&kbd, ec::KEY_J);
+    t.release(&kbd, ec::KEY_F13);
+    assert_eq!(vec![(ec::KEY_F13, 1), (ec::KEY_J, 1), (ec::KEY_J, 0), (ec::KEY_F13, 0)], t.take_output_keys());
+}
+
+#[test]
+#[should_panic(expected = r#"Invalid rule for KEY_J"#)]
+fn test_rules_unknown_modifier() {
+    let mut config = KeyRemapperConfiguration::new("Test", "");
+    config.set_rules(Rules::from_strs(&["p+J -> LEFT"]).unwrap());
+}
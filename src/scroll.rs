@@ -0,0 +1,346 @@
+//! Scrolling filters for pointer devices.
+//!
+//! Wheel events are sent as both the high-resolution ones, where 120 is a detent, and the
+//! classic ones for the apps that don't support high-resolution scrolling.
+use std::time::{Duration, Instant};
+
+use crate::evdev::{
+    ec::{self, EventType},
+    InputEvent,
+};
+
+/// Units of REL_WHEEL_HI_RES / REL_HWHEEL_HI_RES per detent.
+pub const HI_RES_PER_DETENT: i32 = 120;
+
+/// Converts scroll amounts into wheel events, keeping the fractions and the partial detents.
+#[derive(Debug, Clone, Default)]
+struct WheelAccumulator {
+    /// Horizontal, vertical.
+    remainders: [f64; 2],
+    partial_detents: [i32; 2],
+}
+
+impl WheelAccumulator {
+    /// Return the events to scroll by `amount` in high-resolution units. `axis` is 0 for horizontal
+    /// and 1 for vertical.
+    fn scroll(&mut self, axis: usize, amount: f64) -> Vec<InputEvent> {
+        let (hi_res_code, code) = [(ec::REL_HWHEEL_HI_RES, ec::REL_HWHEEL), (ec::REL_WHEEL_HI_RES, ec::REL_WHEEL)][axis];
+        let total = self.remainders[axis] + amount;
+        let hi_res = total.trunc();
+        self.remainders[axis] = total - hi_res;
+        if hi_res == 0.0 {
+            return vec![];
+        }
+
+        let mut ret = vec![InputEvent::new(EventType::EV_REL, hi_res_code, hi_res as i32)];
+        self.partial_detents[axis] += hi_res as i32;
+        let detents = self.partial_detents[axis] / HI_RES_PER_DETENT;
+        if detents != 0 {
+            self.partial_detents[axis] -= detents * HI_RES_PER_DETENT;
+            ret.push(InputEvent::new(EventType::EV_REL, code, detents));
+        }
+        return ret;
+    }
+
+    fn reset(&mut self) {
+        *self = WheelAccumulator::default();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MiddleButtonState {
+    Released,
+    Pressed { at: Instant, moved: bool },
+}
+
+/// "Hold the middle button and move to scroll" for pointing sticks, like libinput's on-button scrolling,
+/// which is lost when the device is grabbed.
+///
+/// BTN_MIDDLE is held back. While it's pressed, REL_X / REL_Y are converted to the wheel events. If
+/// it's released without moving within the click timeout, a middle click is sent instead.
+#[derive(Debug, Clone)]
+pub struct MiddleButtonScroll {
+    speed: f64,
+    click_timeout: Duration,
+    state: MiddleButtonState,
+    wheel: WheelAccumulator,
+}
+
+impl MiddleButtonScroll {
+    pub fn new() -> MiddleButtonScroll {
+        return MiddleButtonScroll {
+            speed: 15.0,
+            click_timeout: Duration::from_millis(300),
+            state: MiddleButtonState::Released,
+            wheel: WheelAccumulator::default(),
+        };
+    }
+
+    /// High-resolution wheel units per movement unit. The default is 15, i.e. 8 units for a detent.
+    pub fn set_speed(&mut self, value: f64) -> &mut MiddleButtonScroll {
+        self.speed = value;
+        self
+    }
+
+    /// The middle button needs to be released within this duration to click. The default is 300ms.
+    pub fn set_click_timeout(&mut self, value: Duration) -> &mut MiddleButtonScroll {
+        self.click_timeout = value;
+        self
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        return self.state != MiddleButtonState::Released;
+    }
+
+    /// Feed an event, and return the events to send instead. Moving up scrolls up.
+    pub fn on_event(&mut self, ev: &InputEvent, now: Instant) -> Vec<InputEvent> {
+        match (ev.event_type, ev.code, self.state) {
+            (EventType::EV_KEY, ec::BTN_MIDDLE, MiddleButtonState::Released) if ev.value == 1 => {
+                self.state = MiddleButtonState::Pressed { at: now, moved: false };
+                self.wheel.reset();
+                return vec![];
+            }
+            (EventType::EV_KEY, ec::BTN_MIDDLE, MiddleButtonState::Pressed { at, moved }) => {
+                if ev.value != 0 {
+                    return vec![]; // Auto-repeat.
+                }
+                self.state = MiddleButtonState::Released;
+                if moved || now.saturating_duration_since(at) > self.click_timeout {
+                    return vec![];
+                }
+                return vec![
+                    InputEvent::new_key_event(ec::BTN_MIDDLE, 1),
+                    InputEvent::new_syn_report(),
+                    InputEvent::new_key_event(ec::BTN_MIDDLE, 0),
+                ];
+            }
+            (EventType::EV_REL, ec::REL_X, MiddleButtonState::Pressed { at, .. }) => {
+                self.state = MiddleButtonState::Pressed { at, moved: true };
+                return self.wheel.scroll(0, ev.value as f64 * self.speed);
+            }
+            (EventType::EV_REL, ec::REL_Y, MiddleButtonState::Pressed { at, .. }) => {
+                self.state = MiddleButtonState::Pressed { at, moved: true };
+                return self.wheel.scroll(1, -ev.value as f64 * self.speed);
+            }
+            _ => return vec![*ev],
+        }
+    }
+}
+
+impl Default for MiddleButtonScroll {
+    fn default() -> Self {
+        return MiddleButtonScroll::new();
+    }
+}
+
+/// Smooth scrolling with momentum, e.g. for scrolling with keys. Like touchpad scrolling, it sends
+/// small REL_WHEEL_HI_RES steps, speeds up while a direction is held, and slows down gradually after
+/// it's released.
+///
+/// Call `tick()` periodically, e.g. with `KeyRemapper::schedule_periodic()`, and send the returned
+/// events to a device created with `KeyRemapper::create_mouse_uinput()`. Use a separate scroller for
+/// each device to configure them separately.
+#[derive(Debug, Clone)]
+pub struct InertialScroller {
+    initial_speed: f64,
+    max_speed: f64,
+    acceleration_time: Duration,
+    friction: f64,
+
+    /// Horizontal, vertical. -1, 0 or 1.
+    directions: [i32; 2],
+    held_since: [Instant; 2],
+
+    /// In high-resolution units per second.
+    velocities: [f64; 2],
+    last_tick: Option<Instant>,
+    wheel: WheelAccumulator,
+}
+
+impl InertialScroller {
+    pub fn new() -> InertialScroller {
+        let now = Instant::now();
+        return InertialScroller {
+            initial_speed: 10.0,
+            max_speed: 60.0,
+            acceleration_time: Duration::from_secs(1),
+            friction: 4.0,
+            directions: [0, 0],
+            held_since: [now, now],
+            velocities: [0.0, 0.0],
+            last_tick: None,
+            wheel: WheelAccumulator::default(),
+        };
+    }
+
+    /// Detents per second when a direction is pressed. The default is 10.
+    pub fn set_initial_speed(&mut self, value: f64) -> &mut InertialScroller {
+        self.initial_speed = value;
+        self
+    }
+
+    /// Detents per second after `acceleration_time`. The default is 60.
+    pub fn set_max_speed(&mut self, value: f64) -> &mut InertialScroller {
+        self.max_speed = value;
+        self
+    }
+
+    /// How long it takes to go from the initial speed to the max speed. The default is 1s.
+    pub fn set_acceleration_time(&mut self, value: Duration) -> &mut InertialScroller {
+        self.acceleration_time = value;
+        self
+    }
+
+    /// How quickly the momentum decays after a direction is released. The speed is multiplied by
+    /// `e ^ (-friction * seconds)`, so higher is shorter. The default is 4. Use `f64::INFINITY` to
+    /// stop immediately.
+    pub fn set_friction(&mut self, value: f64) -> &mut InertialScroller {
+        self.friction = value;
+        self
+    }
+
+    /// Scroll right with 1, left with -1, and release with 0.
+    pub fn set_horizontal(&mut self, direction: i32, now: Instant) {
+        self.set_direction(0, direction, now);
+    }
+
+    /// Scroll up with 1, down with -1, and release with 0.
+    pub fn set_vertical(&mut self, direction: i32, now: Instant) {
+        self.set_direction(1, direction, now);
+    }
+
+    fn set_direction(&mut self, axis: usize, direction: i32, now: Instant) {
+        let direction = direction.signum();
+        if !self.is_active() {
+            self.last_tick = Some(now);
+        }
+        if direction != 0 && direction != self.directions[axis] {
+            self.held_since[axis] = now;
+        }
+        self.directions[axis] = direction;
+    }
+
+    /// Stop scrolling immediately, without the momentum.
+    pub fn stop(&mut self) {
+        self.directions = [0, 0];
+        self.velocities = [0.0, 0.0];
+        self.last_tick = None;
+        self.wheel.reset();
+    }
+
+    /// Return true if it's scrolling, either because a direction is held or with the momentum.
+    pub fn is_active(&self) -> bool {
+        return self.directions != [0, 0] || self.velocities != [0.0, 0.0];
+    }
+
+    /// Return the wheel events to send for the time since the last tick.
+    pub fn tick(&mut self, now: Instant) -> Vec<InputEvent> {
+        if !self.is_active() {
+            self.last_tick = None;
+            return vec![];
+        }
+        let elapsed = now.saturating_duration_since(self.last_tick.unwrap_or(now)).as_secs_f64();
+        self.last_tick = Some(now);
+
+        let mut ret = vec![];
+        for axis in 0..2 {
+            let direction = self.directions[axis];
+            if direction != 0 {
+                let held = now.saturating_duration_since(self.held_since[axis]).as_secs_f64();
+                let progress = if self.acceleration_time.is_zero() {
+                    1.0
+                } else {
+                    (held / self.acceleration_time.as_secs_f64()).min(1.0)
+                };
+                let speed = self.initial_speed + (self.max_speed - self.initial_speed) * progress;
+                self.velocities[axis] = direction as f64 * speed * HI_RES_PER_DETENT as f64;
+            } else {
+                self.velocities[axis] *= (-self.friction * elapsed).exp();
+                // Stop below half a detent per second.
+                if self.velocities[axis].abs() < HI_RES_PER_DETENT as f64 / 2.0 {
+                    self.velocities[axis] = 0.0;
+                }
+            }
+            ret.extend(self.wheel.scroll(axis, self.velocities[axis] * elapsed));
+        }
+        return ret;
+    }
+}
+
+impl Default for InertialScroller {
+    fn default() -> Self {
+        return InertialScroller::new();
+    }
+}
+
+#[test]
+fn test_middle_button_scroll() {
+    let rel = |code, value| InputEvent::new(EventType::EV_REL, code, value);
+    let middle = |value| InputEvent::new_key_event(ec::BTN_MIDDLE, value);
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+
+    let mut scroll = MiddleButtonScroll::new();
+    scroll.set_speed(40.0);
+
+    // A quick click is sent as-is.
+    assert!(scroll.on_event(&middle(1), at(0)).is_empty());
+    assert_eq!(vec![InputEvent::new_syn_report()], scroll.on_event(&InputEvent::new_syn_report(), at(0)));
+    assert_eq!(vec![middle(1), InputEvent::new_syn_report(), middle(0)], scroll.on_event(&middle(0), at(100)));
+
+    // Moving up scrolls up, with a detent every 120 units.
+    scroll.on_event(&middle(1), at(1000));
+    assert_eq!(vec![rel(ec::REL_WHEEL_HI_RES, 80)], scroll.on_event(&rel(ec::REL_Y, -2), at(1010)));
+    assert_eq!(
+        vec![rel(ec::REL_WHEEL_HI_RES, 80), rel(ec::REL_WHEEL, 1)],
+        scroll.on_event(&rel(ec::REL_Y, -2), at(1020))
+    );
+    assert_eq!(vec![rel(ec::REL_HWHEEL_HI_RES, -40)], scroll.on_event(&rel(ec::REL_X, -1), at(1030)));
+    assert!(scroll.on_event(&middle(0), at(1040)).is_empty());
+
+    // Holding too long without moving doesn't click.
+    scroll.on_event(&middle(1), at(2000));
+    assert!(scroll.on_event(&middle(0), at(2500)).is_empty());
+    assert_eq!(vec![rel(ec::REL_X, 3)], scroll.on_event(&rel(ec::REL_X, 3), at(2600)));
+}
+
+#[test]
+fn test_inertial_scroller() {
+    let rel = |code, value| InputEvent::new(EventType::EV_REL, code, value);
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+
+    let mut scroller = InertialScroller::new();
+    scroller
+        .set_initial_speed(10.0)
+        .set_max_speed(20.0)
+        .set_acceleration_time(Duration::from_millis(1000));
+    assert!(scroller.tick(at(0)).is_empty());
+
+    // About 10 detents per second at first, then it speeds up to 20.
+    scroller.set_vertical(1, at(0));
+    assert_eq!(vec![rel(ec::REL_WHEEL_HI_RES, 12)], scroller.tick(at(10)));
+    assert_eq!(vec![rel(ec::REL_WHEEL_HI_RES, 118), rel(ec::REL_WHEEL, 1)], scroller.tick(at(100)));
+    assert_eq!(rel(ec::REL_WHEEL_HI_RES, 2184), scroller.tick(at(1010))[0]);
+
+    // It slows down after the release, and eventually stops.
+    scroller.set_vertical(0, at(1010));
+    let mut last = i32::MAX - 1;
+    let mut time = 1010;
+    while scroller.is_active() {
+        time += 10;
+        let events = scroller.tick(at(time));
+        if let Some(ev) = events.iter().find(|ev| ev.code == ec::REL_WHEEL_HI_RES) {
+            // Off by one at most, because of the carried fractions.
+            assert!(ev.value > 0 && ev.value <= last + 1);
+            last = ev.value;
+        }
+    }
+    assert!(time < 3000);
+
+    // Stopping drops the momentum.
+    scroller.set_horizontal(-1, at(5000));
+    assert_eq!(vec![rel(ec::REL_HWHEEL_HI_RES, -12)], scroller.tick(at(5010)));
+    scroller.stop();
+    assert!(!scroller.is_active());
+}
@@ -99,10 +99,13 @@ pub(crate) fn make_timespec(duration: time::Duration) -> libc::timespec {
     }
 }
 
-pub(crate) fn select(fds: &Vec<RawFd>) -> io::Result<RawFd> {
+/// Wait until any of `fds` becomes readable and return it, or return `None` if none becomes ready
+/// within `timeout`.
+pub(crate) fn select(fds: &Vec<RawFd>, timeout: Option<time::Duration>) -> io::Result<Option<RawFd>> {
     if fds.len() == 0 {
         panic!("fds can't be empty");
     }
+    let timespec = timeout.map(make_timespec);
     unsafe {
         loop {
             let mut fd_set = FdSet::new();
@@ -114,10 +117,13 @@ pub(crate) fn select(fds: &Vec<RawFd>) -> io::Result<RawFd> {
             let mut sigmask: libc::sigset_t = MaybeUninit::zeroed().assume_init();
             libc::sigemptyset(&mut sigmask as *mut libc::sigset_t);
 
-            pselect(max + 1, Some(&mut fd_set), None, None, None, Some(&sigmask))?;
+            let ready = pselect(max + 1, Some(&mut fd_set), None, None, timespec.as_ref(), Some(&sigmask))?;
+            if ready == 0 && timeout.is_some() {
+                return Ok(None);
+            }
             for i in 0..(max + 1) {
                 if fd_set.is_set(i) {
-                    return Ok(i);
+                    return Ok(Some(i));
                 }
             }
             eprintln!("No fds selected after pselect()!");
@@ -0,0 +1,339 @@
+//! Tap-hold (dual-role) keys, e.g. ESC that sends ESC when tapped alone, but acts as a modifier
+//! when held. See `KeyRemapperConfiguration::add_tap_hold_key()`.
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use crate::evdev::InputEvent;
+
+/// What a tap-hold key does while it's held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldAction {
+    /// Act as a modifier for `InputEvent::with_modifiers()` and `KeyRemapper::is_*_on()`, e.g. `'e'`.
+    /// It counts as the modifier's first (left) key. Nothing is sent to the uinput device.
+    Modifier(char),
+
+    /// Keep a key (e.g. `KEY_LEFTCTRL`) pressed on the uinput device.
+    Key(i32),
+}
+
+/// Configuration of a single tap-hold key.
+#[derive(Debug, Clone)]
+pub struct TapHoldKey {
+    pub(crate) code: i32,
+    pub(crate) tap_code: i32,
+    pub(crate) hold: HoldAction,
+    pub(crate) tapping_term: Duration,
+    pub(crate) permissive_hold: bool,
+    pub(crate) hold_on_other_key_press: bool,
+}
+
+impl TapHoldKey {
+    /// `code` sends `tap_code` when tapped, and does `hold` when held.
+    pub fn new(code: i32, tap_code: i32, hold: HoldAction) -> TapHoldKey {
+        return TapHoldKey {
+            code,
+            tap_code,
+            hold,
+            tapping_term: Duration::from_millis(200),
+            permissive_hold: false,
+            hold_on_other_key_press: false,
+        };
+    }
+
+    /// If the key is held longer than this, it's a hold even if no other keys are pressed.
+    /// The default is 200ms.
+    pub fn set_tapping_term(&mut self, value: Duration) -> &mut TapHoldKey {
+        self.tapping_term = value;
+        self
+    }
+
+    /// If another key is pressed *and released* while this key is held, it's a hold, even within
+    /// the tapping term.
+    pub fn set_permissive_hold(&mut self, value: bool) -> &mut TapHoldKey {
+        self.permissive_hold = value;
+        self
+    }
+
+    /// If another key is pressed while this key is held, it's a hold, even within the tapping term.
+    pub fn set_hold_on_other_key_press(&mut self, value: bool) -> &mut TapHoldKey {
+        self.hold_on_other_key_press = value;
+        self
+    }
+}
+
+/// Output of `TapHoldState`. `usize`s are indexes to the `TapHoldKey`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TapHoldOutput<P> {
+    /// Pass the event to the next stage.
+    Event(InputEvent, P),
+    Tap(usize),
+    HoldStart(usize),
+    HoldEnd(usize),
+}
+
+#[derive(Debug)]
+struct Pending {
+    key: usize,
+    pressed_at: Instant,
+}
+
+/// State machine of the tap-hold keys. It's generic over `P`, which is passed through along with
+/// each event, so the caller can remember where the event came from.
+///
+/// While a key is undecided, all the other events are buffered, and they're replayed once it's
+/// decided, so the next stage sees them after the tap or the start of the hold.
+#[derive(Debug)]
+pub(crate) struct TapHoldState<P> {
+    keys: Vec<TapHoldKey>,
+    pending: Option<Pending>,
+    buffer: Vec<(InputEvent, P, Instant)>,
+    holding: HashSet<usize>,
+}
+
+impl<P> TapHoldState<P> {
+    pub(crate) fn new(keys: Vec<TapHoldKey>) -> TapHoldState<P> {
+        return TapHoldState {
+            keys,
+            pending: None,
+            buffer: vec![],
+            holding: HashSet::new(),
+        };
+    }
+
+    pub(crate) fn key(&self, index: usize) -> &TapHoldKey {
+        return &self.keys[index];
+    }
+
+    pub(crate) fn find_key(&self, code: i32) -> Option<usize> {
+        return self.keys.iter().position(|k| k.code == code);
+    }
+
+    /// Return true if `code` is a tap-hold key.
+    pub(crate) fn is_tap_hold_key(&self, code: i32) -> bool {
+        return self.find_key(code).is_some();
+    }
+
+    /// Return true if a tap-hold key with `HoldAction::Modifier(modifier)` is being held.
+    pub(crate) fn is_modifier_held(&self, modifier: char) -> bool {
+        return self.holding.iter().any(|i| self.keys[*i].hold == HoldAction::Modifier(modifier));
+    }
+
+    /// Deadline of the undecided key, if any.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        return self.pending.as_ref().map(|p| p.pressed_at + self.keys[p.key].tapping_term);
+    }
+
+    pub(crate) fn on_event(&mut self, ev: &InputEvent, payload: P, now: Instant) -> Vec<TapHoldOutput<P>> {
+        let mut out = vec![];
+        self.check_timeout(now, &mut out);
+        self.process(ev, payload, now, &mut out);
+        self.check_timeout(now, &mut out);
+        return out;
+    }
+
+    /// Decide the undecided key as a hold if the tapping term has passed.
+    pub(crate) fn on_timeout(&mut self, now: Instant) -> Vec<TapHoldOutput<P>> {
+        let mut out = vec![];
+        self.check_timeout(now, &mut out);
+        return out;
+    }
+
+    /// Forget all the state, e.g. when the devices are lost, and return the `HoldEnd`s for the
+    /// keys being held. The buffered events are dropped.
+    pub(crate) fn reset(&mut self) -> Vec<TapHoldOutput<P>> {
+        self.pending = None;
+        self.buffer.clear();
+        let mut holding: Vec<usize> = self.holding.drain().collect();
+        holding.sort();
+        return holding.into_iter().map(TapHoldOutput::HoldEnd).collect();
+    }
+
+    fn check_timeout(&mut self, now: Instant, out: &mut Vec<TapHoldOutput<P>>) {
+        while let Some(deadline) = self.next_deadline() {
+            if now < deadline {
+                return;
+            }
+            self.decide_hold(out);
+        }
+    }
+
+    fn decide_hold(&mut self, out: &mut Vec<TapHoldOutput<P>>) {
+        let pending = self.pending.take().unwrap();
+        self.holding.insert(pending.key);
+        out.push(TapHoldOutput::HoldStart(pending.key));
+        self.flush(out);
+    }
+
+    /// Replay the buffered events.
+    fn flush(&mut self, out: &mut Vec<TapHoldOutput<P>>) {
+        let buffer: Vec<(InputEvent, P, Instant)> = self.buffer.drain(..).collect();
+        for (ev, payload, time) in buffer {
+            self.process(&ev, payload, time, out);
+        }
+    }
+
+    fn process(&mut self, ev: &InputEvent, payload: P, now: Instant, out: &mut Vec<TapHoldOutput<P>>) {
+        if let Some(pending) = &self.pending {
+            let key = &self.keys[pending.key];
+            if ev.is_key_event() && ev.code == key.code {
+                if ev.value == 0 {
+                    if now >= pending.pressed_at + key.tapping_term {
+                        // The timer hasn't fired yet, but it's already a hold.
+                        self.decide_hold(out);
+                        self.process(ev, payload, now, out);
+                    } else {
+                        let index = pending.key;
+                        self.pending = None;
+                        out.push(TapHoldOutput::Tap(index));
+                        self.flush(out);
+                    }
+                }
+                return; // Ignore auto-repeat.
+            }
+
+            let hold = if ev.is_key_down_event() {
+                key.hold_on_other_key_press
+            } else if ev.is_key_up_event() {
+                // Only if the key was pressed after the tap-hold key.
+                key.permissive_hold && self.buffer.iter().any(|(b, ..)| b.is_key_down_event() && b.code == ev.code)
+            } else {
+                false
+            };
+            self.buffer.push((*ev, payload, now));
+            if hold {
+                self.decide_hold(out);
+            }
+            return;
+        }
+
+        if ev.is_key_event() {
+            if let Some(index) = self.find_key(ev.code) {
+                match ev.value {
+                    1 if !self.holding.contains(&index) => {
+                        self.pending = Some(Pending { key: index, pressed_at: now });
+                    }
+                    0 if self.holding.remove(&index) => {
+                        out.push(TapHoldOutput::HoldEnd(index));
+                    }
+                    _ => {} // Auto-repeat, or a stray event.
+                }
+                return;
+            }
+        }
+        out.push(TapHoldOutput::Event(*ev, payload));
+    }
+}
+
+#[cfg(test)]
+impl crate::testing::TimedKeyState for TapHoldState<()> {
+    type Output = TapHoldOutput<()>;
+
+    fn on_event(&mut self, ev: &InputEvent, now: Instant) -> Vec<TapHoldOutput<()>> {
+        return TapHoldState::on_event(self, ev, (), now);
+    }
+
+    fn on_timeout(&mut self, now: Instant) -> Vec<TapHoldOutput<()>> {
+        return TapHoldState::on_timeout(self, now);
+    }
+}
+
+#[test]
+fn test_tap_hold_tap() {
+    use crate::evdev::ec;
+    use crate::testing::{run_timed_key_state, TestStep::*};
+    use TapHoldOutput::*;
+
+    let start = Instant::now();
+    let mut state = TapHoldState::new(vec![TapHoldKey::new(ec::KEY_ESC, ec::KEY_ESC, HoldAction::Modifier('e')).clone()]);
+
+    // Quick tap.
+    assert_eq!(
+        vec![Tap(0)],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_ESC, 1), Key(100, ec::KEY_ESC, 0)])
+    );
+
+    // Another key pressed and released within the tapping term -> still a tap, followed by the key.
+    assert_eq!(
+        vec![
+            Tap(0),
+            Event(InputEvent::new_key_event(ec::KEY_A, 1), ()),
+            Event(InputEvent::new_key_event(ec::KEY_A, 0), ()),
+        ],
+        run_timed_key_state(
+            &mut state,
+            start,
+            &[Key(0, ec::KEY_ESC, 1), Key(10, ec::KEY_A, 1), Key(20, ec::KEY_A, 0), Key(30, ec::KEY_ESC, 0)]
+        )
+    );
+    assert!(!state.is_modifier_held('e'));
+}
+
+#[test]
+fn test_tap_hold_hold() {
+    use crate::evdev::ec;
+    use crate::testing::{run_timed_key_state, TestStep::*};
+    use TapHoldOutput::*;
+
+    let start = Instant::now();
+    let mut state = TapHoldState::new(vec![TapHoldKey::new(ec::KEY_ESC, ec::KEY_ESC, HoldAction::Modifier('e')).clone()]);
+
+    // Slow tap -> hold, without a tap.
+    assert_eq!(
+        vec![HoldStart(0), HoldEnd(0)],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_ESC, 1), Key(300, ec::KEY_ESC, 0)])
+    );
+
+    // The timer fires, and the buffered key is replayed after the hold starts.
+    assert_eq!(
+        vec![HoldStart(0), Event(InputEvent::new_key_event(ec::KEY_A, 1), ())],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_ESC, 1), Key(10, ec::KEY_A, 1), Timeout(250)])
+    );
+    assert!(state.is_modifier_held('e'));
+    assert_eq!(
+        vec![Event(InputEvent::new_key_event(ec::KEY_A, 0), ()), HoldEnd(0)],
+        run_timed_key_state(&mut state, start, &[Key(300, ec::KEY_A, 0), Key(310, ec::KEY_ESC, 0)])
+    );
+    assert!(!state.is_modifier_held('e'));
+}
+
+#[test]
+fn test_tap_hold_policies() {
+    use crate::evdev::ec;
+    use crate::testing::{run_timed_key_state, TestStep::*};
+    use TapHoldOutput::*;
+
+    let start = Instant::now();
+
+    let mut state = TapHoldState::new(vec![TapHoldKey::new(ec::KEY_CAPSLOCK, ec::KEY_ESC, HoldAction::Key(ec::KEY_LEFTCTRL))
+        .set_permissive_hold(true)
+        .clone()]);
+    assert_eq!(
+        vec![
+            HoldStart(0),
+            Event(InputEvent::new_key_event(ec::KEY_A, 1), ()),
+            Event(InputEvent::new_key_event(ec::KEY_A, 0), ()),
+            HoldEnd(0),
+        ],
+        run_timed_key_state(
+            &mut state,
+            start,
+            &[
+                Key(0, ec::KEY_CAPSLOCK, 1),
+                Key(10, ec::KEY_A, 1),
+                Key(20, ec::KEY_A, 0),
+                Key(30, ec::KEY_CAPSLOCK, 0)
+            ]
+        )
+    );
+
+    let mut state = TapHoldState::new(vec![TapHoldKey::new(ec::KEY_CAPSLOCK, ec::KEY_ESC, HoldAction::Key(ec::KEY_LEFTCTRL))
+        .set_hold_on_other_key_press(true)
+        .clone()]);
+    assert_eq!(
+        vec![HoldStart(0), Event(InputEvent::new_key_event(ec::KEY_A, 1), ())],
+        run_timed_key_state(&mut state, start, &[Key(0, ec::KEY_CAPSLOCK, 1), Key(10, ec::KEY_A, 1)])
+    );
+    assert_eq!(vec![HoldEnd(0)], state.reset());
+}
@@ -0,0 +1,557 @@
+//! Hardware-free harness to test remappers. `TestRemapper` runs a `KeyRemapperConfiguration` without
+//! the input devices, uinput, the tray icon and the notifications: the events are fed to the same
+//! pipeline as `main_loop()` (the tracker, the tap-hold keys, the layers, `on_event`, etc), the
+//! output goes to `MemorySink`s, and the timers run on a `VirtualClock`.
+//!
+//! `DeviceTestRunner` runs a remapper against a virtual keyboard created with uinput instead, so it
+//! covers the device detection, the grab, uinput and udev too.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use parking_lot::RwLock;
+
+use crate::{
+    core::{main_loop, InputSource, KeyRemapperUi, Result, UinputFactory},
+    evdev::{
+        ec::{self, EventType},
+        list_devices_from_path_with_filter,
+        sink::MemorySink,
+        uinput::Uinput,
+        DeviceIdentity, EvdevDevice, EventsDescriptor, InputEvent,
+    },
+    native, select,
+    timer::{Clock, VirtualClock},
+    KeyRemapper, KeyRemapperConfiguration,
+};
+
+/// `InputSource` that returns the devices added to it. Clones share the same devices.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryInputSource {
+    devices: Arc<Mutex<Vec<EvdevDevice>>>,
+}
+
+impl MemoryInputSource {
+    pub fn new() -> MemoryInputSource {
+        return MemoryInputSource::default();
+    }
+
+    pub fn add_device(&self, device: &EvdevDevice) {
+        self.devices.lock().unwrap().push(device.clone());
+    }
+
+    pub fn remove_device(&self, device: &EvdevDevice) {
+        self.devices.lock().unwrap().retain(|d| d.id() != device.id());
+    }
+}
+
+impl InputSource for MemoryInputSource {
+    fn find_devices(&mut self) -> Result<Vec<EvdevDevice>> {
+        return Ok(self.devices.lock().unwrap().clone());
+    }
+}
+
+/// Runs a `KeyRemapperConfiguration` in memory. See the module doc.
+pub struct TestRemapper {
+    key_remapper: KeyRemapper,
+    source: MemoryInputSource,
+    clock: VirtualClock,
+
+    /// Output sinks by the uinput device name.
+    sinks: Arc<Mutex<HashMap<String, MemorySink>>>,
+
+    /// Events the uinput devices support, by the name.
+    supported_events: Arc<Mutex<HashMap<String, EventsDescriptor>>>,
+}
+
+impl TestRemapper {
+    pub fn new(config: &KeyRemapperConfiguration) -> TestRemapper {
+        let mut config = config.clone();
+        config.set_defaults();
+        config.update_regexes();
+
+        let source = MemoryInputSource::new();
+        let clock = VirtualClock::new();
+        let sinks: Arc<Mutex<HashMap<String, MemorySink>>> = Arc::new(Mutex::new(HashMap::new()));
+        let supported_events: Arc<Mutex<HashMap<String, EventsDescriptor>>> = Arc::new(Mutex::new(HashMap::new()));
+
+        let factory_sinks = sinks.clone();
+        let factory_events = supported_events.clone();
+        let factory: UinputFactory = Arc::new(move |name: &str, events: &EventsDescriptor, _: Option<&DeviceIdentity>| {
+            let sink = MemorySink::new();
+            factory_sinks.lock().unwrap().insert(name.to_string(), sink.clone());
+            factory_events.lock().unwrap().insert(name.to_string(), events.clone());
+            return Ok(Uinput::with_sink(Box::new(sink)));
+        });
+
+        let key_remapper = KeyRemapper::with_parts(config, KeyRemapperUi::headless(), Box::new(source.clone()), factory, Arc::new(clock.clone()));
+        return TestRemapper {
+            key_remapper,
+            source,
+            clock,
+            sinks,
+            supported_events,
+        };
+    }
+
+    pub fn remapper(&self) -> &KeyRemapper {
+        return &self.key_remapper;
+    }
+
+    pub fn clock(&self) -> &VirtualClock {
+        return &self.clock;
+    }
+
+    /// Add a virtual input device. It's picked up by the next `start()` or `devices_lost()`.
+    pub fn add_device(&self, name: &str, events: &EventsDescriptor) -> EvdevDevice {
+        let device = EvdevDevice::new_virtual(name, &DeviceIdentity::default(), events);
+        self.source.add_device(&device);
+        return device;
+    }
+
+    /// Add a virtual keyboard that has all the keys.
+    pub fn add_keyboard(&self, name: &str) -> EvdevDevice {
+        return self.add_device(name, &EventsDescriptor::with_all_key_events());
+    }
+
+    pub fn remove_device(&self, device: &EvdevDevice) {
+        self.source.remove_device(device);
+    }
+
+    /// Call `on_start`, and detect the devices, like the I/O thread does when it starts.
+    pub fn start(&self) {
+        let callbacks = self.key_remapper.config().callbacks_cloned();
+        (*callbacks.on_start)(&self.key_remapper);
+        self.key_remapper.detect_devices();
+    }
+
+    /// Simulate a device connection or disconnection: reset the states, release the keys, and detect
+    /// the devices again.
+    pub fn devices_lost(&self) {
+        self.key_remapper.handle_devices_lost();
+        self.key_remapper.detect_devices();
+    }
+
+    /// Feed a batch of events read from `device`, and then run the timers that are due.
+    pub fn send(&self, device: &EvdevDevice, events: &[InputEvent]) {
+        self.key_remapper.process_input_events(device, events);
+        self.key_remapper.run_due_timers();
+    }
+
+    /// Feed a key event followed by a SYN_REPORT.
+    pub fn send_key(&self, device: &EvdevDevice, code: i32, value: i32) {
+        self.send(device, &[InputEvent::new_key_event(code, value), InputEvent::new_syn_report()]);
+    }
+
+    pub fn press(&self, device: &EvdevDevice, code: i32) {
+        self.send_key(device, code, 1);
+    }
+
+    pub fn release(&self, device: &EvdevDevice, code: i32) {
+        self.send_key(device, code, 0);
+    }
+
+    pub fn tap(&self, device: &EvdevDevice, code: i32) {
+        self.press(device, code);
+        self.release(device, code);
+    }
+
+    /// Move the clock forward by `duration`, running the timers in the deadline order. The clock is
+    /// set to each timer's deadline when it runs.
+    pub fn advance(&self, duration: Duration) {
+        let end = self.clock.now() + duration;
+        loop {
+            match self.key_remapper.next_timer_deadline() {
+                Some(deadline) if deadline <= end => {
+                    if deadline > self.clock.now() {
+                        self.clock.set(deadline);
+                    }
+                    self.key_remapper.run_due_timers();
+                }
+                _ => break,
+            }
+        }
+        self.clock.set(end);
+    }
+
+    fn sink(&self, name_suffix: &str) -> MemorySink {
+        let name = format!("{}{}", self.key_remapper.config().uinput_devices_prefix, name_suffix);
+        match self.sinks.lock().unwrap().get(&name) {
+            Some(sink) => return sink.clone(),
+            None => panic!("uinput device {} not found", name),
+        }
+    }
+
+    /// Events the uinput device created with `name_suffix` supports. `""` is the main one.
+    pub fn supported_events_of(&self, name_suffix: &str) -> EventsDescriptor {
+        let name = format!("{}{}", self.key_remapper.config().uinput_devices_prefix, name_suffix);
+        match self.supported_events.lock().unwrap().get(&name) {
+            Some(events) => return events.clone(),
+            None => panic!("uinput device {} not found", name),
+        }
+    }
+
+    /// Upload a rumble effect to the main uinput device like a program (e.g. a game) does, and return
+    /// its ID, or the error the remapper returned as a negative errno.
+    pub fn upload_ff_rumble(&self, strong_magnitude: u16, weak_magnitude: u16, length: Duration) -> std::result::Result<i16, i32> {
+        let mut effect: native::ff_effect = unsafe { std::mem::zeroed() };
+        effect.type_ = ec::FF_RUMBLE as u16;
+        effect.id = -1;
+        effect.replay.length = length.as_millis().min(u16::MAX as u128) as u16;
+        effect.u.rumble.strong_magnitude = strong_magnitude;
+        effect.u.rumble.weak_magnitude = weak_magnitude;
+
+        let sink = self.sink("");
+        let (request, id) = sink.request_ff_upload(&effect);
+        self.key_remapper.handle_uinput_events(&[request]);
+        return match sink.ff_result(&request) {
+            Some(0) => Ok(id),
+            Some(retval) => Err(retval),
+            None => panic!("FF upload request not handled"),
+        };
+    }
+
+    /// Erase a force-feedback effect on the main uinput device, and return the result (0 or a negative errno).
+    pub fn erase_ff_effect(&self, id: i16) -> i32 {
+        let sink = self.sink("");
+        let request = sink.request_ff_erase(id);
+        self.key_remapper.handle_uinput_events(&[request]);
+        return sink.ff_result(&request).expect("FF erase request not handled");
+    }
+
+    /// Start (`value` is the repeat count) or stop (`value` is 0) playing an effect on the main uinput device.
+    pub fn play_ff_effect(&self, id: i16, value: i32) {
+        self.key_remapper.handle_uinput_events(&[InputEvent::new(EventType::EV_FF, id as i32, value)]);
+    }
+
+    /// Events written to the main uinput device so far.
+    pub fn output(&self) -> Vec<InputEvent> {
+        return self.sink("").events();
+    }
+
+    /// Same as `output()`, but also clear them.
+    pub fn take_output(&self) -> Vec<InputEvent> {
+        return self.sink("").take_events();
+    }
+
+    /// Same as `take_output()`, but only return the key events as `(code, value)`.
+    pub fn take_output_keys(&self) -> Vec<(i32, i32)> {
+        return self
+            .take_output()
+            .iter()
+            .filter(|ev| ev.event_type == EventType::EV_KEY)
+            .map(|ev| (ev.code, ev.value))
+            .collect();
+    }
+
+    /// Events written to the uinput device created with `name_suffix`, e.g. with `KeyRemapper::create_mouse_uinput()`.
+    pub fn output_of(&self, name_suffix: &str) -> Vec<InputEvent> {
+        return self.sink(name_suffix).events();
+    }
+}
+
+/// How long `DeviceTestRunner` waits for the devices to show up and for the output events.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `main_loop()` ignores the virtual devices' udev events for this long after it starts.
+const UDEV_GRACE_PERIOD: Duration = Duration::from_millis(2500);
+
+static NEXT_RUNNER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Wait for the evdev device named `name` to show up, and open it without grabbing it.
+fn open_device_by_name(name: &str) -> Result<EvdevDevice> {
+    let deadline = Instant::now() + DEVICE_TIMEOUT;
+    loop {
+        let mut devices = list_devices_from_path_with_filter(false, "/dev/input/event*", |d| d.name() == name)?;
+        if devices.len() > 0 {
+            return Ok(devices.remove(0));
+        }
+        if Instant::now() > deadline {
+            return Err(format!("Device {} not found", name).into());
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Runs a remapper on its I/O thread against a virtual keyboard created with `Uinput`, and reads the
+/// remapper's output back from its uinput device. The remapper only uses the virtual keyboard, and
+/// it runs without the tray icon and the notifications.
+///
+/// It needs write access to /dev/uinput and /dev/input/event*, so the tests using it should be
+/// `#[ignore]`d and run with `--ignored`.
+pub struct DeviceTestRunner {
+    key_remapper: KeyRemapper,
+    keyboard_name: String,
+    keyboard: Option<Uinput>,
+    output: EvdevDevice,
+    started: Instant,
+
+    /// Number of times the remapper has detected the keyboard.
+    detections: Arc<AtomicUsize>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DeviceTestRunner {
+    pub fn start(config: &KeyRemapperConfiguration) -> Result<DeviceTestRunner> {
+        let id = format!("{}-{}", std::process::id(), NEXT_RUNNER_ID.fetch_add(1, Ordering::SeqCst));
+        let keyboard_name = format!("keyremapper-test-keyboard-{}", id);
+
+        let mut config = config.clone();
+        config.set_device_name_regex(&format!("^{}$", regex::escape(&keyboard_name)));
+        config.set_id_regex("");
+        config.uinput_device_name_suffix = format!("-test-{}", id);
+        config.set_defaults();
+        config.update_regexes();
+
+        // The clones share the callbacks, so copy them before wrapping one, not to change `config`'s.
+        config.callbacks = Arc::new(RwLock::new(config.callbacks_cloned()));
+        let detections = Arc::new(AtomicUsize::new(0));
+        {
+            let on_devices_detected = config.callbacks_cloned().on_devices_detected;
+            let detections = detections.clone();
+            config.on_devices_detected(move |km, devices| {
+                (*on_devices_detected)(km, devices);
+                detections.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        let keyboard = Uinput::new(&keyboard_name, &EventsDescriptor::with_all_key_events())?;
+        open_device_by_name(&keyboard_name)?; // Make sure the remapper can find it.
+
+        let key_remapper = KeyRemapper::with_ui(config.clone(), KeyRemapperUi::headless());
+        let output = open_device_by_name(&config.uinput_devices_prefix)?;
+
+        let thread = {
+            let key_remapper = key_remapper.clone();
+            thread::Builder::new()
+                .name(format!("keyremapper-test-{}-io", id))
+                .spawn(move || main_loop(&key_remapper))?
+        };
+
+        let ret = DeviceTestRunner {
+            key_remapper,
+            keyboard_name,
+            keyboard: Some(keyboard),
+            output,
+            started: Instant::now(),
+            detections,
+            thread: Some(thread),
+        };
+        ret.wait_for_detections(1)?;
+        return Ok(ret);
+    }
+
+    pub fn remapper(&self) -> &KeyRemapper {
+        return &self.key_remapper;
+    }
+
+    /// The virtual keyboard to send the input events with.
+    pub fn keyboard(&self) -> &Uinput {
+        return self.keyboard.as_ref().unwrap();
+    }
+
+    fn wait_for_detections(&self, count: usize) -> Result<()> {
+        let deadline = Instant::now() + DEVICE_TIMEOUT;
+        while self.detections.load(Ordering::SeqCst) < count || self.key_remapper.devices().is_empty() {
+            if Instant::now() > deadline {
+                return Err(format!("{} wasn't detected", self.keyboard_name).into());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        return Ok(());
+    }
+
+    /// Unplug the virtual keyboard and plug it again, and wait for the remapper to detect it.
+    pub fn reconnect_keyboard(&mut self) -> Result<()> {
+        let elapsed = self.started.elapsed();
+        if elapsed < UDEV_GRACE_PERIOD {
+            thread::sleep(UDEV_GRACE_PERIOD - elapsed);
+        }
+        let count = self.detections.load(Ordering::SeqCst);
+
+        self.keyboard = None;
+        thread::sleep(Duration::from_millis(100));
+        self.keyboard = Some(Uinput::new(&self.keyboard_name, &EventsDescriptor::with_all_key_events())?);
+
+        return self.wait_for_detections(count + 1);
+    }
+
+    pub fn press(&self, code: i32) {
+        self.keyboard().send_event(&InputEvent::new_key_event(code, 1)).unwrap();
+    }
+
+    pub fn release(&self, code: i32) {
+        self.keyboard().send_event(&InputEvent::new_key_event(code, 0)).unwrap();
+    }
+
+    pub fn tap(&self, code: i32) {
+        self.press(code);
+        self.release(code);
+    }
+
+    /// Read the events written by the remapper until none arrive for `idle`. Like `MemorySink`,
+    /// the timestamps are dropped.
+    pub fn read_output(&self, idle: Duration) -> Vec<InputEvent> {
+        let mut ret = vec![];
+        let fds = vec![self.output.device_fd()];
+        while select::select(&fds, Some(idle)).expect("pselect() failed").is_some() {
+            let events = self.output.next_events().expect("Unable to read the output events");
+            ret.extend(events.iter().map(|ev| InputEvent::new(ev.event_type, ev.code, ev.value)));
+        }
+        return ret;
+    }
+
+    /// Wait for the remapper to write `expected.len()` key events, and check them as `(code, value)`.
+    pub fn assert_output_keys(&self, expected: &[(i32, i32)]) {
+        let deadline = Instant::now() + DEVICE_TIMEOUT;
+        let mut keys = vec![];
+        while keys.len() < expected.len() && Instant::now() < deadline {
+            for ev in self.read_output(Duration::from_millis(100)) {
+                if ev.event_type == EventType::EV_KEY {
+                    keys.push((ev.code, ev.value));
+                }
+            }
+        }
+        assert_eq!(expected, &keys[..]);
+    }
+
+    /// Stop the I/O thread, which releases the keyboard and the pressed keys. The output can still be read.
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.key_remapper.stop_main_loop();
+            if thread.join().is_err() {
+                eprintln!("The I/O thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for DeviceTestRunner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// State machine that buffers key events until a later key event or a timeout, e.g. `TapHoldState`.
+#[cfg(test)]
+pub(crate) trait TimedKeyState {
+    type Output;
+
+    fn on_event(&mut self, ev: &InputEvent, now: Instant) -> Vec<Self::Output>;
+
+    fn on_timeout(&mut self, now: Instant) -> Vec<Self::Output>;
+}
+
+/// Input to `run_timed_key_state()`, at milliseconds since the start.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum TestStep {
+    /// Key event with the code and the value.
+    Key(u64, i32, i32),
+    /// The timer fires.
+    Timeout(u64),
+}
+
+/// Feed `steps` to `state` and return all the outputs.
+#[cfg(test)]
+pub(crate) fn run_timed_key_state<S: TimedKeyState>(state: &mut S, start: Instant, steps: &[TestStep]) -> Vec<S::Output> {
+    let mut ret = vec![];
+    for step in steps {
+        match *step {
+            TestStep::Key(millis, code, value) => ret.extend(state.on_event(&InputEvent::new_key_event(code, value), start + Duration::from_millis(millis))),
+            TestStep::Timeout(millis) => ret.extend(state.on_timeout(start + Duration::from_millis(millis))),
+        }
+    }
+    return ret;
+}
+
+#[test]
+fn test_test_remapper() {
+    use crate::evdev::ec;
+    use crate::taphold::{HoldAction, TapHoldKey};
+
+    let mut config = KeyRemapperConfiguration::new("Test", "");
+    config
+        .add_tap_hold_key(&TapHoldKey::new(ec::KEY_CAPSLOCK, ec::KEY_ESC, HoldAction::Key(ec::KEY_LEFTCTRL)))
+        .on_event(|km, _device, ev| {
+            if ev.is_key_event() && ev.code == ec::KEY_A {
+                km.send_key_event(ec::KEY_B, ev.value);
+                return;
+            }
+            km.send_event(ev);
+        });
+
+    let t = TestRemapper::new(&config);
+    let kbd = t.add_keyboard("keyboard");
+    t.start();
+
+    // Remapped by on_event.
+    t.tap(&kbd, ec::KEY_A);
+    assert_eq!(
+        vec![
+            InputEvent::new_key_event(ec::KEY_B, 1),
+            InputEvent::new_syn_report(),
+            InputEvent::new_key_event(ec::KEY_B, 0),
+            InputEvent::new_syn_report(),
+        ],
+        t.take_output()
+    );
+
+    // Tapped within the tapping term -> ESC.
+    t.press(&kbd, ec::KEY_CAPSLOCK);
+    t.advance(Duration::from_millis(100));
+    assert_eq!(Vec::<(i32, i32)>::new(), t.take_output_keys());
+    t.release(&kbd, ec::KEY_CAPSLOCK);
+    assert_eq!(vec![(ec::KEY_ESC, 1), (ec::KEY_ESC, 0)], t.take_output_keys());
+
+    // Held longer than the tapping term -> CTRL, even without other keys.
+    t.press(&kbd, ec::KEY_CAPSLOCK);
+    t.advance(Duration::from_millis(199));
+    assert_eq!(Vec::<(i32, i32)>::new(), t.take_output_keys());
+    t.advance(Duration::from_millis(1));
+    assert_eq!(vec![(ec::KEY_LEFTCTRL, 1)], t.take_output_keys());
+    t.tap(&kbd, ec::KEY_A);
+    assert_eq!(vec![(ec::KEY_B, 1), (ec::KEY_B, 0)], t.take_output_keys());
+    assert!(t.remapper().is_out_key_on(ec::KEY_LEFTCTRL));
+
+    // Devices lost -> the pressed keys are released.
+    t.devices_lost();
+    assert_eq!(vec![(ec::KEY_LEFTCTRL, 0)], t.take_output_keys());
+    assert!(!t.remapper().is_key_on(ec::KEY_CAPSLOCK));
+}
+
+// Needs write access to /dev/uinput and /dev/input/event*, so run it with `--ignored` as root.
+#[test]
+#[ignore]
+fn test_device_test_runner() {
+    use crate::evdev::ec;
+
+    let mut config = KeyRemapperConfiguration::new("Test", "");
+    config.on_event(|km, _device, ev| {
+        if ev.is_key_event() && ev.code == ec::KEY_A {
+            km.send_key_event(ec::KEY_B, ev.value);
+            return;
+        }
+        km.send_event(ev);
+    });
+
+    let mut runner = DeviceTestRunner::start(&config).unwrap();
+    runner.tap(ec::KEY_A);
+    runner.tap(ec::KEY_C);
+    runner.assert_output_keys(&[(ec::KEY_B, 1), (ec::KEY_B, 0), (ec::KEY_C, 1), (ec::KEY_C, 0)]);
+
+    // The remapper picks up the keyboard again after it's reconnected.
+    runner.reconnect_keyboard().unwrap();
+    runner.press(ec::KEY_A);
+    runner.assert_output_keys(&[(ec::KEY_B, 1)]);
+
+    // Stopping releases the pressed keys.
+    runner.stop();
+    runner.assert_output_keys(&[(ec::KEY_B, 0)]);
+}
@@ -0,0 +1,170 @@
+//! Typing text with key presses. See `KeyRemapper::type_text()`.
+use std::collections::HashMap;
+
+use crate::evdev::ec;
+
+/// Maps characters to keys, i.e. which key to press, with or without shift, to type a character.
+/// Use `KeyboardLayout::us()` for the US layout, or build one for other layouts with `set_key()`.
+#[derive(Debug, Clone)]
+pub struct KeyboardLayout {
+    pub(crate) name: String,
+    keys: HashMap<char, (i32, bool)>,
+}
+
+impl KeyboardLayout {
+    pub fn new(name: &str) -> KeyboardLayout {
+        return KeyboardLayout {
+            name: name.to_string(),
+            keys: HashMap::new(),
+        };
+    }
+
+    /// Type `c` by pressing `code`, with shift if `shift` is true.
+    pub fn set_key(&mut self, c: char, code: i32, shift: bool) -> &mut KeyboardLayout {
+        self.keys.insert(c, (code, shift));
+        self
+    }
+
+    /// Return the key and whether shift is needed to type `c`.
+    pub fn key_for(&self, c: char) -> Option<(i32, bool)> {
+        return self.keys.get(&c).copied();
+    }
+
+    /// The US layout.
+    pub fn us() -> KeyboardLayout {
+        let mut ret = KeyboardLayout::new("us");
+        let letters = [
+            ec::KEY_A,
+            ec::KEY_B,
+            ec::KEY_C,
+            ec::KEY_D,
+            ec::KEY_E,
+            ec::KEY_F,
+            ec::KEY_G,
+            ec::KEY_H,
+            ec::KEY_I,
+            ec::KEY_J,
+            ec::KEY_K,
+            ec::KEY_L,
+            ec::KEY_M,
+            ec::KEY_N,
+            ec::KEY_O,
+            ec::KEY_P,
+            ec::KEY_Q,
+            ec::KEY_R,
+            ec::KEY_S,
+            ec::KEY_T,
+            ec::KEY_U,
+            ec::KEY_V,
+            ec::KEY_W,
+            ec::KEY_X,
+            ec::KEY_Y,
+            ec::KEY_Z,
+        ];
+        for (c, code) in ('a'..='z').zip(letters.iter()) {
+            ret.set_key(c, *code, false);
+            ret.set_key(c.to_ascii_uppercase(), *code, true);
+        }
+
+        // (key, without shift, with shift)
+        let others = [
+            (ec::KEY_1, '1', '!'),
+            (ec::KEY_2, '2', '@'),
+            (ec::KEY_3, '3', '#'),
+            (ec::KEY_4, '4', '$'),
+            (ec::KEY_5, '5', '%'),
+            (ec::KEY_6, '6', '^'),
+            (ec::KEY_7, '7', '&'),
+            (ec::KEY_8, '8', '*'),
+            (ec::KEY_9, '9', '('),
+            (ec::KEY_0, '0', ')'),
+            (ec::KEY_MINUS, '-', '_'),
+            (ec::KEY_EQUAL, '=', '+'),
+            (ec::KEY_LEFTBRACE, '[', '{'),
+            (ec::KEY_RIGHTBRACE, ']', '}'),
+            (ec::KEY_BACKSLASH, '\\', '|'),
+            (ec::KEY_SEMICOLON, ';', ':'),
+            (ec::KEY_APOSTROPHE, '\'', '"'),
+            (ec::KEY_GRAVE, '`', '~'),
+            (ec::KEY_COMMA, ',', '<'),
+            (ec::KEY_DOT, '.', '>'),
+            (ec::KEY_SLASH, '/', '?'),
+        ];
+        for (code, normal, shifted) in others.iter() {
+            ret.set_key(*normal, *code, false);
+            ret.set_key(*shifted, *code, true);
+        }
+
+        ret.set_key(' ', ec::KEY_SPACE, false);
+        ret.set_key('\t', ec::KEY_TAB, false);
+        ret.set_key('\n', ec::KEY_ENTER, false);
+        return ret;
+    }
+}
+
+impl Default for KeyboardLayout {
+    fn default() -> Self {
+        return KeyboardLayout::us();
+    }
+}
+
+/// How to type characters that the keyboard layout can't produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeInput {
+    /// Don't type them.
+    Disabled,
+
+    /// Ctrl+Shift+U, the code point in hex, and then space, which GTK and IBus support.
+    CtrlShiftU,
+}
+
+/// Return the key presses, as pairs of a key and modifiers for `KeyRemapper::press_key()`, to type `c`,
+/// or `None` if it can't be typed.
+pub(crate) fn key_presses_for_char(layout: &KeyboardLayout, unicode_input: UnicodeInput, c: char) -> Option<Vec<(i32, &'static str)>> {
+    if let Some((code, shift)) = layout.key_for(c) {
+        return Some(vec![(code, if shift { "s" } else { "" })]);
+    }
+    match unicode_input {
+        UnicodeInput::Disabled => return None,
+        UnicodeInput::CtrlShiftU => {
+            // The input methods read the characters the host's layout produces, so the hex digits are
+            // typed with the layout, e.g. with shift on AZERTY. The US keys are only used for the digits
+            // that the layout doesn't have.
+            let us = KeyboardLayout::us();
+            let mut ret = vec![(ec::KEY_U, "cs")];
+            for digit in format!("{:x}", c as u32).chars() {
+                let (code, shift) = layout.key_for(digit).or_else(|| us.key_for(digit))?;
+                ret.push((code, if shift { "s" } else { "" }));
+            }
+            ret.push((ec::KEY_SPACE, ""));
+            return Some(ret);
+        }
+    }
+}
+
+#[test]
+fn test_key_presses_for_char() {
+    let us = KeyboardLayout::us();
+    assert_eq!(Some(vec![(ec::KEY_A, "")]), key_presses_for_char(&us, UnicodeInput::Disabled, 'a'));
+    assert_eq!(Some(vec![(ec::KEY_A, "s")]), key_presses_for_char(&us, UnicodeInput::Disabled, 'A'));
+    assert_eq!(Some(vec![(ec::KEY_2, "s")]), key_presses_for_char(&us, UnicodeInput::Disabled, '@'));
+    assert_eq!(None, key_presses_for_char(&us, UnicodeInput::Disabled, 'é'));
+    assert_eq!(
+        Some(vec![(ec::KEY_U, "cs"), (ec::KEY_E, ""), (ec::KEY_9, ""), (ec::KEY_SPACE, "")]),
+        key_presses_for_char(&us, UnicodeInput::CtrlShiftU, 'é')
+    );
+
+    // Other layouts can be built on top of another.
+    let mut layout = KeyboardLayout::us();
+    layout.set_key('é', ec::KEY_SLASH, false);
+    assert_eq!(Some(vec![(ec::KEY_SLASH, "")]), key_presses_for_char(&layout, UnicodeInput::CtrlShiftU, 'é'));
+
+    // The hex digits are typed with the layout, e.g. with shift on AZERTY, or with the US keys if it doesn't have them.
+    let mut azerty = KeyboardLayout::new("fr");
+    azerty.set_key('&', ec::KEY_1, false).set_key('1', ec::KEY_1, true);
+    azerty.set_key('ç', ec::KEY_9, false).set_key('9', ec::KEY_9, true);
+    assert_eq!(
+        Some(vec![(ec::KEY_U, "cs"), (ec::KEY_E, ""), (ec::KEY_9, "s"), (ec::KEY_SPACE, "")]),
+        key_presses_for_char(&azerty, UnicodeInput::CtrlShiftU, 'é')
+    );
+}
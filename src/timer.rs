@@ -0,0 +1,282 @@
+//! Timers that fire on the I/O thread. See `KeyRemapper::schedule_after()`.
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::File,
+    io::{Read, Write},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Handle to a scheduled timer, which can be used to cancel it.
+#[derive(Debug, Clone)]
+pub struct TimerHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TimerHandle {
+    fn new() -> TimerHandle {
+        return TimerHandle {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+    }
+
+    /// Cancel the timer. The callback won't be called after this returns, unless it's already running.
+    /// It's safe to call it from any thread, and more than once.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(atomic::Ordering::SeqCst);
+    }
+}
+
+struct TimerEntry<T> {
+    deadline: Instant,
+    seq: u64,
+    period: Option<Duration>,
+    handle: TimerHandle,
+    callback: T,
+}
+
+// BinaryHeap is a max-heap, so reverse the order to get the earliest deadline first.
+// Timers with the same deadline fire in the order they were scheduled.
+impl<T> Ord for TimerEntry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other.deadline.cmp(&self.deadline).then_with(|| other.seq.cmp(&self.seq));
+    }
+}
+
+impl<T> PartialOrd for TimerEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl<T> PartialEq for TimerEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl<T> Eq for TimerEntry<T> {}
+
+/// Queue of one-shot and periodic timers. It doesn't call the callbacks itself; `pop_due()` returns
+/// the ones that are due, so the caller can run them without holding any locks.
+pub(crate) struct TimerQueue<T: Clone> {
+    heap: BinaryHeap<TimerEntry<T>>,
+    next_seq: u64,
+}
+
+impl<T: Clone> TimerQueue<T> {
+    pub(crate) fn new() -> TimerQueue<T> {
+        return TimerQueue {
+            heap: BinaryHeap::new(),
+            next_seq: 0,
+        };
+    }
+
+    /// Schedule `callback` at `now + delay`, and then every `period` if it's given.
+    pub(crate) fn schedule(&mut self, now: Instant, delay: Duration, period: Option<Duration>, callback: T) -> TimerHandle {
+        let handle = TimerHandle::new();
+        self.push(now + delay, period, handle.clone(), callback);
+        return handle;
+    }
+
+    fn push(&mut self, deadline: Instant, period: Option<Duration>, handle: TimerHandle, callback: T) {
+        self.heap.push(TimerEntry {
+            deadline,
+            seq: self.next_seq,
+            period,
+            handle,
+            callback,
+        });
+        self.next_seq += 1;
+    }
+
+    fn drop_cancelled(&mut self) {
+        while let Some(top) = self.heap.peek() {
+            if !top.handle.is_cancelled() {
+                return;
+            }
+            self.heap.pop();
+        }
+    }
+
+    /// Return the deadline of the earliest timer.
+    pub(crate) fn next_deadline(&mut self) -> Option<Instant> {
+        self.drop_cancelled();
+        return self.heap.peek().map(|top| top.deadline);
+    }
+
+    /// Remove and return the callbacks of all the timers that are due at `now`, along with their
+    /// handles. Periodic timers are rescheduled; if they're late, missed periods are skipped rather
+    /// than fired in a burst.
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Vec<(TimerHandle, T)> {
+        let mut ret = vec![];
+        let mut rescheduled = vec![];
+        loop {
+            self.drop_cancelled();
+            match self.heap.peek() {
+                Some(top) if top.deadline <= now => {}
+                _ => break,
+            }
+            let entry = self.heap.pop().unwrap();
+            ret.push((entry.handle.clone(), entry.callback.clone()));
+
+            if let Some(period) = entry.period {
+                let mut deadline = entry.deadline + period;
+                if deadline <= now {
+                    deadline = now + period;
+                }
+                rescheduled.push((deadline, entry));
+            }
+        }
+        for (deadline, entry) in rescheduled {
+            self.push(deadline, entry.period, entry.handle, entry.callback);
+        }
+        return ret;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        return self.heap.iter().filter(|e| !e.handle.is_cancelled()).count();
+    }
+}
+
+/// Source of the current time for the timers and the time-based features, e.g. tap-hold keys.
+/// `KeyRemapper` uses `SystemClock`; tests use `VirtualClock` to control the time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// `Clock` that returns `Instant::now()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        return Instant::now();
+    }
+}
+
+/// `Clock` that only moves when it's told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        return VirtualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        };
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    /// Set the time. It must not go backwards.
+    pub fn set(&self, now: Instant) {
+        let mut current = self.now.lock().unwrap();
+        if now < *current {
+            panic!("VirtualClock must not go backwards");
+        }
+        *current = now;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        return *self.now.lock().unwrap();
+    }
+}
+
+/// Wakes up the I/O thread's `select()`, e.g. when a timer is scheduled from another thread.
+#[derive(Debug, Clone)]
+pub(crate) struct Waker {
+    file: Arc<File>,
+}
+
+impl Waker {
+    pub(crate) fn new() -> std::io::Result<Waker> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        return Ok(Waker {
+            file: Arc::new(unsafe { File::from_raw_fd(fd) }),
+        });
+    }
+
+    pub(crate) fn fd(&self) -> RawFd {
+        return self.file.as_raw_fd();
+    }
+
+    pub(crate) fn wake(&self) {
+        // This only fails with EAGAIN when the counter is about to overflow, in which case
+        // the fd is readable anyway.
+        let _ = (&*self.file).write(&1_u64.to_ne_bytes());
+    }
+
+    /// Reset the fd so it's no longer readable.
+    pub(crate) fn drain(&self) {
+        let mut buf = [0_u8; 8];
+        let _ = (&*self.file).read(&mut buf);
+    }
+}
+
+#[test]
+fn test_timer_queue() {
+    let start = Instant::now();
+    let ms = |millis: u64| Duration::from_millis(millis);
+
+    let mut q: TimerQueue<&str> = TimerQueue::new();
+    assert_eq!(None, q.next_deadline());
+
+    q.schedule(start, ms(20), None, "b");
+    q.schedule(start, ms(10), None, "a");
+    q.schedule(start, ms(20), None, "c");
+    let d = q.schedule(start, ms(15), None, "d");
+    assert_eq!(4, q.len());
+    assert_eq!(Some(start + ms(10)), q.next_deadline());
+
+    assert!(q.pop_due(start + ms(5)).is_empty());
+
+    let due: Vec<&str> = q.pop_due(start + ms(10)).into_iter().map(|(_, c)| c).collect();
+    assert_eq!(vec!["a"], due);
+
+    d.cancel();
+    assert_eq!(2, q.len());
+    assert_eq!(Some(start + ms(20)), q.next_deadline());
+
+    // Same deadline -> FIFO.
+    let due: Vec<&str> = q.pop_due(start + ms(100)).into_iter().map(|(_, c)| c).collect();
+    assert_eq!(vec!["b", "c"], due);
+    assert_eq!(None, q.next_deadline());
+}
+
+#[test]
+fn test_timer_queue_periodic() {
+    let start = Instant::now();
+    let ms = |millis: u64| Duration::from_millis(millis);
+
+    let mut q: TimerQueue<&str> = TimerQueue::new();
+    let p = q.schedule(start, ms(10), Some(ms(10)), "p");
+
+    assert_eq!(1, q.pop_due(start + ms(10)).len());
+    assert_eq!(Some(start + ms(20)), q.next_deadline());
+
+    // Late by more than a period -> fire once, and skip the missed ones.
+    assert_eq!(1, q.pop_due(start + ms(55)).len());
+    assert_eq!(Some(start + ms(65)), q.next_deadline());
+
+    p.cancel();
+    assert!(q.pop_due(start + ms(100)).is_empty());
+    assert_eq!(None, q.next_deadline());
+}
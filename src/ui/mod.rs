@@ -8,6 +8,9 @@ use x11::xlib::{self, Display};
 
 use crate::native::{c_string_from_str, string_from_c_str};
 
+#[cfg(feature = "xtest")]
+pub mod xtest;
+
 // Note, looks like this doesn't need to be called on the I/O thread to use `get_active_window_info()`.
 pub fn x_init_threads() {
     unsafe {
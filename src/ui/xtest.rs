@@ -0,0 +1,113 @@
+//! `OutputSink` that injects the events into the X server with the XTEST extension, e.g. to drive
+//! a nested or remote X server that doesn't see the uinput devices.
+use std::ptr;
+
+use x11::xlib::{self, Display};
+
+use crate::{
+    evdev::{
+        ec::{self, EventType},
+        sink::OutputSink,
+        EvdevError, InputEvent,
+    },
+    native,
+};
+
+/// The X keycodes are the evdev key codes plus 8, with the evdev XKB rules.
+const X_KEYCODE_OFFSET: i32 = 8;
+const MAX_X_KEYCODE: i32 = 255;
+
+/// Injects key, button, pointer motion and wheel events with XTEST. The other events are ignored.
+#[derive(Debug)]
+pub struct XTestSink {
+    display: *mut Display,
+}
+
+// The display connection is only used by this sink, which `Uinput` locks.
+unsafe impl Send for XTestSink {}
+unsafe impl Sync for XTestSink {}
+
+impl XTestSink {
+    /// Connect to the X server in `$DISPLAY`.
+    pub fn new() -> anyhow::Result<XTestSink> {
+        unsafe {
+            let display = xlib::XOpenDisplay(ptr::null());
+            if display == ptr::null_mut() {
+                anyhow::bail!("XOpenDisplay() failed. (Is it under X11?)");
+            }
+            let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+            if native::XTestQueryExtension(display, &mut event_base, &mut error_base, &mut major, &mut minor) == 0 {
+                xlib::XCloseDisplay(display);
+                anyhow::bail!("The X server doesn't support XTEST");
+            }
+            return Ok(XTestSink { display });
+        }
+    }
+
+    fn button_for_code(code: i32) -> Option<u32> {
+        return match code {
+            ec::BTN_LEFT => Some(1),
+            ec::BTN_MIDDLE => Some(2),
+            ec::BTN_RIGHT => Some(3),
+            ec::BTN_SIDE => Some(8),
+            ec::BTN_EXTRA => Some(9),
+            _ => None,
+        };
+    }
+
+    /// Click `button` `count` times, for the wheel.
+    fn click(&self, button: u32, count: i32) {
+        for _ in 0..count {
+            unsafe {
+                native::XTestFakeButtonEvent(self.display, button, 1, 0);
+                native::XTestFakeButtonEvent(self.display, button, 0, 0);
+            }
+        }
+    }
+}
+
+impl OutputSink for XTestSink {
+    fn write_event(&mut self, ev: &InputEvent) -> Result<(), EvdevError> {
+        match ev.event_type {
+            EventType::EV_KEY => {
+                let is_press = if ev.value != 0 { 1 } else { 0 };
+                unsafe {
+                    match XTestSink::button_for_code(ev.code) {
+                        Some(button) => native::XTestFakeButtonEvent(self.display, button, is_press, 0),
+                        None if ev.code + X_KEYCODE_OFFSET <= MAX_X_KEYCODE => {
+                            native::XTestFakeKeyEvent(self.display, (ev.code + X_KEYCODE_OFFSET) as u32, is_press, 0)
+                        }
+                        None => 0, // X doesn't have them, e.g. the gamepad buttons.
+                    };
+                }
+            }
+            EventType::EV_REL => match ev.code {
+                ec::REL_X => unsafe {
+                    native::XTestFakeRelativeMotionEvent(self.display, ev.value, 0, 0);
+                },
+                ec::REL_Y => unsafe {
+                    native::XTestFakeRelativeMotionEvent(self.display, 0, ev.value, 0);
+                },
+                // The hi-res events are ignored; the devices send the normal ones too.
+                ec::REL_WHEEL if ev.value > 0 => self.click(4, ev.value),
+                ec::REL_WHEEL => self.click(5, -ev.value),
+                ec::REL_HWHEEL if ev.value > 0 => self.click(7, ev.value),
+                ec::REL_HWHEEL => self.click(6, -ev.value),
+                _ => {}
+            },
+            EventType::EV_SYN if ev.is_syn_report() => unsafe {
+                xlib::XFlush(self.display);
+            },
+            _ => {}
+        }
+        return Ok(());
+    }
+}
+
+impl Drop for XTestSink {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
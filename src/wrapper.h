@@ -1,3 +1,4 @@
 #include <libevdev/libevdev.h>
 #include <libevdev/libevdev-uinput.h>
+#include <linux/uinput.h>
 #include <libudev.h>
@@ -0,0 +1 @@
+start-myevtest.sh
\ No newline at end of file